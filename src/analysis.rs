@@ -1,74 +1,625 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Instant,
+};
 
-use anyhow::Result;
-use itertools::Itertools;
-use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+use image::{imageops::FilterType, DynamicImage};
+use tracing::warn;
 
 use crate::{
-    image_archive::{ImageArchiver},
-    ocr::{CharacterRecognizer, RecognizeItem},
-    repository::{EntityImage, EntityText, Repository},
+    barcode::CodeDetector,
+    embedding::{self, Embedder, SemanticHit},
+    image_archive::{ImageArchive, ImageArchiver},
+    ocr::{cache::CacheStats, CharacterRecognizer, MarkupBox, RecognizeItem},
+    repository::{
+        EntityEmbedding, EntityImage, EntitySession, EntityText, Repository, SearchMode,
+        SearchOptions, SearchPage, SearchResult, TextKind,
+    },
     screenshot::Screenshot,
+    snippet,
+    stats::{IngestMetrics, IngestStats},
 };
 
+/// Upper bound of the upscaling factor of `Analysis::reocr_region`, keeping the upscaled
+/// image within a reasonable size.
+const MAX_REOCR_SCALE: u32 = 8;
+/// Upper bound of the pixels of the upscaled region of `Analysis::reocr_region`, a larger region
+/// is upscaled less.
+const MAX_REOCR_PIXELS: u64 = 16_000_000;
+/// Images deleted per round by `Analysis::delete_matching`.
+const DELETE_PAGE_SIZE: u32 = 500;
+/// Keyword matches merged with the semantic ones by a hybrid search.
+const HYBRID_KEYWORD_CANDIDATES: u32 = 1000;
+/// Best ranked images grouped by a search grouping by session, the sessions are counted among
+/// them only.
+const GROUP_CANDIDATES: u32 = 1000;
+/// Lines of the latest images scored by a semantic search, bounding the memory and time of a
+/// query as the archive grows.
+const SEMANTIC_CANDIDATES: u32 = 10_000;
+/// Constant of the reciprocal rank fusion of a hybrid search, dampening the weight of the top
+/// ranks of each ranking.
+const RRF_K: f64 = 60.0;
+
+/// Arguments of `Analysis::reocr_region` which cannot be read, like a region outside of the
+/// image, as opposed to a failure to read them.
+#[derive(Debug)]
+pub struct InvalidRegion(pub String);
+
+impl std::fmt::Display for InvalidRegion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidRegion {}
+
 pub struct Analysis {
-    ocr: Arc<dyn CharacterRecognizer + Send + Sync>,
+    /// Reads the frames, absent when the archive is only searched, see `Analysis::read_only`.
+    ocr: Option<Arc<dyn CharacterRecognizer + Send + Sync>>,
+    code_detector: Option<Arc<dyn CodeDetector + Send + Sync>>,
     repo: Arc<dyn Repository + Send + Sync>,
     archiver: Arc<dyn ImageArchiver + Send + Sync>,
+    /// Embeds the lines for semantic search, which is disabled without it.
+    embedder: Option<Arc<dyn Embedder + Send + Sync>>,
+    metrics: IngestMetrics,
 }
 
 impl Analysis {
     pub fn new(
         ocr: Arc<dyn CharacterRecognizer + Send + Sync>,
+        code_detector: Arc<dyn CodeDetector + Send + Sync>,
+        repo: Arc<dyn Repository + Send + Sync>,
+        archiver: Arc<dyn ImageArchiver + Send + Sync>,
+        embedder: Option<Arc<dyn Embedder + Send + Sync>>,
+    ) -> Self {
+        Self {
+            ocr: Some(ocr),
+            code_detector: Some(code_detector),
+            repo,
+            archiver,
+            embedder,
+            metrics: IngestMetrics::new(),
+        }
+    }
+
+    /// Analysis of an archive which is only searched, it records and reads no frame. The
+    /// embedder serves the semantic searches.
+    pub fn read_only(
         repo: Arc<dyn Repository + Send + Sync>,
         archiver: Arc<dyn ImageArchiver + Send + Sync>,
+        embedder: Option<Arc<dyn Embedder + Send + Sync>>,
     ) -> Self {
         Self {
-            ocr,
+            ocr: None,
+            code_detector: None,
             repo,
             archiver,
+            embedder,
+            metrics: IngestMetrics::new(),
         }
     }
 
+    fn ocr(&self) -> Result<&Arc<dyn CharacterRecognizer + Send + Sync>> {
+        self.ocr
+            .as_ref()
+            .ok_or_else(|| anyhow!("the archive is only searched, frames are not read"))
+    }
+
+    /// Counters of the ingest since the analysis was created.
+    pub fn ingest_stats(&self) -> IngestStats {
+        self.metrics.snapshot()
+    }
+
+    /// Hit rate of the OCR cache, when the recognizer has one.
+    pub fn ocr_cache_stats(&self) -> Option<CacheStats> {
+        self.ocr.as_ref().and_then(|it| it.cache_stats())
+    }
+
     pub async fn record_screenshot(&self, screenshot: &Screenshot) -> Result<()> {
+        let result = self.save_screenshot(screenshot).await;
+        self.metrics.record_frame(result.is_ok());
+        result
+    }
+
+    async fn save_screenshot(&self, screenshot: &Screenshot) -> Result<()> {
         let archive = self.archiver.archive(screenshot).await?;
-        let entity_image = EntityImage::new(0, archive.archive_type, archive.archive_detail, screenshot.metadata.captured_at_epoch);
-        let entity_image = self.repo.save_image(&entity_image).await?;
+        let entity_image = EntityImage::new(
+            0,
+            archive.archive_type,
+            archive.archive_detail,
+            screenshot.metadata.captured_at_epoch,
+            Some(screenshot.metadata.screen_id),
+        );
+        let entity_texts = self.recognize_texts(&screenshot.image, 0).await?;
+        let Some(mut previous_texts) = self.previous_texts(&entity_image).await? else {
+            let (image, texts) = self.repo.save_frame(&entity_image, &entity_texts).await?;
+            self.embed_lines(image.id, &texts, |_| true).await;
+            return Ok(());
+        };
+        // an unchanged screen does not store its texts again
+        let mut new_texts = vec![];
+        let mut continued = vec![];
+        for text in entity_texts {
+            match previous_texts.iter().position(|it| it.same_as(&text)) {
+                Some(index) => continued.push(previous_texts.swap_remove(index).id),
+                None => new_texts.push(text),
+            }
+        }
+        let (image, texts) = self
+            .repo
+            .save_frame_continuing(&entity_image, &new_texts, &continued)
+            .await?;
+        // lines without a new text were embedded along with an earlier frame
+        self.embed_lines(image.id, &texts, |it| !continued.contains(&it.id))
+            .await;
+        Ok(())
+    }
 
-        let ocr_result: Vec<RecognizeItem> = self.ocr.recognize(&screenshot.image).await?;
-        let entity_texts: Vec<EntityText> = ocr_result
+    /// Replace the embeddings of the image with those of its lines holding a text matching
+    /// `is_new`. Nothing is embedded without an embedder, and a failure is only logged since the
+    /// texts are already saved.
+    async fn embed_lines<F>(&self, image_id: u32, texts: &[EntityText], is_new: F)
+    where
+        F: Fn(&EntityText) -> bool,
+    {
+        let Some(embedder) = &self.embedder else {
+            return;
+        };
+        let lines: Vec<Vec<&EntityText>> = snippet::lines(texts)
+            .into_iter()
+            .filter(|line| line.iter().any(|it| is_new(it)))
+            .collect();
+        let contents: Vec<String> = lines
+            .iter()
+            .map(|line| {
+                line.iter()
+                    .map(|it| it.text.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect();
+        let result = async {
+            let vectors = embedder.embed(&contents).await?;
+            let embeddings: Vec<EntityEmbedding> = lines
+                .iter()
+                .zip(vectors)
+                .map(|(line, vector)| {
+                    let text_ids = line.iter().map(|it| it.id).collect();
+                    EntityEmbedding::new(image_id, embedder.model(), text_ids, vector)
+                })
+                .collect();
+            self.repo.replace_embeddings(image_id, &embeddings).await
+        };
+        if let Err(e) = result.await {
+            warn!("failed to embed the lines of image {}: {}", image_id, e);
+        }
+    }
+
+    /// Texts of the previous frame of the screen of the image, `None` without such a frame.
+    async fn previous_texts(&self, image: &EntityImage) -> Result<Option<Vec<EntityText>>> {
+        if image.screen_id.is_none() {
+            return Ok(None);
+        }
+        let previous = self
+            .repo
+            .get_nearest_image(image.captured_at_epoch, image.screen_id)
+            .await?;
+        match previous {
+            Some(it) if it.captured_at_epoch <= image.captured_at_epoch => {
+                Ok(Some(self.repo.get_texts_by_image_ids(&[it.id]).await?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Load an already archived image, run OCR on it again and replace its texts.
+    ///
+    /// Returns the number of texts stored for the image.
+    pub async fn reindex_image(&self, entity_image: &EntityImage) -> Result<usize> {
+        let image_archive = ImageArchive::new(
+            entity_image.archive_type.clone(),
+            entity_image.archive_info.clone(),
+        );
+        let image = self.archiver.load(&image_archive).await?;
+        let entity_texts = self.recognize_texts(&image, entity_image.id).await?;
+        let saved = self
+            .repo
+            .replace_texts(entity_image.id, &entity_texts)
+            .await?;
+        self.embed_lines(entity_image.id, &saved, |_| true).await;
+        Ok(saved.len())
+    }
+
+    /// Recognize the words of the image with OCR, along with the payloads of decoded QR codes
+    /// and barcodes.
+    async fn recognize_texts(
+        &self,
+        image: &DynamicImage,
+        image_id: u32,
+    ) -> Result<Vec<EntityText>> {
+        let started_at = Instant::now();
+        let ocr_result: Vec<RecognizeItem> = self.ocr()?.recognize(image).await?;
+        self.metrics.record_ocr(started_at.elapsed());
+        let mut entity_texts: Vec<EntityText> = ocr_result
             .iter()
             .filter(|it| it.level == 5)
             .filter_map(|it: &RecognizeItem| -> Option<EntityText> { it.try_into().ok() })
             .map(|mut it| {
-                it.image_id = entity_image.id;
+                it.image_id = image_id;
                 it
             })
             .collect();
-        self.repo.save_texts(&entity_texts).await?;
+
+        // a failed detection should not lose the recognized words
+        let codes = match &self.code_detector {
+            Some(code_detector) => match code_detector.detect(image).await {
+                Ok(codes) => codes,
+                Err(e) => {
+                    warn!("failed to detect codes in image {}: {}", image_id, e);
+                    vec![]
+                }
+            },
+            None => vec![],
+        };
+        entity_texts.extend(codes.into_iter().map(|it| {
+            EntityText::new(
+                0,
+                image_id,
+                it.kind,
+                it.payload,
+                it.markup.left,
+                it.markup.top,
+                it.markup.width,
+                it.markup.height,
+            )
+        }));
+        Ok(entity_texts)
+    }
+
+    /// Read a region of an archived image again: the region is cropped, upscaled by `scale` and
+    /// recognized with thorough settings, which catches small text missed at capture time. The
+    /// scale is lowered as needed to keep the upscaled region within `MAX_REOCR_PIXELS`.
+    ///
+    /// A scale out of range or a region empty or crossing the border of the image fails with
+    /// `InvalidRegion`.
+    ///
+    /// With `save`, the recognized texts replace the texts inside the region in the repository.
+    pub async fn reocr_region(
+        &self,
+        image_id: u32,
+        region: &MarkupBox,
+        scale: u32,
+        save: bool,
+    ) -> Result<Vec<EntityText>> {
+        if !(1..=MAX_REOCR_SCALE).contains(&scale) {
+            return Err(InvalidRegion(format!(
+                "scale must be between 1 and {}, got {}",
+                MAX_REOCR_SCALE, scale
+            ))
+            .into());
+        }
+        if region.width == 0 || region.height == 0 {
+            return Err(InvalidRegion("region must not be empty".to_string()).into());
+        }
+        let entity_image = self.repo.get_image_by_id(image_id).await?;
+        let image_archive = ImageArchive::new(entity_image.archive_type, entity_image.archive_info);
+        let image = self.archiver.load(&image_archive).await?;
+        let right = u64::from(region.left) + u64::from(region.width);
+        let bottom = u64::from(region.top) + u64::from(region.height);
+        if right > u64::from(image.width()) || bottom > u64::from(image.height()) {
+            return Err(InvalidRegion(format!(
+                "region is outside of image {} of {}x{}",
+                image_id,
+                image.width(),
+                image.height()
+            ))
+            .into());
+        }
+        let pixels = u64::from(region.width) * u64::from(region.height);
+        let scale = (1..=scale)
+            .rev()
+            .find(|it| pixels * u64::from(it * it) <= MAX_REOCR_PIXELS)
+            .unwrap_or(1);
+
+        let cropped = image.crop_imm(region.left, region.top, region.width, region.height);
+        let upscaled = DynamicImage::ImageLuma8(image::imageops::resize(
+            &cropped.to_luma8(),
+            region.width * scale,
+            region.height * scale,
+            FilterType::Lanczos3,
+        ));
+        let ocr_result = self.ocr()?.recognize_thoroughly(&upscaled).await?;
+        let entity_texts: Vec<EntityText> = ocr_result
+            .iter()
+            .filter(|it| it.level == 5 && !it.text.trim().is_empty())
+            .map(|it| {
+                // map the box back to the coordinates of the original image
+                EntityText::new(
+                    0,
+                    image_id,
+                    TextKind::Ocr,
+                    it.text.clone(),
+                    region.left + it.markup.left / scale,
+                    region.top + it.markup.top / scale,
+                    it.markup.width.div_ceil(scale),
+                    it.markup.height.div_ceil(scale),
+                )
+            })
+            .collect();
+
+        if !save {
+            return Ok(entity_texts);
+        }
+        let saved = self
+            .repo
+            .replace_texts_in_region(image_id, region, &entity_texts)
+            .await?;
+        let texts = self.repo.get_texts_by_image_ids(&[image_id]).await?;
+        self.embed_lines(image_id, &texts, |_| true).await;
+        Ok(saved)
+    }
+
+    /// Search a page of images, along with the snippets of the lines around their hits and
+    /// their sessions. When grouping by session, only the best image of each session is listed.
+    pub async fn search(&self, options: &SearchOptions) -> Result<SearchPage> {
+        let mut page = if options.group_by_session {
+            self.grouped_search(options).await?
+        } else {
+            let mut page = self.ranked_search(options).await?;
+            self.fill_sessions(&mut page.results).await?;
+            page
+        };
+        let image_ids: Vec<u32> = page.results.iter().map(|it| it.image_id).collect();
+        let words = self.repo.get_texts_by_image_ids(&image_ids).await?;
+        for result in page.results.iter_mut() {
+            let words: Vec<EntityText> = words
+                .iter()
+                .filter(|it| it.image_id == result.image_id)
+                .cloned()
+                .collect();
+            result.snippets = snippet::build_snippets(&result.texts, &words);
+        }
+        Ok(page)
+    }
+
+    /// Rank the best images of each session among the `GROUP_CANDIDATES` best images, the other
+    /// matching images of a session are listed with its best one.
+    async fn grouped_search(&self, options: &SearchOptions) -> Result<SearchPage> {
+        let mut candidates = self
+            .ranked_search(&SearchOptions {
+                limit: GROUP_CANDIDATES,
+                offset: 0,
+                ..options.clone()
+            })
+            .await?
+            .results;
+        self.fill_sessions(&mut candidates).await?;
+        let mut groups: Vec<SearchResult> = vec![];
+        let mut group_of_session: HashMap<u32, usize> = HashMap::new();
+        for result in candidates {
+            let session_id = result.session.as_ref().map(|it| it.id);
+            match session_id.and_then(|id| group_of_session.get(&id)) {
+                Some(index) => groups[*index].session_image_ids.push(result.image_id),
+                None => {
+                    if let Some(id) = session_id {
+                        group_of_session.insert(id, groups.len());
+                    }
+                    groups.push(result);
+                }
+            }
+        }
+        let total = groups.len() as u64;
+        let results = groups
+            .into_iter()
+            .skip(options.offset as usize)
+            .take(options.limit as usize)
+            .collect();
+        Ok(SearchPage { total, results })
+    }
+
+    /// Fill in the sessions of the results, an image not segmented yet has none.
+    async fn fill_sessions(&self, results: &mut [SearchResult]) -> Result<()> {
+        let image_ids: Vec<u32> = results.iter().map(|it| it.image_id).collect();
+        let mut sessions: HashMap<u32, EntitySession> = self
+            .repo
+            .get_sessions_of_images(&image_ids)
+            .await?
+            .into_iter()
+            .collect();
+        for result in results.iter_mut() {
+            result.session = sessions.remove(&result.image_id);
+        }
         Ok(())
     }
 
-    pub async fn search(&self, text: &str) -> Result<Vec<SearchResult>> {
-        let texts = self.repo.full_text_search(text).await?;
-        let result: Vec<SearchResult> = texts
+    /// A page of images ranked by the mode of the search.
+    async fn ranked_search(&self, options: &SearchOptions) -> Result<SearchPage> {
+        Ok(match options.mode {
+            SearchMode::Keyword => self.repo.search(options).await?,
+            SearchMode::Semantic => {
+                let hits = self.semantic_hits(options).await?;
+                let total = hits.len() as u64;
+                let hits = hits
+                    .into_iter()
+                    .skip(options.offset as usize)
+                    .take(options.limit as usize)
+                    .collect();
+                let results = self.semantic_results(hits).await?;
+                SearchPage { total, results }
+            }
+            SearchMode::Hybrid => self.hybrid_search(options).await?,
+        })
+    }
+
+    /// Every image with lines similar to the query among the `SEMANTIC_CANDIDATES` latest
+    /// lines in the time range, best first.
+    async fn semantic_hits(&self, options: &SearchOptions) -> Result<Vec<SemanticHit>> {
+        let embedder = self.embedder.as_ref().ok_or_else(|| {
+            anyhow!("semantic search is disabled, configure an embedding provider")
+        })?;
+        let query = embedder.embed(std::slice::from_ref(&options.text)).await?;
+        let candidates = self
+            .repo
+            .get_embeddings(&embedder.model(), options, SEMANTIC_CANDIDATES)
+            .await?;
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        Ok(embedding::rank(options, &query[0], candidates, now))
+    }
+
+    /// Results of the semantic hits, with the texts of their lines.
+    async fn semantic_results(&self, hits: Vec<SemanticHit>) -> Result<Vec<SearchResult>> {
+        let image_ids: Vec<u32> = hits.iter().map(|it| it.image.id).collect();
+        let texts = self.repo.get_texts_by_image_ids(&image_ids).await?;
+        Ok(hits
+            .into_iter()
+            .map(|hit| {
+                let texts = texts
+                    .iter()
+                    .filter(|it| it.image_id == hit.image.id && hit.text_ids.contains(&it.id))
+                    .cloned()
+                    .collect();
+                SearchResult::new(
+                    hit.image.id,
+                    hit.image.captured_at_epoch,
+                    hit.image.screen_id,
+                    hit.score,
+                    texts,
+                )
+            })
+            .collect())
+    }
+
+    /// Merge the keyword and semantic rankings by reciprocal rank fusion: an image scores the sum
+    /// of `1 / (RRF_K + rank)` over the rankings it appears in, negated so lower is better. The
+    /// texts of a result are its keyword matches followed by its similar lines.
+    async fn hybrid_search(&self, options: &SearchOptions) -> Result<SearchPage> {
+        let keyword = self
+            .repo
+            .search(&SearchOptions {
+                limit: HYBRID_KEYWORD_CANDIDATES,
+                offset: 0,
+                mode: SearchMode::Keyword,
+                ..options.clone()
+            })
+            .await?
+            .results;
+        let semantic = self.semantic_hits(options).await?;
+
+        let mut scores: HashMap<u32, f64> = HashMap::new();
+        let rankings = [
+            keyword.iter().map(|it| it.image_id).collect::<Vec<_>>(),
+            semantic.iter().map(|it| it.image.id).collect(),
+        ];
+        for ranking in rankings {
+            for (rank, image_id) in ranking.into_iter().enumerate() {
+                *scores.entry(image_id).or_default() -= 1.0 / (RRF_K + rank as f64 + 1.0);
+            }
+        }
+        let mut ranked: Vec<(u32, f64)> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        let total = ranked.len() as u64;
+        let ranked: Vec<(u32, f64)> = ranked
+            .into_iter()
+            .skip(options.offset as usize)
+            .take(options.limit as usize)
+            .collect();
+
+        let on_page = |image_id: u32| ranked.iter().any(|(id, _)| *id == image_id);
+        let mut keyword: HashMap<u32, SearchResult> = keyword
+            .into_iter()
+            .filter(|it| on_page(it.image_id))
+            .map(|it| (it.image_id, it))
+            .collect();
+        let semantic = semantic
             .into_iter()
-            .group_by(|it| it.image_id)
+            .filter(|it| on_page(it.image.id))
+            .collect();
+        let mut semantic: HashMap<u32, SearchResult> = self
+            .semantic_results(semantic)
+            .await?
             .into_iter()
-            .map(|(image_id, group)| SearchResult::new(image_id, group.collect()))
+            .map(|it| (it.image_id, it))
             .collect();
-        Ok(result)
+        let mut results = Vec::with_capacity(ranked.len());
+        for (image_id, score) in ranked {
+            let mut result = match (keyword.remove(&image_id), semantic.remove(&image_id)) {
+                (Some(mut matched), Some(similar)) => {
+                    let known: HashSet<u32> = matched.texts.iter().map(|it| it.id).collect();
+                    matched.texts.extend(
+                        similar
+                            .texts
+                            .into_iter()
+                            .filter(|it| !known.contains(&it.id)),
+                    );
+                    matched
+                }
+                (Some(it), None) | (None, Some(it)) => it,
+                (None, None) => continue,
+            };
+            result.score = score;
+            results.push(result);
+        }
+        Ok(SearchPage { total, results })
     }
-}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SearchResult {
-    pub image_id: u32,
-    pub texts: Vec<EntityText>,
-}
+    /// Delete the images along with their texts and archives, returns the number of deleted
+    /// images.
+    pub async fn delete_images(&self, image_ids: &[u32]) -> Result<u64> {
+        let deleted = self.repo.delete_images(image_ids).await?;
+        self.delete_archives(&deleted).await;
+        Ok(deleted.len() as u64)
+    }
 
-impl SearchResult {
-    pub fn new(image_id: u32, texts: Vec<EntityText>) -> Self {
-        Self { image_id, texts }
+    /// Delete the images captured in `[from_epoch, to_epoch]` like `delete_images`.
+    pub async fn delete_captured_between(&self, from_epoch: u64, to_epoch: u64) -> Result<u64> {
+        let deleted = self
+            .repo
+            .delete_images_captured_between(from_epoch, to_epoch)
+            .await?;
+        self.delete_archives(&deleted).await;
+        Ok(deleted.len() as u64)
+    }
+
+    /// Delete every image matching the keyword search like `delete_images`, the pagination and
+    /// mode of the options are ignored. A text seen in several images matches in the first of
+    /// them captured in the time range, then in the next one once it is deleted, so images
+    /// captured outside of the range are kept.
+    pub async fn delete_matching(&self, options: &SearchOptions) -> Result<u64> {
+        let options = SearchOptions {
+            limit: DELETE_PAGE_SIZE,
+            offset: 0,
+            ..options.clone()
+        };
+        let mut deleted = 0;
+        // the first page is deleted until nothing matches anymore
+        loop {
+            let page = self.repo.search(&options).await?;
+            let image_ids: Vec<u32> = page.results.iter().map(|it| it.image_id).collect();
+            if image_ids.is_empty() {
+                break;
+            }
+            let count = self.delete_images(&image_ids).await?;
+            if count == 0 {
+                break;
+            }
+            deleted += count;
+        }
+        Ok(deleted)
+    }
+
+    /// Remove the archives of deleted images. The records are deleted first: an archive left
+    /// behind is only wasted space, while a record without its archive could not be shown.
+    async fn delete_archives(&self, images: &[EntityImage]) {
+        for image in images {
+            let archive = ImageArchive::new(image.archive_type.clone(), image.archive_info.clone());
+            if let Err(err) = self.archiver.delete(&archive).await {
+                warn!(
+                    "failed to delete the archive {} of image {}: {}",
+                    image.archive_info, image.id, err
+                );
+            }
+        }
     }
 }
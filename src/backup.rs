@@ -0,0 +1,350 @@
+//! Backup of the database together with the archived images into a single bundle, and restore of
+//! a bundle into the fresh paths of the configuration.
+//!
+//! A bundle is a ZIP archive of a `manifest.json`, a copy of the SQLite database taken while it
+//! stays in use, and the image files the copy refers to under `images/`.
+
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use sqlx_sqlite::SqlitePoolOptions;
+use tracing::{info, warn};
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    config::Config,
+    repository::{
+        sqlite::{migration, SqliteRepository},
+        Repository,
+    },
+};
+
+/// Version of the layout of the bundles written by this binary.
+const BUNDLE_VERSION: u32 = 1;
+const MANIFEST_PATH: &str = "manifest.json";
+const DATABASE_PATH: &str = "dejavu.db";
+const IMAGES_PATH: &str = "images";
+/// Images fetched from the database copy per scan.
+const BATCH_SIZE: u32 = 1000;
+/// Archive type of the images stored as files, see `crate::image_archive::fs`.
+const FILE_SYSTEM_ARCHIVE: &str = "file_system";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    /// Version of the layout of the bundle.
+    pub bundle_version: u32,
+    /// Version of dejavu which wrote the bundle.
+    pub dejavu_version: String,
+    pub created_at_epoch: u64,
+    /// Schema version of the database.
+    pub schema_version: u32,
+    /// Images recorded in the database.
+    pub images: u64,
+    /// Image files in the bundle, an image whose file was gone is recorded without it.
+    pub files: Vec<BundledFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundledFile {
+    /// Path in the bundle.
+    pub path: String,
+    pub bytes: u64,
+}
+
+/// Write a bundle of the database and of the images stored in `image_dir` to `output`.
+pub async fn backup(
+    repo: Arc<dyn Repository + Send + Sync>,
+    image_dir: &Path,
+    output: &Path,
+) -> Result<BackupManifest> {
+    if output.exists() {
+        return Err(anyhow!("{} already exists", output.display()));
+    }
+    let snapshot = PathBuf::from(format!("{}.{}.db", output.display(), uuid::Uuid::new_v4()));
+    repo.backup_database(&snapshot.to_string_lossy()).await?;
+    let result = write_bundle(&snapshot, image_dir, output).await;
+    if let Err(err) = tokio::fs::remove_file(&snapshot).await {
+        warn!(
+            "failed to remove the database copy {}: {}",
+            snapshot.display(),
+            err
+        );
+    }
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(output).await;
+    }
+    result
+}
+
+async fn write_bundle(snapshot: &Path, image_dir: &Path, output: &Path) -> Result<BackupManifest> {
+    // the files are those of the images of the copy, images recorded since are left out
+    let pool = SqlitePoolOptions::new()
+        .connect(&format!("{}?mode=ro", snapshot.display()))
+        .await?;
+    let schema_version = migration::current_version(&pool).await?;
+    let repo = SqliteRepository::new(pool.clone());
+    let mut file_names = Vec::new();
+    let mut images = 0;
+    let mut after_id = 0;
+    loop {
+        let batch = repo.scan_images(after_id, None, None, BATCH_SIZE).await?;
+        let Some(last) = batch.last() else {
+            break;
+        };
+        after_id = last.id;
+        images += batch.len() as u64;
+        for image in batch {
+            if image.archive_type == FILE_SYSTEM_ARCHIVE {
+                file_names.push(image.archive_info);
+            } else {
+                warn!(
+                    "image {} is archived as {}, its image is left out of the backup",
+                    image.id, image.archive_type
+                );
+            }
+        }
+    }
+    pool.close().await;
+
+    let mut manifest = BackupManifest {
+        bundle_version: BUNDLE_VERSION,
+        dejavu_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at_epoch: chrono::Utc::now().timestamp().max(0) as u64,
+        schema_version,
+        images,
+        files: vec![],
+    };
+    let snapshot = snapshot.to_path_buf();
+    let image_dir = image_dir.to_path_buf();
+    let output = output.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut zip = ZipWriter::new(File::create(&output)?);
+        zip.start_file(
+            DATABASE_PATH,
+            FileOptions::default().compression_method(CompressionMethod::Deflated),
+        )?;
+        std::io::copy(&mut File::open(&snapshot)?, &mut zip)?;
+        for file_name in file_names {
+            let mut file = match File::open(image_dir.join(&file_name)) {
+                Ok(file) => file,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    // deleted since the copy of the database was taken
+                    warn!("image file {} is gone, it is left out", file_name);
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+            let path = format!("{}/{}", IMAGES_PATH, file_name);
+            // the images are compressed already
+            zip.start_file(
+                path.as_str(),
+                FileOptions::default()
+                    .compression_method(CompressionMethod::Stored)
+                    .large_file(true),
+            )?;
+            let bytes = std::io::copy(&mut file, &mut zip)?;
+            manifest.files.push(BundledFile { path, bytes });
+        }
+        zip.start_file(MANIFEST_PATH, FileOptions::default())?;
+        serde_json::to_writer_pretty(&mut zip, &manifest)?;
+        zip.finish()?.flush()?;
+        info!(
+            "backed up {} images and {} image files to {}",
+            manifest.images,
+            manifest.files.len(),
+            output.display()
+        );
+        Ok(manifest)
+    })
+    .await?
+}
+
+/// Validate the bundle at `input` and import it into the database file and the image directory
+/// of the configuration. The data directory, the database file and the image directory must
+/// not exist or be empty, and nothing is written to them when the bundle is invalid.
+pub async fn restore(input: &Path, config: &Config) -> Result<BackupManifest> {
+    if !config.database.url.is_empty() {
+        return Err(anyhow!(
+            "a bundle restores a SQLite database, `database.url` must not be set"
+        ));
+    }
+    let data_dir = config.data_dir();
+    let database_file = config.database_file();
+    let image_dir = config.image_dir();
+    for path in [&data_dir, &database_file, &image_dir] {
+        if holds_data(path) {
+            return Err(anyhow!(
+                "restoring needs a fresh data directory, {} already holds data",
+                path.display()
+            ));
+        }
+    }
+    // the bundle is unpacked next to the data directory, then moved in place once validated
+    let staging = PathBuf::from(format!(
+        "{}.restoring-{}",
+        data_dir.display(),
+        uuid::Uuid::new_v4()
+    ));
+    let result = unpack_and_validate(input, &staging).await;
+    let manifest = match result {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            let _ = tokio::fs::remove_dir_all(&staging).await;
+            return Err(err);
+        }
+    };
+    // the validated parts are moved where the configuration puts them
+    tokio::fs::create_dir_all(&data_dir).await?;
+    for (unpacked, target) in [
+        (staging.join(IMAGES_PATH), &image_dir),
+        (staging.join(DATABASE_PATH), &database_file),
+    ] {
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        if target.is_dir() {
+            tokio::fs::remove_dir(target).await?;
+        }
+        tokio::fs::rename(&unpacked, target).await?;
+    }
+    tokio::fs::remove_dir_all(&staging).await?;
+    info!(
+        "restored {} images to {} and {} image files into {}",
+        manifest.images,
+        database_file.display(),
+        manifest.files.len(),
+        image_dir.display()
+    );
+    Ok(manifest)
+}
+
+/// Whether the path is a file, or a directory with entries.
+fn holds_data(path: &Path) -> bool {
+    match std::fs::read_dir(path) {
+        Ok(mut entries) => entries.next().is_some(),
+        Err(_) => path.exists(),
+    }
+}
+
+async fn unpack_and_validate(input: &Path, staging: &Path) -> Result<BackupManifest> {
+    let input = input.to_path_buf();
+    let target = staging.to_path_buf();
+    let manifest = tokio::task::spawn_blocking(move || unpack(&input, &target)).await??;
+
+    // the check of the full text index writes to it, the staged copy is opened for writing
+    let pool = SqlitePoolOptions::new()
+        .connect(&format!(
+            "{}?mode=rw",
+            staging.join(DATABASE_PATH).display()
+        ))
+        .await?;
+    let integrity: String = sqlx::query("PRAGMA integrity_check")
+        .fetch_one(&pool)
+        .await?
+        .get(0);
+    if integrity != "ok" {
+        return Err(anyhow!(
+            "the database of the bundle is corrupt: {}",
+            integrity
+        ));
+    }
+    let schema_version = migration::current_version(&pool).await?;
+    if schema_version != manifest.schema_version {
+        return Err(anyhow!(
+            "the database of the bundle has schema version {}, the manifest records {}",
+            schema_version,
+            manifest.schema_version
+        ));
+    }
+    if schema_version > migration::latest_version() {
+        return Err(anyhow!(
+            "the bundle has schema version {}, newer than {} supported by this binary, please upgrade dejavu",
+            schema_version,
+            migration::latest_version()
+        ));
+    }
+    let images: i64 = sqlx::query("SELECT COUNT(*) FROM images")
+        .fetch_one(&pool)
+        .await?
+        .get(0);
+    pool.close().await;
+    if images as u64 != manifest.images {
+        return Err(anyhow!(
+            "the database of the bundle has {} images, the manifest records {}",
+            images,
+            manifest.images
+        ));
+    }
+    Ok(manifest)
+}
+
+/// Unpack the database and the image files listed by the manifest into `target`, checking them
+/// against the manifest and their checksums.
+fn unpack(input: &Path, target: &Path) -> Result<BackupManifest> {
+    let mut zip = ZipArchive::new(File::open(input)?)
+        .with_context(|| format!("{} is not a backup bundle", input.display()))?;
+    let manifest: BackupManifest = {
+        let mut entry = zip
+            .by_name(MANIFEST_PATH)
+            .map_err(|_| anyhow!("{} has no manifest", input.display()))?;
+        let mut content = String::new();
+        entry.read_to_string(&mut content)?;
+        serde_json::from_str(&content).context("invalid manifest")?
+    };
+    if manifest.bundle_version != BUNDLE_VERSION {
+        return Err(anyhow!(
+            "unsupported bundle version {}, expect {}",
+            manifest.bundle_version,
+            BUNDLE_VERSION
+        ));
+    }
+
+    let image_dir = target.join(IMAGES_PATH);
+    std::fs::create_dir_all(&image_dir)?;
+    unpack_file(&mut zip, DATABASE_PATH, &target.join(DATABASE_PATH), None)?;
+    for file in &manifest.files {
+        // the file name is joined to the data directory, it must not point outside of it
+        let file_name = file
+            .path
+            .strip_prefix(&format!("{}/", IMAGES_PATH))
+            .filter(|it| !it.is_empty() && !it.contains(['/', '\\']) && *it != "..")
+            .ok_or_else(|| anyhow!("invalid image path {} in the manifest", file.path))?;
+        unpack_file(
+            &mut zip,
+            &file.path,
+            &image_dir.join(file_name),
+            Some(file.bytes),
+        )?;
+    }
+    Ok(manifest)
+}
+
+fn unpack_file(
+    zip: &mut ZipArchive<File>,
+    path: &str,
+    target: &Path,
+    bytes: Option<u64>,
+) -> Result<()> {
+    let mut entry = zip
+        .by_name(path)
+        .map_err(|_| anyhow!("{} is missing from the bundle", path))?;
+    // the checksum of the entry is verified once it is read to the end
+    let copied = std::io::copy(&mut entry, &mut File::create(target)?)
+        .with_context(|| format!("failed to unpack {}", path))?;
+    if let Some(bytes) = bytes.filter(|it| *it != copied) {
+        return Err(anyhow!(
+            "{} has {} bytes, the manifest records {}",
+            path,
+            copied,
+            bytes
+        ));
+    }
+    Ok(())
+}
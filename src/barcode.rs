@@ -0,0 +1,104 @@
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use image::{DynamicImage, GrayImage};
+use lru::LruCache;
+use rxing::BarcodeFormat;
+use tracing::debug;
+
+use crate::{
+    ocr::{content_hash, MarkupBox},
+    repository::TextKind,
+};
+
+/// Margin added around the points reported by the decoder, which are the centers of the finder
+/// patterns rather than the outline of the code.
+const CODE_BOX_PADDING: f32 = 8.0;
+
+#[derive(Debug, Clone)]
+pub struct DetectedCode {
+    pub kind: TextKind,
+    pub payload: String,
+    pub markup: MarkupBox,
+}
+
+#[async_trait]
+pub trait CodeDetector {
+    /// Find and decode the QR codes and barcodes visible in the image.
+    async fn detect(&self, image: &DynamicImage) -> anyhow::Result<Vec<DetectedCode>>;
+}
+
+/// Detector backed by rxing, a pure Rust port of ZXing.
+///
+/// Like `crate::ocr::cache::CachedRecognizer`, the codes are kept in a LRU cache keyed by the
+/// content hash of the frame, so an unchanged screen is only decoded once.
+pub struct RxingCodeDetector {
+    cache: Mutex<LruCache<u64, Vec<DetectedCode>>>,
+}
+
+impl RxingCodeDetector {
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            cache: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+}
+
+#[async_trait]
+impl CodeDetector for RxingCodeDetector {
+    async fn detect(&self, image: &DynamicImage) -> anyhow::Result<Vec<DetectedCode>> {
+        let key = content_hash(image);
+        if let Some(codes) = self.cache.lock().unwrap().get(&key) {
+            return Ok(codes.clone());
+        }
+        // decoding is CPU bound, it must not hold up the other tasks of the runtime
+        let luma = image.to_luma8();
+        let codes = tokio::task::spawn_blocking(move || decode(luma)).await?;
+        self.cache.lock().unwrap().put(key, codes.clone());
+        Ok(codes)
+    }
+}
+
+fn decode(luma: GrayImage) -> Vec<DetectedCode> {
+    let (width, height) = luma.dimensions();
+    let results = match rxing::helpers::detect_multiple_in_luma(luma.into_raw(), width, height) {
+        Ok(results) => results,
+        // a frame without any code is reported as an error
+        Err(e) => {
+            debug!("no code decoded: {:?}", e);
+            return vec![];
+        }
+    };
+
+    results
+        .iter()
+        .filter(|it| !it.getText().is_empty() && !it.getPoints().is_empty())
+        .map(|it| {
+            let points = it.getPoints();
+            let min_x = points.iter().map(|p| p.x).fold(f32::MAX, f32::min);
+            let min_y = points.iter().map(|p| p.y).fold(f32::MAX, f32::min);
+            let max_x = points.iter().map(|p| p.x).fold(f32::MIN, f32::max);
+            let max_y = points.iter().map(|p| p.y).fold(f32::MIN, f32::max);
+            let left = (min_x - CODE_BOX_PADDING).max(0.0) as u32;
+            let top = (min_y - CODE_BOX_PADDING).max(0.0) as u32;
+            let right = ((max_x + CODE_BOX_PADDING) as u32).min(width);
+            let bottom = ((max_y + CODE_BOX_PADDING) as u32).min(height);
+            let kind = if matches!(it.getBarcodeFormat(), BarcodeFormat::QR_CODE) {
+                TextKind::QrCode
+            } else {
+                TextKind::Barcode
+            };
+            DetectedCode {
+                kind,
+                payload: it.getText().to_string(),
+                markup: MarkupBox::new(
+                    left,
+                    top,
+                    right.saturating_sub(left),
+                    bottom.saturating_sub(top),
+                ),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,199 @@
+//! The `doctor` command, checking what recording and searching depend on and reporting every
+//! problem found instead of stopping at the first one.
+
+use std::{path::Path, sync::Arc};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    config::LoadedConfig,
+    embedding,
+    image_archive::{fs::FileSystemImageArchiver, ImageArchiver},
+    repository::{self, Repository},
+    screenshot,
+};
+
+/// Frames fetched from the repository per scan.
+const BATCH_SIZE: u32 = 1000;
+/// Archive type of the images stored as files, see `crate::image_archive::fs`.
+const FILE_SYSTEM_ARCHIVE: &str = "file_system";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Ok,
+    /// Recording and searching work, with a limitation.
+    Warn,
+    Fail,
+}
+
+struct Report {
+    failures: usize,
+}
+
+impl Report {
+    fn check(&mut self, name: &str, result: Result<(Status, String)>) {
+        let (status, detail) = result.unwrap_or_else(|e| (Status::Fail, e.to_string()));
+        let label = match status {
+            Status::Ok => "ok",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+        };
+        if status == Status::Fail {
+            self.failures += 1;
+        }
+        println!("{:<5} {}: {}", label, name, detail);
+    }
+}
+
+/// Run the `doctor` command, failing when a check fails.
+pub async fn run(config: LoadedConfig) -> Result<()> {
+    let mut report = Report { failures: 0 };
+    report.check("configuration", Ok(check_config(&config)));
+    let data_dir = config.config.data_dir();
+    report.check("data directory", check_data_dir(&data_dir).await);
+
+    let image_dir = config.config.image_dir();
+    match repository::connect(&config.config.database_url()).await {
+        Ok(repo) => {
+            report.check("database", check_database(&repo).await);
+            let archiver = FileSystemImageArchiver::new(
+                image_dir.to_string_lossy().to_string(),
+                config.config.archive.jpeg_quality as u8,
+            );
+            report.check("archive", check_archive(&repo, &archiver, &image_dir).await);
+        }
+        Err(e) => report.check("database", Err(e)),
+    }
+
+    report.check("tesseract", check_tesseract().await);
+    report.check(
+        "embedding",
+        check_embedding(&config.config.ocr.embedding).await,
+    );
+    report.check("screens", check_screens());
+
+    if report.failures > 0 {
+        return Err(anyhow!("{} checks failed", report.failures));
+    }
+    Ok(())
+}
+
+fn check_config(config: &LoadedConfig) -> (Status, String) {
+    let file = match &config.file {
+        Some(file) => format!("read {}", file.display()),
+        None => "no configuration file".to_string(),
+    };
+    (
+        Status::Ok,
+        format!("{}, {} settings overridden", file, config.sources.len()),
+    )
+}
+
+/// The data directory must be writable, for the database and the reindex checkpoint.
+async fn check_data_dir(data_dir: &Path) -> Result<(Status, String)> {
+    tokio::fs::create_dir_all(data_dir).await?;
+    let probe = data_dir.join(format!(".doctor-{}", uuid::Uuid::new_v4()));
+    tokio::fs::write(&probe, b"")
+        .await
+        .map_err(|e| anyhow!("{} is not writable: {}", data_dir.display(), e))?;
+    tokio::fs::remove_file(&probe).await?;
+    Ok((Status::Ok, format!("{} is writable", data_dir.display())))
+}
+
+async fn check_database(repo: &Arc<dyn Repository + Send + Sync>) -> Result<(Status, String)> {
+    let stats = repo.get_stats().await?;
+    Ok((
+        Status::Ok,
+        format!(
+            "{} frames, {} texts, {} bytes",
+            stats.images, stats.texts, stats.database_bytes
+        ),
+    ))
+}
+
+/// Every frame stored as a file should still have its file.
+async fn check_archive(
+    repo: &Arc<dyn Repository + Send + Sync>,
+    archiver: &FileSystemImageArchiver,
+    image_dir: &Path,
+) -> Result<(Status, String)> {
+    let mut missing = 0;
+    let mut after_id = 0;
+    loop {
+        let batch = repo.scan_images(after_id, None, None, BATCH_SIZE).await?;
+        let Some(last) = batch.last() else {
+            break;
+        };
+        after_id = last.id;
+        for image in batch {
+            if image.archive_type == FILE_SYSTEM_ARCHIVE
+                && !tokio::fs::try_exists(image_dir.join(&image.archive_info)).await?
+            {
+                missing += 1;
+            }
+        }
+    }
+    if !tokio::fs::try_exists(image_dir).await? {
+        if missing > 0 {
+            return Err(anyhow!("{} does not exist", image_dir.display()));
+        }
+        return Ok((
+            Status::Warn,
+            format!("{} does not exist yet", image_dir.display()),
+        ));
+    }
+    let usage = format!(
+        "{}, {} bytes",
+        image_dir.display(),
+        archiver.disk_usage().await?
+    );
+    if missing > 0 {
+        return Ok((
+            Status::Warn,
+            format!(
+                "{}, the image files of {} frames are missing",
+                usage, missing
+            ),
+        ));
+    }
+    Ok((Status::Ok, usage))
+}
+
+/// OCR runs the `tesseract` executable, which must be on the path.
+async fn check_tesseract() -> Result<(Status, String)> {
+    let output = tokio::process::Command::new("tesseract")
+        .arg("--version")
+        .output()
+        .await
+        .map_err(|e| anyhow!("failed to run `tesseract`, is it installed? {}", e))?;
+    // older versions print their version to the standard error
+    let version = [&output.stdout[..], &output.stderr[..]]
+        .iter()
+        .map(|it| String::from_utf8_lossy(it).to_string())
+        .find_map(|it| it.lines().next().map(str::to_string))
+        .unwrap_or_default();
+    if !output.status.success() {
+        return Err(anyhow!("`tesseract --version` failed: {}", version));
+    }
+    Ok((Status::Ok, version))
+}
+
+async fn check_embedding(provider: &str) -> Result<(Status, String)> {
+    let provider: embedding::Provider = provider.parse()?;
+    match provider.build().await? {
+        Some(embedder) => Ok((Status::Ok, format!("model {}", embedder.model()))),
+        None => Ok((
+            Status::Warn,
+            "disabled, semantic search is unavailable".to_string(),
+        )),
+    }
+}
+
+/// Without a screen the capture fails, serving an archive still works.
+fn check_screens() -> Result<(Status, String)> {
+    match screenshot::DefaultCapturer::new().screen_ids() {
+        Ok(ids) if ids.is_empty() => Ok((Status::Warn, "no screen to capture".to_string())),
+        Ok(ids) => Ok((Status::Ok, format!("{} screens to capture", ids.len()))),
+        Err(e) => Ok((Status::Warn, format!("failed to list the screens: {}", e))),
+    }
+}
@@ -0,0 +1,97 @@
+//! The `gc` command, removing the image files left behind by frames which were never saved or
+//! whose deletion was interrupted, then compacting the database.
+
+use std::{collections::HashSet, time::Duration};
+
+use anyhow::Result;
+
+use super::App;
+
+/// Frames fetched from the repository per scan.
+const BATCH_SIZE: u32 = 1000;
+/// Archive type of the images stored as files, see `crate::image_archive::fs`.
+const FILE_SYSTEM_ARCHIVE: &str = "file_system";
+/// Extension of the archived image files, other files in the image directory are never
+/// removed.
+const IMAGE_EXTENSION: &str = ".jpg";
+/// Age under which an unreferenced file is kept, a running capture writes the image file
+/// before saving its frame.
+const MIN_ORPHAN_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// Arguments of the `gc` command.
+#[derive(Debug, clap::Args)]
+pub struct GcArgs {
+    /// Report what would be removed without removing anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// Run the `gc` command.
+pub async fn run(args: GcArgs, app: &App) -> Result<()> {
+    // the files are listed after the frames, so a file saved meanwhile is too recent to remove
+    let mut referenced = HashSet::new();
+    let mut after_id = 0;
+    loop {
+        let batch = app
+            .repo
+            .scan_images(after_id, None, None, BATCH_SIZE)
+            .await?;
+        let Some(last) = batch.last() else {
+            break;
+        };
+        after_id = last.id;
+        referenced.extend(
+            batch
+                .into_iter()
+                .filter(|it| it.archive_type == FILE_SYSTEM_ARCHIVE)
+                .map(|it| it.archive_info),
+        );
+    }
+
+    let image_dir = app.config.config.image_dir();
+    let mut orphans = 0;
+    let mut orphan_bytes = 0;
+    let mut entries = tokio::fs::read_dir(&image_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if !metadata.is_file()
+            || !file_name.ends_with(IMAGE_EXTENSION)
+            || referenced.contains(&file_name)
+        {
+            continue;
+        }
+        let age = metadata.modified()?.elapsed().unwrap_or_default();
+        if age < MIN_ORPHAN_AGE {
+            continue;
+        }
+        orphans += 1;
+        orphan_bytes += metadata.len();
+        if args.dry_run {
+            println!("would remove {}", entry.path().display());
+            continue;
+        }
+        match tokio::fs::remove_file(entry.path()).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+    }
+    if args.dry_run {
+        println!(
+            "{} unreferenced image files, {} bytes, would be removed",
+            orphans, orphan_bytes
+        );
+        return Ok(());
+    }
+    println!(
+        "removed {} unreferenced image files, {} bytes",
+        orphans, orphan_bytes
+    );
+
+    let report = app.repo.maintain().await?;
+    println!(
+        "maintained the database, reclaimed {} bytes, {} bytes left",
+        report.reclaimed_bytes, report.database_bytes
+    );
+    Ok(())
+}
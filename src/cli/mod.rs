@@ -0,0 +1,255 @@
+//! The commands of the binary. They share the wiring of the repository, the image archiver and
+//! the analysis built from the configuration, see `App`.
+
+use std::{num::NonZeroUsize, path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+
+use crate::{
+    analysis::Analysis,
+    backup, barcode,
+    config::{self, LoadedConfig},
+    embedding::{self, Embedder},
+    export,
+    image_archive::fs::FileSystemImageArchiver,
+    ocr,
+    reindex::{ReindexOptions, ReindexState, Reindexer},
+    repository::{self, Repository},
+};
+
+mod doctor;
+mod gc;
+mod search;
+mod serve;
+
+/// Interval of the progress lines of the `reindex` command.
+const REINDEX_PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Record the screen and search through what was on it.
+#[derive(Parser)]
+#[command(version)]
+pub struct Cli {
+    /// Record and serve the web interface when absent
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    config: config::ConfigArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Record the screens and serve the web interface
+    Serve {
+        /// Only serve the web interface over the data directory, opening the database read-only
+        /// and refusing to change frames, for an archive copied from elsewhere or a machine
+        /// without a display
+        #[arg(long)]
+        read_only: bool,
+    },
+    /// Record the screens without serving the web interface
+    Capture,
+    /// Search the recorded frames
+    Search(search::SearchArgs),
+    /// Print the texts of a frame and optionally save its image
+    Show(search::ShowArgs),
+    /// Run OCR again over the recorded frames
+    Reindex {
+        /// Start of the range, as an epoch, an RFC 3339 time or a date in UTC
+        #[arg(long, value_parser = export::parse_time)]
+        from: Option<u64>,
+        /// End of the range, as an epoch, an RFC 3339 time or a date in UTC
+        #[arg(long, value_parser = export::parse_time)]
+        to: Option<u64>,
+        /// Continue after the last frame handled by an interrupted run
+        #[arg(long)]
+        resume: bool,
+        /// Pause between two frames
+        #[arg(long, default_value_t = 0)]
+        throttle_millis: u64,
+    },
+    /// Remove the image files no frame refers to and compact the database
+    Gc(gc::GcArgs),
+    /// Export the frames of a time range or matching a search
+    Export(export::ExportArgs),
+    /// Back up the database and the archived images into a single bundle
+    Backup {
+        /// File to write the bundle to
+        #[arg(long, short)]
+        output: PathBuf,
+    },
+    /// Restore a bundle written by `backup` into a fresh data directory
+    Restore {
+        /// The bundle to restore
+        #[arg(long, short)]
+        input: PathBuf,
+    },
+    /// Check the configuration, the database, the archive and the OCR engine
+    Doctor,
+}
+
+/// The parts shared by the commands, wired from the configuration.
+pub struct App {
+    pub config: LoadedConfig,
+    pub repo: Arc<dyn Repository + Send + Sync>,
+    pub archiver: Arc<FileSystemImageArchiver>,
+}
+
+impl App {
+    /// Connect to the database, creating the image directory when missing.
+    pub async fn open(config: LoadedConfig) -> Result<Self> {
+        let image_dir = config.config.image_dir();
+        tokio::fs::create_dir_all(&image_dir).await?;
+        let repo = repository::connect(&config.config.database_url()).await?;
+        let archiver = Arc::new(FileSystemImageArchiver::new(
+            image_dir.to_string_lossy().to_string(),
+            config.config.archive.jpeg_quality as u8,
+        ));
+        Ok(Self {
+            config,
+            repo,
+            archiver,
+        })
+    }
+
+    /// Open the database read-only, creating nothing in the data directory.
+    pub async fn open_read_only(config: LoadedConfig) -> Result<Self> {
+        let repo = repository::connect_read_only(&config.config.database_url()).await?;
+        let archiver = Arc::new(FileSystemImageArchiver::new(
+            config.config.image_dir().to_string_lossy().to_string(),
+            config.config.archive.jpeg_quality as u8,
+        ));
+        Ok(Self {
+            config,
+            repo,
+            archiver,
+        })
+    }
+
+    /// Build the analysis with the OCR engine and the embedder of the configuration.
+    pub async fn analysis(&self) -> Result<Arc<Analysis>> {
+        let config = &self.config.config;
+        let ocr_cache_granularity: ocr::cache::CacheGranularity = config.ocr.cache.parse()?;
+        let cache_capacity = NonZeroUsize::new(config.ocr.cache_capacity as usize).unwrap();
+        let recognizer = Arc::new(ocr::cache::CachedRecognizer::new(
+            Arc::new(ocr::TesseractOCR::new()),
+            ocr_cache_granularity,
+            cache_capacity,
+        ));
+        Ok(Arc::new(Analysis::new(
+            recognizer,
+            Arc::new(barcode::RxingCodeDetector::new(cache_capacity)),
+            self.repo.clone(),
+            self.archiver.clone(),
+            self.embedder().await?,
+        )))
+    }
+
+    /// Build the analysis searching the archive without reading frames, with the embedder of
+    /// the configuration for the semantic searches.
+    pub async fn read_only_analysis(&self) -> Result<Arc<Analysis>> {
+        Ok(Arc::new(Analysis::read_only(
+            self.repo.clone(),
+            self.archiver.clone(),
+            self.embedder().await?,
+        )))
+    }
+
+    async fn embedder(&self) -> Result<Option<Arc<dyn Embedder + Send + Sync>>> {
+        let embedding_provider: embedding::Provider = self.config.config.ocr.embedding.parse()?;
+        embedding_provider.build().await
+    }
+
+    pub fn reindexer(&self, analysis: Arc<Analysis>) -> Arc<Reindexer> {
+        let checkpoint_path = self.config.config.data_dir().join("reindex.checkpoint");
+        Arc::new(Reindexer::new(
+            analysis,
+            self.repo.clone(),
+            checkpoint_path.to_string_lossy().to_string(),
+        ))
+    }
+}
+
+/// Run the command of the arguments, recording and serving when there is none.
+pub async fn run(cli: Cli) -> Result<()> {
+    let config = LoadedConfig::load(&cli.config)?;
+    let command = cli.command.unwrap_or(Command::Serve { read_only: false });
+    // a bundle is restored before anything is created in the data directory
+    if let Command::Restore { input } = &command {
+        backup::restore(input, &config.config).await?;
+        return Ok(());
+    }
+    // the doctor reports a database it fails to open instead of failing with it
+    if let Command::Doctor = &command {
+        return doctor::run(config).await;
+    }
+
+    if let Command::Serve { read_only: true } = &command {
+        return serve::run_read_only(App::open_read_only(config).await?).await;
+    }
+
+    let app = App::open(config).await?;
+    match command {
+        Command::Serve { .. } => serve::run(app, true).await,
+        Command::Capture => serve::run(app, false).await,
+        Command::Search(args) => search::search(args, &app).await,
+        Command::Show(args) => search::show(args, &app).await,
+        Command::Reindex {
+            from,
+            to,
+            resume,
+            throttle_millis,
+        } => {
+            let options = ReindexOptions {
+                from_epoch: from,
+                to_epoch: to,
+                resume,
+                throttle_millis,
+            };
+            reindex(&app, options).await
+        }
+        Command::Gc(args) => gc::run(args, &app).await,
+        Command::Export(args) => export::run(args, app.repo, app.archiver).await,
+        Command::Backup { output } => {
+            backup::backup(app.repo, &app.config.config.image_dir(), &output).await?;
+            Ok(())
+        }
+        Command::Restore { .. } | Command::Doctor => unreachable!("handled before opening"),
+    }
+}
+
+/// Reindex until done, Ctrl-C stops after the current frame and leaves a checkpoint to resume
+/// from.
+async fn reindex(app: &App, options: ReindexOptions) -> Result<()> {
+    let reindexer = app.reindexer(app.analysis().await?);
+    reindexer.clone().start(options).await?;
+    let mut interval = tokio::time::interval(REINDEX_PROGRESS_INTERVAL);
+    let mut interrupted = false;
+    let progress = loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c(), if !interrupted => {
+                eprintln!("stopping after the current frame, resume with `--resume`");
+                reindexer.cancel().await;
+                interrupted = true;
+            },
+            _ = interval.tick() => {
+                let progress = reindexer.progress().await;
+                if progress.state != ReindexState::Running {
+                    break progress;
+                }
+                eprintln!(
+                    "{} frames reindexed, {} failed, last frame {}",
+                    progress.processed_images, progress.failed_images, progress.last_image_id
+                );
+            },
+        }
+    };
+    eprintln!(
+        "reindex {:?}: {} frames reindexed, {} failed, {} texts saved",
+        progress.state, progress.processed_images, progress.failed_images, progress.saved_texts
+    );
+    match progress.error {
+        Some(error) => Err(anyhow::anyhow!("reindex failed: {}", error)),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,172 @@
+//! The `search` and `show` commands, reading the recorded frames from the terminal.
+
+use std::{io::Write, path::PathBuf};
+
+use anyhow::{Context, Result};
+
+use super::App;
+use crate::{
+    export::{self, ExportedFrame},
+    image_archive::{ImageArchive, ImageArchiver},
+    repository::{SearchMode, SearchOptions, SearchResult},
+    snippet,
+};
+
+/// Arguments of the `search` command.
+#[derive(Debug, clap::Args)]
+pub struct SearchArgs {
+    /// The text to search for
+    text: String,
+    /// How the text is matched: `keyword`, `semantic` or `hybrid`
+    #[arg(long, default_value = "keyword")]
+    mode: SearchMode,
+    /// Tolerate typos and OCR errors
+    #[arg(long)]
+    fuzzy: bool,
+    /// Start of the range, as an epoch, an RFC 3339 time or a date in UTC
+    #[arg(long, value_parser = export::parse_time)]
+    from: Option<u64>,
+    /// End of the range, as an epoch, an RFC 3339 time or a date in UTC
+    #[arg(long, value_parser = export::parse_time)]
+    to: Option<u64>,
+    /// Only search the frames of this screen
+    #[arg(long)]
+    screen_id: Option<u32>,
+    /// List the best frame of each session only
+    #[arg(long)]
+    group_by_session: bool,
+    /// Number of listed frames
+    #[arg(long, default_value_t = 10)]
+    limit: u32,
+    /// Number of best ranked frames skipped
+    #[arg(long, default_value_t = 0)]
+    offset: u32,
+    /// Print a JSON object per frame instead of text
+    #[arg(long)]
+    json: bool,
+}
+
+/// Arguments of the `show` command.
+#[derive(Debug, clap::Args)]
+pub struct ShowArgs {
+    /// Id of the frame
+    image_id: u32,
+    /// Save the image of the frame to this file, its extension picks the format
+    #[arg(long)]
+    image: Option<PathBuf>,
+    /// Print a JSON object instead of text
+    #[arg(long)]
+    json: bool,
+}
+
+/// Run the `search` command, listing the best ranked frames with their snippets.
+pub async fn search(args: SearchArgs, app: &App) -> Result<()> {
+    let options = SearchOptions {
+        mode: args.mode,
+        fuzzy: args.fuzzy,
+        from_epoch: args.from,
+        to_epoch: args.to,
+        screen_id: args.screen_id,
+        group_by_session: args.group_by_session,
+        ..SearchOptions::new(args.text, args.limit, args.offset, 0.0)
+    };
+    let page = app.read_only_analysis().await?.search(&options).await?;
+    let mut stdout = std::io::stdout().lock();
+    for result in &page.results {
+        if args.json {
+            serde_json::to_writer(&mut stdout, result)?;
+            writeln!(stdout)?;
+        } else {
+            write_result(&mut stdout, result)?;
+        }
+    }
+    eprintln!("{} of {} matching frames", page.results.len(), page.total);
+    Ok(())
+}
+
+/// A frame and its snippets, the matching words in brackets.
+fn write_result<W: Write>(writer: &mut W, result: &SearchResult) -> Result<()> {
+    write!(
+        writer,
+        "frame {}  {}",
+        result.image_id,
+        export::format_epoch(result.captured_at_epoch, export::TIME_FORMAT)
+    )?;
+    if let Some(screen_id) = result.screen_id {
+        write!(writer, "  screen {}", screen_id)?;
+    }
+    if !result.session_image_ids.is_empty() {
+        write!(
+            writer,
+            "  and {} more in the session",
+            result.session_image_ids.len()
+        )?;
+    }
+    writeln!(writer)?;
+    for snippet in &result.snippets {
+        let words: Vec<String> = snippet
+            .fragments
+            .iter()
+            .map(|it| {
+                if it.highlighted {
+                    format!("[{}]", it.text)
+                } else {
+                    it.text.clone()
+                }
+            })
+            .collect();
+        writeln!(writer, "    {}", words.join(" "))?;
+    }
+    Ok(())
+}
+
+/// Run the `show` command, printing the texts of the frame in reading order.
+pub async fn show(args: ShowArgs, app: &App) -> Result<()> {
+    let image = app
+        .repo
+        .get_image_by_id(args.image_id)
+        .await
+        .with_context(|| format!("failed to read frame {}", args.image_id))?;
+    let texts = app.repo.get_texts_by_image_ids(&[image.id]).await?;
+    if let Some(path) = &args.image {
+        let archive = ImageArchive::new(image.archive_type.clone(), image.archive_info.clone());
+        app.archiver.load(&archive).await?.save(path)?;
+        eprintln!(
+            "saved the image of frame {} to {}",
+            image.id,
+            path.display()
+        );
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    if args.json {
+        let frame = ExportedFrame {
+            image_id: image.id,
+            captured_at_epoch: image.captured_at_epoch,
+            captured_at: export::format_epoch(image.captured_at_epoch, "%Y-%m-%dT%H:%M:%SZ"),
+            screen_id: image.screen_id,
+            image: None,
+            matched_text_ids: vec![],
+            texts,
+        };
+        serde_json::to_writer(&mut stdout, &frame)?;
+        writeln!(stdout)?;
+        return Ok(());
+    }
+    write!(
+        stdout,
+        "frame {}  {}",
+        image.id,
+        export::format_epoch(image.captured_at_epoch, export::TIME_FORMAT)
+    )?;
+    if let Some(screen_id) = image.screen_id {
+        write!(stdout, "  screen {}", screen_id)?;
+    }
+    writeln!(stdout)?;
+    writeln!(stdout)?;
+    for line in snippet::lines(&texts) {
+        let words: Vec<&str> = line.iter().map(|it| it.text.as_str()).collect();
+        writeln!(stdout, "{}", words.join(" "))?;
+    }
+    Ok(())
+}
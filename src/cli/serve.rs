@@ -0,0 +1,173 @@
+//! The `serve` and `capture` commands, recording the screens until Ctrl-C, or serving an
+//! archive without touching it.
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use axum::{extract::MatchedPath, http::Request};
+use tokio::{signal, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+use tower_http::trace::TraceLayer;
+use tracing::{info, info_span};
+
+use super::App;
+use crate::{
+    analysis::Analysis,
+    http, maintenance,
+    markup::ImageMarkupDecorator,
+    screenshot::{self, Capturer},
+    session,
+};
+
+/// Record the screens along with the segmentation of the sessions and the maintenance of the
+/// database, and serve the web interface when `serve_http`.
+pub async fn run(app: App, serve_http: bool) -> Result<()> {
+    let config = app.config.config.clone();
+    let analysis = app.analysis().await?;
+    let token = CancellationToken::new();
+    let segmenter_task = {
+        let options = session::SessionOptions {
+            max_gap_secs: config.capture.session_gap_secs,
+            ..session::SessionOptions::default()
+        };
+        let segmenter = session::Segmenter::new(app.repo.clone(), options);
+        let cloned_token = token.clone();
+        tokio::task::spawn(async move { segmenter.run(cloned_token).await })
+    };
+    let maintenance_task = {
+        let options = maintenance::MaintenanceOptions {
+            interval_secs: config.database.maintenance_interval_secs,
+        };
+        let maintainer = maintenance::Maintainer::new(app.repo.clone(), options);
+        let cloned_token = token.clone();
+        tokio::task::spawn(async move { maintainer.run(cloned_token).await })
+    };
+    let capture_task = {
+        let interval = Duration::from_millis(config.capture.interval_millis);
+        let cloned_token = token.clone();
+        let analysis = analysis.clone();
+        tokio::task::spawn(async move { capture(analysis, interval, cloned_token).await })
+    };
+
+    if serve_http {
+        let service = Arc::new(http::service::Service::new(
+            analysis.clone(),
+            Arc::new(ImageMarkupDecorator::new()),
+            app.repo.clone(),
+            app.archiver.clone(),
+            Some(app.reindexer(analysis.clone())),
+            Arc::new(app.config),
+            false,
+        ));
+        spawn_server(service, config.bind_address(), token.clone());
+    }
+
+    shutdown_on_ctrl_c(token).await?;
+    capture_task.await?;
+    segmenter_task.await?;
+    maintenance_task.await?;
+    Ok(())
+}
+
+/// Serve the web interface over the archive without ever writing to it, neither recording nor
+/// maintaining the database, the requests changing frames are refused. No OCR engine is set up,
+/// only the embedder of the semantic searches.
+pub async fn run_read_only(app: App) -> Result<()> {
+    let config = app.config.config.clone();
+    let service = Arc::new(http::service::Service::new(
+        app.read_only_analysis().await?,
+        Arc::new(ImageMarkupDecorator::new()),
+        app.repo.clone(),
+        app.archiver.clone(),
+        None,
+        Arc::new(app.config),
+        true,
+    ));
+    info!(
+        "serving {} read-only on {}",
+        config.data_dir().display(),
+        config.bind_address()
+    );
+    let token = CancellationToken::new();
+    let server_task = spawn_server(service, config.bind_address(), token.clone());
+    shutdown_on_ctrl_c(token).await?;
+    server_task.await?;
+    Ok(())
+}
+
+fn spawn_server(
+    service: Arc<http::service::Service>,
+    address: SocketAddr,
+    token: CancellationToken,
+) -> JoinHandle<()> {
+    let router = http::router(service).layer(TraceLayer::new_for_http().make_span_with(
+        |request: &Request<_>| {
+            // Log the matched route's path (with placeholders not filled in).
+            // Use request.uri() or OriginalUri if you want the real path.
+            let matched_path = request
+                .extensions()
+                .get::<MatchedPath>()
+                .map(MatchedPath::as_str);
+
+            info_span!(
+                "http_request",
+                method = ?request.method(),
+                matched_path,
+                some_other_field = tracing::field::Empty,
+            )
+        },
+    ));
+    tokio::task::spawn(async move {
+        axum::Server::bind(&address)
+            .serve(router.into_make_service())
+            .with_graceful_shutdown(async {
+                token.cancelled().await;
+            })
+            .await
+            .unwrap();
+    })
+}
+
+async fn shutdown_on_ctrl_c(token: CancellationToken) -> Result<()> {
+    let shutdown_guard = tokio::spawn(async move {
+        signal::ctrl_c().await.unwrap();
+        info!("Ctrl-C received, shutting down");
+        token.cancel();
+    });
+    shutdown_guard.await?;
+    Ok(())
+}
+
+/// Capture all the screens at every interval and record them until cancelled.
+async fn capture(analysis: Arc<Analysis>, interval: Duration, token: CancellationToken) {
+    let capturer = screenshot::DefaultCapturer::new();
+    let mut capture_interval = tokio::time::interval(interval);
+    loop {
+        if token.is_cancelled() {
+            break;
+        }
+        tokio::select! {
+            _ = token.cancelled() => {
+                info!("shutting down capture task");
+                break;
+            },
+            _ = capture_interval.tick() => {
+                let captures = capturer.capture().await.unwrap();
+                let mut tasks: Vec<JoinHandle<()>> = Vec::new();
+                for item in captures {
+                    let analysis = analysis.clone();
+                    let task = tokio::task::spawn(async move {
+                        let result = analysis.record_screenshot(&item).await;
+                        if let Err(e) = result {
+                            info!("failed to record screenshot: {}", e);
+                        }
+                    });
+                    tasks.push(task);
+                }
+                for task in tasks {
+                    task.await.unwrap();
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,455 @@
+//! Configuration of dejavu, layered from the defaults, a TOML file, environment variables and
+//! command-line flags, each layer overriding the previous ones.
+//!
+//! Keys are grouped in sections, `http.bind` is `bind` in the `[http]` table of the file, the
+//! `DEJAVU_HTTP_BIND` environment variable, or `--set http.bind=...` on the command line.
+
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{embedding, ocr::cache::CacheGranularity};
+
+/// Environment variables named before the configuration was layered, with their keys.
+const LEGACY_VARIABLES: &[(&str, &str)] = &[("DEJAVU_EMBEDDING", "ocr.embedding")];
+/// Where a key left to its default comes from.
+const DEFAULT_SOURCE: &str = "default";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub paths: PathsConfig,
+    pub database: DatabaseConfig,
+    pub capture: CaptureConfig,
+    pub ocr: OcrConfig,
+    pub archive: ArchiveConfig,
+    pub http: HttpConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PathsConfig {
+    /// Directory of the data, `~` stands for the home directory.
+    pub data_dir: String,
+    /// The SQLite database, relative to the data directory.
+    pub database_file: String,
+    /// Directory of the archived images, relative to the data directory.
+    pub image_dir: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// URL of the database, the SQLite database of the data directory when empty.
+    pub url: String,
+    /// Pause between two maintenances of the database, see `crate::maintenance`.
+    pub maintenance_interval_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CaptureConfig {
+    /// Pause between two captures of the screens, at least a second as the archive names its
+    /// files and the frames are timed to the second.
+    pub interval_millis: u64,
+    /// Longest pause between two frames of a session of activity, see `crate::session`.
+    pub session_gap_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OcrConfig {
+    /// Granularity of the cache of recognized texts, `frame` or `<width>x<height>` tiles.
+    pub cache: String,
+    /// Number of frames or tiles kept in the cache.
+    pub cache_capacity: u64,
+    /// Embedder of the lines for semantic search, see `crate::embedding::Provider`.
+    pub embedding: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArchiveConfig {
+    /// Quality of the archived JPEG images, from 1 to 100.
+    pub jpeg_quality: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpConfig {
+    /// Address the web server listens on.
+    pub bind: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let data_dir = dirs::data_dir()
+            .map(|it| it.join("dejavu").to_string_lossy().to_string())
+            .unwrap_or_else(|| "~/.dejavu".to_string());
+        Self {
+            paths: PathsConfig {
+                data_dir,
+                database_file: "dejavu.db".to_string(),
+                image_dir: "images".to_string(),
+            },
+            database: DatabaseConfig {
+                url: String::new(),
+                maintenance_interval_secs: 6 * 3600,
+            },
+            capture: CaptureConfig {
+                interval_millis: 2000,
+                session_gap_secs: 300,
+            },
+            ocr: OcrConfig {
+                cache: "frame".to_string(),
+                cache_capacity: 1024,
+                embedding: "hashing".to_string(),
+            },
+            archive: ArchiveConfig { jpeg_quality: 90 },
+            http: HttpConfig {
+                bind: "0.0.0.0:12333".to_string(),
+            },
+        }
+    }
+}
+
+impl Config {
+    pub fn data_dir(&self) -> PathBuf {
+        match self.paths.data_dir.strip_prefix("~/") {
+            Some(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+            None => PathBuf::from(&self.paths.data_dir),
+        }
+    }
+
+    pub fn image_dir(&self) -> PathBuf {
+        self.data_dir().join(&self.paths.image_dir)
+    }
+
+    /// The SQLite database, used unless `database.url` is set.
+    pub fn database_file(&self) -> PathBuf {
+        self.data_dir().join(&self.paths.database_file)
+    }
+
+    /// URL of the database, creating the SQLite database of the data directory if needed.
+    pub fn database_url(&self) -> String {
+        if !self.database.url.is_empty() {
+            return self.database.url.clone();
+        }
+        format!("{}?mode=rwc", self.database_file().display())
+    }
+
+    pub fn bind_address(&self) -> SocketAddr {
+        self.http.bind.parse().expect("validated by `validate`")
+    }
+
+    /// Check the values beyond their types, the error names the key and where it was set.
+    fn validate(&self, sources: &BTreeMap<String, String>) -> Result<()> {
+        let invalid = |key: &str, message: String| {
+            let source = sources.get(key).map_or(DEFAULT_SOURCE, String::as_str);
+            anyhow!("invalid `{}` from {}: {}", key, source, message)
+        };
+        for (key, value) in [
+            ("paths.data_dir", &self.paths.data_dir),
+            ("paths.database_file", &self.paths.database_file),
+            ("paths.image_dir", &self.paths.image_dir),
+        ] {
+            if value.is_empty() {
+                return Err(invalid(key, "must not be empty".to_string()));
+            }
+        }
+        for (key, value) in [
+            (
+                "database.maintenance_interval_secs",
+                self.database.maintenance_interval_secs,
+            ),
+            ("capture.session_gap_secs", self.capture.session_gap_secs),
+            ("ocr.cache_capacity", self.ocr.cache_capacity),
+        ] {
+            if value == 0 {
+                return Err(invalid(key, "must be greater than 0".to_string()));
+            }
+        }
+        if self.capture.interval_millis < 1000 {
+            return Err(invalid(
+                "capture.interval_millis",
+                format!(
+                    "must be at least 1000, got {}",
+                    self.capture.interval_millis
+                ),
+            ));
+        }
+        self.ocr
+            .cache
+            .parse::<CacheGranularity>()
+            .map_err(|e| invalid("ocr.cache", e.to_string()))?;
+        self.ocr
+            .embedding
+            .parse::<embedding::Provider>()
+            .map_err(|e| invalid("ocr.embedding", e.to_string()))?;
+        if !(1..=100).contains(&self.archive.jpeg_quality) {
+            return Err(invalid(
+                "archive.jpeg_quality",
+                format!("must be from 1 to 100, got {}", self.archive.jpeg_quality),
+            ));
+        }
+        self.http.bind.parse::<SocketAddr>().map_err(|_| {
+            invalid(
+                "http.bind",
+                format!("expect `<ip>:<port>`, got `{}`", self.http.bind),
+            )
+        })?;
+        Ok(())
+    }
+}
+
+/// Command-line flags of the configuration, shared by every command.
+#[derive(Debug, Default, clap::Args)]
+pub struct ConfigArgs {
+    /// Configuration file, `<config dir>/dejavu/config.toml` is read when it exists otherwise
+    #[arg(long, global = true, env = "DEJAVU_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Directory of the data, overriding `paths.data_dir`
+    #[arg(long, global = true)]
+    pub data_dir: Option<String>,
+    /// Address of the web server, overriding `http.bind`
+    #[arg(long, global = true)]
+    pub bind: Option<String>,
+    /// Set a key of the configuration, like `--set capture.interval_millis=5000`
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE")]
+    pub overrides: Vec<String>,
+}
+
+/// The effective configuration, along with where its keys were set.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LoadedConfig {
+    pub config: Config,
+    /// The configuration file read, if any.
+    pub file: Option<PathBuf>,
+    /// Where each key not left to its default was set, by key.
+    pub sources: BTreeMap<String, String>,
+}
+
+impl LoadedConfig {
+    /// Layer the configuration file, the environment variables and the flags over the
+    /// defaults.
+    pub fn load(args: &ConfigArgs) -> Result<Self> {
+        let file = match &args.config {
+            Some(path) => Some(path.clone()),
+            None => dirs::config_dir()
+                .map(|it| it.join("dejavu").join("config.toml"))
+                .filter(|it| it.exists()),
+        };
+        let content = file
+            .as_ref()
+            .map(|path| {
+                std::fs::read_to_string(path)
+                    .with_context(|| format!("failed to read {}", path.display()))
+            })
+            .transpose()?;
+        let mut flags = vec![];
+        if let Some(data_dir) = &args.data_dir {
+            flags.push((
+                "paths.data_dir".to_string(),
+                data_dir.clone(),
+                "flag --data-dir".to_string(),
+            ));
+        }
+        if let Some(bind) = &args.bind {
+            flags.push((
+                "http.bind".to_string(),
+                bind.clone(),
+                "flag --bind".to_string(),
+            ));
+        }
+        for set in &args.overrides {
+            let (key, value) = set
+                .split_once('=')
+                .ok_or_else(|| anyhow!("expect `--set <key>=<value>`, got `{}`", set))?;
+            flags.push((
+                key.to_string(),
+                value.to_string(),
+                format!("flag --set {}", key),
+            ));
+        }
+        Self::layer(
+            file.as_deref().zip(content.as_deref()),
+            std::env::vars(),
+            flags,
+        )
+    }
+
+    /// Layer the content of the file, the variables of the environment and the flags as
+    /// `(key, value, source)` over the defaults.
+    pub fn layer(
+        file: Option<(&Path, &str)>,
+        variables: impl IntoIterator<Item = (String, String)>,
+        flags: Vec<(String, String, String)>,
+    ) -> Result<Self> {
+        let mut layers = Layers::new()?;
+        if let Some((path, content)) = file {
+            let source = format!("file {}", path.display());
+            let table: toml::Table = toml::from_str(content)
+                .with_context(|| format!("invalid configuration file {}", path.display()))?;
+            for (section, keys) in table {
+                let toml::Value::Table(keys) = keys else {
+                    return Err(anyhow!(
+                        "`{}` in {} must be a table of keys",
+                        section,
+                        source
+                    ));
+                };
+                for (name, value) in keys {
+                    layers.set(&format!("{}.{}", section, name), value, &source)?;
+                }
+            }
+        }
+
+        let variables: BTreeMap<String, String> = variables.into_iter().collect();
+        let mut keys_by_variable: Vec<(String, String)> = LEGACY_VARIABLES
+            .iter()
+            .map(|(variable, key)| (variable.to_string(), key.to_string()))
+            .collect();
+        keys_by_variable.extend(layers.keys().into_iter().map(|key| {
+            (
+                format!("DEJAVU_{}", key.replace('.', "_").to_uppercase()),
+                key,
+            )
+        }));
+        for (variable, key) in keys_by_variable {
+            if let Some(value) = variables.get(&variable) {
+                layers.set(
+                    &key,
+                    toml::Value::String(value.clone()),
+                    &format!("env {}", variable),
+                )?;
+            }
+        }
+
+        for (key, value, source) in flags {
+            layers.set(&key, toml::Value::String(value), &source)?;
+        }
+        let mut loaded = layers.finish()?;
+        loaded.file = file.map(|(path, _)| path.to_path_buf());
+        Ok(loaded)
+    }
+
+    /// The configuration with the credentials of the database URL masked, for display. Both the
+    /// password of the user and a `password` query parameter are masked.
+    pub fn redacted(&self) -> Self {
+        let mut redacted = self.clone();
+        let url = &mut redacted.config.database.url;
+        if let Some((scheme, rest)) = url.split_once("://") {
+            if let Some((credentials, host)) = rest.rsplit_once('@') {
+                if let Some((user, _)) = credentials.split_once(':') {
+                    *url = format!("{}://{}:***@{}", scheme, user, host);
+                }
+            }
+        }
+        if let Some((base, query)) = url.split_once('?') {
+            let parameters: Vec<String> = query
+                .split('&')
+                .map(|it| match it.split_once('=') {
+                    Some((key, _)) if key.eq_ignore_ascii_case("password") => {
+                        format!("{}=***", key)
+                    }
+                    _ => it.to_string(),
+                })
+                .collect();
+            *url = format!("{}?{}", base, parameters.join("&"));
+        }
+        redacted
+    }
+}
+
+/// Sections of keys being layered over the defaults.
+struct Layers {
+    defaults: toml::Table,
+    table: toml::Table,
+    sources: BTreeMap<String, String>,
+}
+
+impl Layers {
+    fn new() -> Result<Self> {
+        let defaults = toml::Table::try_from(Config::default())?;
+        Ok(Self {
+            table: defaults.clone(),
+            defaults,
+            sources: BTreeMap::new(),
+        })
+    }
+
+    /// Every key, as `section.key`.
+    fn keys(&self) -> Vec<String> {
+        self.defaults
+            .iter()
+            .filter_map(|(section, keys)| Some((section, keys.as_table()?)))
+            .flat_map(|(section, keys)| {
+                keys.keys().map(move |name| format!("{}.{}", section, name))
+            })
+            .collect()
+    }
+
+    /// Set the key, a string is converted to the type of the key.
+    fn set(&mut self, key: &str, value: toml::Value, source: &str) -> Result<()> {
+        let unknown = || anyhow!("unknown configuration key `{}` from {}", key, source);
+        let (section, name) = key.split_once('.').ok_or_else(unknown)?;
+        let default = self
+            .defaults
+            .get(section)
+            .and_then(|it| it.get(name))
+            .ok_or_else(unknown)?;
+        let value = match (default, value) {
+            (toml::Value::Integer(_), toml::Value::String(s)) => match s.trim().parse::<u64>() {
+                Ok(it) => toml::Value::Integer(it.try_into()?),
+                Err(_) => {
+                    return Err(anyhow!(
+                        "invalid `{}` from {}: expect a positive integer, got `{}`",
+                        key,
+                        source,
+                        s
+                    ))
+                }
+            },
+            (toml::Value::Integer(_), toml::Value::Integer(it)) if it < 0 => {
+                return Err(anyhow!(
+                    "invalid `{}` from {}: expect a positive integer, got {}",
+                    key,
+                    source,
+                    it
+                ))
+            }
+            (default, value) if default.same_type(&value) => value,
+            (default, value) => {
+                return Err(anyhow!(
+                    "invalid `{}` from {}: expect {}, got {}",
+                    key,
+                    source,
+                    default.type_str(),
+                    value.type_str()
+                ))
+            }
+        };
+        self.table
+            .get_mut(section)
+            .and_then(|it| it.as_table_mut())
+            .expect("sections of the defaults")
+            .insert(name.to_string(), value);
+        self.sources.insert(key.to_string(), source.to_string());
+        Ok(())
+    }
+
+    fn finish(self) -> Result<LoadedConfig> {
+        let config: Config = toml::Value::Table(self.table).try_into()?;
+        config.validate(&self.sources)?;
+        Ok(LoadedConfig {
+            config,
+            file: None,
+            sources: self.sources,
+        })
+    }
+}
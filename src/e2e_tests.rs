@@ -0,0 +1,1570 @@
+//! End-to-end tests driving the ingest path and the http api in-process, with scripted capture
+//! and OCR backends standing in for the display and tesseract.
+
+use std::{num::NonZeroUsize, sync::Arc};
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, Method, Request, StatusCode},
+    Router,
+};
+use clap::Parser;
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use rxing::{qrcode::QRCodeWriter, BarcodeFormat, Writer};
+use sqlx_sqlite::SqlitePoolOptions;
+use tower::ServiceExt;
+
+use crate::{
+    analysis::Analysis,
+    backup,
+    barcode::RxingCodeDetector,
+    cli,
+    config::{Config, LoadedConfig},
+    embedding::hashing::HashingEmbedder,
+    http,
+    image_archive::{fs::FileSystemImageArchiver, in_memory::InMemoryImageArchiver},
+    markup::ImageMarkupDecorator,
+    ocr::{scripted::ScriptedRecognizer, MarkupBox},
+    reindex::{ReindexOptions, ReindexProgress, ReindexState, Reindexer},
+    repository::{
+        self, in_memory::InMemoryRepository, sqlite::SqliteRepository, EntityText, Repository,
+        SearchOptions, SearchResult, TextKind, TimelineOptions,
+    },
+    screenshot::{scripted::ScriptedCapturer, Capturer},
+    session::{Segmenter, SessionOptions},
+};
+
+struct Harness {
+    capturer: ScriptedCapturer,
+    ocr: Arc<ScriptedRecognizer>,
+    analysis: Arc<Analysis>,
+    repo: Arc<dyn Repository + Send + Sync>,
+    archiver: Arc<InMemoryImageArchiver>,
+    router: Router,
+}
+
+impl Harness {
+    fn new(repo: Arc<dyn Repository + Send + Sync>) -> Self {
+        Self::with_access(repo, false)
+    }
+
+    /// Serve the repository refusing the requests writing to it.
+    fn read_only(repo: Arc<dyn Repository + Send + Sync>) -> Self {
+        Self::with_access(repo, true)
+    }
+
+    fn with_access(repo: Arc<dyn Repository + Send + Sync>, read_only: bool) -> Self {
+        let ocr = Arc::new(ScriptedRecognizer::new());
+        let archiver = Arc::new(InMemoryImageArchiver::new());
+        let embedder = Arc::new(HashingEmbedder::new());
+        // a read-only server reads no frame, like `cli::serve::run_read_only`
+        let (analysis, reindexer) = if read_only {
+            let analysis = Analysis::read_only(repo.clone(), archiver.clone(), Some(embedder));
+            (Arc::new(analysis), None)
+        } else {
+            let analysis = Arc::new(Analysis::new(
+                ocr.clone(),
+                code_detector(),
+                repo.clone(),
+                archiver.clone(),
+                Some(embedder),
+            ));
+            let checkpoint_path = std::env::temp_dir()
+                .join(format!("dejavu-{}.checkpoint", uuid::Uuid::new_v4()))
+                .to_string_lossy()
+                .to_string();
+            let reindexer = Arc::new(Reindexer::new(
+                analysis.clone(),
+                repo.clone(),
+                checkpoint_path,
+            ));
+            (analysis, Some(reindexer))
+        };
+        let service = Arc::new(http::service::Service::new(
+            analysis.clone(),
+            Arc::new(ImageMarkupDecorator::new()),
+            repo.clone(),
+            archiver.clone(),
+            reindexer,
+            Arc::new(LoadedConfig::default()),
+            read_only,
+        ));
+        Self {
+            capturer: ScriptedCapturer::new(),
+            ocr,
+            analysis,
+            repo,
+            archiver,
+            router: http::router(service),
+        }
+    }
+
+    fn in_memory() -> Self {
+        Self::new(Arc::new(InMemoryRepository::new()))
+    }
+
+    async fn sqlite() -> Self {
+        // connections of the pool share the same in-memory database
+        let pool = SqlitePoolOptions::new()
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let repo = SqliteRepository::new(pool);
+        repo.initialize().await.unwrap();
+        Self::new(Arc::new(repo))
+    }
+
+    /// Script a frame of one screen, recognized as the given words.
+    async fn script_frame(
+        &self,
+        captured_at_epoch: u64,
+        image: DynamicImage,
+        words: Vec<(&str, MarkupBox)>,
+    ) {
+        self.ocr.script(&image, words).await;
+        self.capturer
+            .push(captured_at_epoch, vec![(0, image)])
+            .await;
+    }
+
+    /// Capture once and record every screenshot, like a tick of the capture loop.
+    async fn tick(&self) {
+        let captures = self.capturer.capture().await.unwrap();
+        for item in captures {
+            self.analysis.record_screenshot(&item).await.unwrap();
+        }
+    }
+
+    /// First page of images matching the text, ranked by relevance.
+    async fn search(&self, text: &str) -> anyhow::Result<Vec<SearchResult>> {
+        let options = SearchOptions::new(text.to_string(), 100, 0, 0.0);
+        Ok(self.analysis.search(&options).await?.results)
+    }
+
+    async fn get(&self, uri: &str) -> (StatusCode, HeaderMap, Vec<u8>) {
+        self.request(Method::GET, uri).await
+    }
+
+    async fn delete(&self, uri: &str) -> (StatusCode, HeaderMap, Vec<u8>) {
+        self.request(Method::DELETE, uri).await
+    }
+
+    async fn request(&self, method: Method, uri: &str) -> (StatusCode, HeaderMap, Vec<u8>) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, headers, body.to_vec())
+    }
+}
+
+fn code_detector() -> Arc<RxingCodeDetector> {
+    Arc::new(RxingCodeDetector::new(NonZeroUsize::new(16).unwrap()))
+}
+
+fn frame(shade: u8) -> DynamicImage {
+    DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+        320,
+        200,
+        Rgba([shade, shade, shade, 255]),
+    ))
+}
+
+async fn assert_search_finds_recorded_words(harness: Harness) {
+    harness
+        .script_frame(
+            1_000,
+            frame(220),
+            vec![
+                ("hello", MarkupBox::new(20, 20, 60, 16)),
+                ("world", MarkupBox::new(90, 20, 60, 16)),
+            ],
+        )
+        .await;
+    harness
+        .script_frame(
+            1_002,
+            frame(200),
+            vec![("world", MarkupBox::new(40, 100, 60, 16))],
+        )
+        .await;
+    harness.tick().await;
+    harness.tick().await;
+
+    let hello = harness.search("hello").await.unwrap();
+    assert_eq!(hello.len(), 1);
+    assert_eq!(hello[0].texts.len(), 1);
+    assert_eq!(hello[0].texts[0].text, "hello");
+    let image = harness
+        .repo
+        .get_image_by_id(hello[0].image_id)
+        .await
+        .unwrap();
+    assert_eq!(image.captured_at_epoch, 1_000);
+
+    let world = harness.search("world").await.unwrap();
+    let mut image_ids: Vec<u32> = world.iter().map(|it| it.image_id).collect();
+    image_ids.sort();
+    image_ids.dedup();
+    assert_eq!(image_ids.len(), 2);
+
+    assert!(harness.search("missing").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn search_finds_recorded_words_in_memory() {
+    assert_search_finds_recorded_words(Harness::in_memory()).await;
+}
+
+#[tokio::test]
+async fn search_finds_recorded_words_sqlite() {
+    assert_search_finds_recorded_words(Harness::sqlite().await).await;
+}
+
+#[tokio::test]
+async fn search_endpoint_returns_matching_texts() {
+    let harness = Harness::sqlite().await;
+    harness
+        .script_frame(
+            1_000,
+            frame(220),
+            vec![("invoice", MarkupBox::new(20, 20, 80, 16))],
+        )
+        .await;
+    harness.tick().await;
+
+    let (status, _, body) = harness.get("/api/search?text=invoice").await;
+    assert_eq!(status, StatusCode::OK);
+    let results: Vec<SearchResult> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].texts[0].text, "invoice");
+    assert_eq!(results[0].texts[0].left, 20);
+}
+
+#[tokio::test]
+async fn qr_codes_are_decoded_and_searchable() {
+    let harness = Harness::sqlite().await;
+    let matrix = QRCodeWriter
+        .encode("guest-network", &BarcodeFormat::QR_CODE, 120, 120)
+        .unwrap();
+    let mut image = RgbaImage::from_pixel(320, 200, Rgba([255, 255, 255, 255]));
+    for y in 0..matrix.getHeight() {
+        for x in 0..matrix.getWidth() {
+            if matrix.get(x, y) {
+                image.put_pixel(100 + x, 40 + y, Rgba([0, 0, 0, 255]));
+            }
+        }
+    }
+    harness
+        .script_frame(1_000, DynamicImage::ImageRgba8(image), vec![])
+        .await;
+    harness.tick().await;
+
+    let (status, _, body) = harness.get("/api/search?text=guest").await;
+    assert_eq!(status, StatusCode::OK);
+    let results: Vec<SearchResult> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(results.len(), 1);
+    let code = &results[0].texts[0];
+    assert_eq!(code.kind, TextKind::QrCode);
+    assert_eq!(code.text, "guest-network");
+    // the box lies on the code, within its quiet zone
+    assert!(code.left >= 100 && code.left + code.width <= 220);
+    assert!(code.top >= 40 && code.top + code.height <= 160);
+    assert!(code.width >= 60 && code.height >= 60);
+}
+
+#[tokio::test]
+async fn search_endpoint_ranks_and_paginates() {
+    let harness = Harness::sqlite().await;
+    for (epoch, shade, words) in [
+        (1_000, 200, "quarterly report draft for the team"),
+        (1_002, 210, "report"),
+        (1_004, 220, "annual report of the company"),
+        (1_006, 230, "unrelated"),
+    ] {
+        harness
+            .script_frame(
+                epoch,
+                frame(shade),
+                vec![(words, MarkupBox::new(20, 20, 200, 16))],
+            )
+            .await;
+        harness.tick().await;
+    }
+
+    let (status, headers, body) = harness.get("/api/search?text=report&limit=2").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["x-total-count"], "3");
+    let first_page: Vec<SearchResult> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(first_page.len(), 2);
+    // the shortest matching text is the most relevant
+    assert_eq!(first_page[0].texts[0].text, "report");
+    assert_eq!(first_page[0].captured_at_epoch, 1_002);
+    assert!(first_page[0].score <= first_page[1].score);
+
+    let (_, headers, body) = harness
+        .get("/api/search?text=report&limit=2&offset=2")
+        .await;
+    assert_eq!(headers["x-total-count"], "3");
+    let second_page: Vec<SearchResult> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(second_page.len(), 1);
+    assert!(first_page
+        .iter()
+        .all(|it| it.image_id != second_page[0].image_id));
+
+    let (_, headers, body) = harness
+        .get("/api/search?text=report&limit=2&offset=10")
+        .await;
+    assert_eq!(headers["x-total-count"], "3");
+    let past_end: Vec<SearchResult> = serde_json::from_slice(&body).unwrap();
+    assert!(past_end.is_empty());
+}
+
+#[tokio::test]
+async fn search_endpoint_filters_by_time_and_screen() {
+    let harness = Harness::sqlite().await;
+    for (epoch, shade) in [(1_000, 200), (2_000, 210)] {
+        let left = frame(shade);
+        let right = frame(shade + 5);
+        harness
+            .ocr
+            .script(
+                &left,
+                vec![("meeting", MarkupBox::new(20, u32::from(shade) / 10, 80, 16))],
+            )
+            .await;
+        harness
+            .ocr
+            .script(
+                &right,
+                vec![("meeting", MarkupBox::new(40, u32::from(shade) / 5, 80, 16))],
+            )
+            .await;
+        harness
+            .capturer
+            .push(epoch, vec![(1, left), (2, right)])
+            .await;
+        harness.tick().await;
+    }
+
+    let search = |uri: &'static str| async {
+        let (status, headers, body) = harness.get(uri).await;
+        assert_eq!(status, StatusCode::OK);
+        let results: Vec<SearchResult> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(headers["x-total-count"], results.len().to_string().as_str());
+        results
+    };
+    assert_eq!(search("/api/search?text=meeting").await.len(), 4);
+
+    let late = search("/api/search?text=meeting&from=1500").await;
+    assert_eq!(late.len(), 2);
+    assert!(late.iter().all(|it| it.captured_at_epoch == 2_000));
+
+    let right = search("/api/search?text=meeting&screen_id=2&to=1500").await;
+    assert_eq!(right.len(), 1);
+    assert_eq!(right[0].screen_id, Some(2));
+    assert_eq!(right[0].captured_at_epoch, 1_000);
+
+    assert!(search("/api/search?text=meeting&from=3000")
+        .await
+        .is_empty());
+}
+
+async fn assert_fuzzy_search_tolerates_ocr_errors(harness: Harness) {
+    for (epoch, shade, word) in [
+        (1_000, 200, "rnodern"),
+        (1_002, 210, "modern"),
+        (1_004, 220, "modular"),
+    ] {
+        harness
+            .script_frame(
+                epoch,
+                frame(shade),
+                vec![(word, MarkupBox::new(20, 20, 80, 16))],
+            )
+            .await;
+        harness.tick().await;
+    }
+
+    let exact = harness.search("modern").await.unwrap();
+    assert_eq!(exact.len(), 1);
+
+    let options = SearchOptions {
+        fuzzy: true,
+        ..SearchOptions::new("Modern".to_string(), 10, 0, 0.0)
+    };
+    let fuzzy = harness.analysis.search(&options).await.unwrap();
+    assert_eq!(fuzzy.total, 2);
+    assert_eq!(fuzzy.results[0].texts[0].text, "modern");
+    assert_eq!(fuzzy.results[1].texts[0].text, "rnodern");
+}
+
+#[tokio::test]
+async fn fuzzy_search_tolerates_ocr_errors_in_memory() {
+    assert_fuzzy_search_tolerates_ocr_errors(Harness::in_memory()).await;
+}
+
+#[tokio::test]
+async fn fuzzy_search_tolerates_ocr_errors_sqlite() {
+    assert_fuzzy_search_tolerates_ocr_errors(Harness::sqlite().await).await;
+}
+
+#[tokio::test]
+async fn search_endpoint_accepts_fuzzy_mode() {
+    let harness = Harness::sqlite().await;
+    harness
+        .script_frame(
+            1_000,
+            frame(220),
+            vec![("invoce", MarkupBox::new(20, 20, 80, 16))],
+        )
+        .await;
+    harness.tick().await;
+
+    let (_, headers, _) = harness.get("/api/search?text=invoice").await;
+    assert_eq!(headers["x-total-count"], "0");
+    let (status, headers, body) = harness.get("/api/search?text=invoice&fuzzy=true").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["x-total-count"], "1");
+    let results: Vec<SearchResult> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(results[0].texts[0].text, "invoce");
+}
+
+#[tokio::test]
+async fn search_results_carry_line_snippets() {
+    let harness = Harness::sqlite().await;
+    harness
+        .script_frame(
+            1_000,
+            frame(220),
+            vec![
+                ("the", MarkupBox::new(10, 20, 24, 12)),
+                ("quick", MarkupBox::new(40, 21, 40, 12)),
+                ("brown", MarkupBox::new(86, 20, 40, 13)),
+                ("fox", MarkupBox::new(132, 19, 24, 12)),
+                // a word of another column on the same line
+                ("sidebar", MarkupBox::new(260, 20, 50, 12)),
+                // a word of the next line
+                ("jumps", MarkupBox::new(10, 40, 40, 12)),
+            ],
+        )
+        .await;
+    harness.tick().await;
+
+    let (status, _, body) = harness.get("/api/search?text=brown").await;
+    assert_eq!(status, StatusCode::OK);
+    let results: Vec<SearchResult> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(results[0].snippets.len(), 1);
+    let snippet = &results[0].snippets[0];
+    let text: String = snippet
+        .fragments
+        .iter()
+        .map(|it| it.text.as_str())
+        .collect();
+    assert_eq!(text, "the quick brown fox");
+    let highlighted: Vec<&str> = snippet
+        .fragments
+        .iter()
+        .filter(|it| it.highlighted)
+        .map(|it| it.text.as_str())
+        .collect();
+    assert_eq!(highlighted, vec!["brown"]);
+}
+
+#[tokio::test]
+async fn text_with_quotes_survives_ingest() {
+    let harness = Harness::sqlite().await;
+    harness
+        .script_frame(
+            1_000,
+            frame(220),
+            vec![("don't", MarkupBox::new(20, 20, 60, 16))],
+        )
+        .await;
+    harness.tick().await;
+
+    let results = harness.search("don").await.unwrap();
+    assert_eq!(results.len(), 1);
+    let text = harness
+        .repo
+        .get_text_by_id(results[0].texts[0].id)
+        .await
+        .unwrap();
+    assert_eq!(text.text, "don't");
+}
+
+#[tokio::test]
+async fn frame_with_many_texts_is_saved_whole() {
+    let harness = Harness::sqlite().await;
+    let words: Vec<String> = (0..300)
+        .map(|i| format!("word{}'); DROP TABLE texts; --", i))
+        .collect();
+    harness
+        .script_frame(
+            1_000,
+            frame(220),
+            words
+                .iter()
+                .enumerate()
+                .map(|(i, it)| (it.as_str(), MarkupBox::new(i as u32, 0, 1, 1)))
+                .collect(),
+        )
+        .await;
+    harness.tick().await;
+
+    let results = harness.search("word299").await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].texts[0].text, words[299]);
+    assert_eq!(results[0].texts[0].left, 299);
+    let results = harness.search("DROP").await.unwrap();
+    assert_eq!(results[0].texts.len(), 300);
+}
+
+#[tokio::test]
+async fn image_endpoint_renders_markup() {
+    let harness = Harness::in_memory();
+    harness
+        .script_frame(
+            1_000,
+            frame(220),
+            vec![("receipt", MarkupBox::new(100, 80, 80, 20))],
+        )
+        .await;
+    harness.tick().await;
+    let results = harness.search("receipt").await.unwrap();
+    let uri = format!(
+        "/api/image?image_id={}&text_ids={}",
+        results[0].image_id, results[0].texts[0].id
+    );
+
+    let (status, _, body) = harness.get(&uri).await;
+    assert_eq!(status, StatusCode::OK);
+    let rendered = image::load_from_memory(&body).unwrap();
+    assert_eq!((rendered.width(), rendered.height()), (320, 200));
+
+    // the recognized word keeps its pixels
+    let inside = rendered.get_pixel(140, 90);
+    assert!(inside[0] > 180, "inside {:?}", inside);
+    // the rest of the frame is darkened
+    let outside = rendered.get_pixel(10, 10);
+    assert!(outside[0] < 80, "outside {:?}", outside);
+    // the word is framed by a yellow border
+    let border = rendered.get_pixel(140, 78);
+    assert!(
+        border[0] > 180 && border[1] > 180 && border[2] < 120,
+        "border {:?}",
+        border
+    );
+
+    let (status, _, body) = harness
+        .get(&format!(
+            "/api/image?image_id={}&text_ids=abc",
+            results[0].image_id
+        ))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(String::from_utf8_lossy(&body), "invalid text id `abc`");
+}
+
+#[tokio::test]
+async fn timeline_endpoints_scrub_through_frames() {
+    let harness = Harness::sqlite().await;
+    for (epoch, shade) in [(1_000, 200), (1_010, 210), (1_020, 220)] {
+        harness.script_frame(epoch, frame(shade), vec![]).await;
+        harness.tick().await;
+    }
+    let harness = &harness;
+    let get_json = |uri: String| async move {
+        let (status, _, body) = harness.get(&uri).await;
+        assert_eq!(status, StatusCode::OK, "{}", uri);
+        serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+    };
+
+    let first = get_json("/api/timeline?limit=2".to_string()).await;
+    let epochs: Vec<u64> = first["frames"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|it| it["captured_at_epoch"].as_u64().unwrap())
+        .collect();
+    assert_eq!(epochs, vec![1_000, 1_010]);
+    let cursor = first["next_cursor"].as_str().unwrap();
+    let second = get_json(format!("/api/timeline?limit=2&cursor={}", cursor)).await;
+    assert_eq!(second["frames"][0]["captured_at_epoch"], 1_020);
+    assert!(second["next_cursor"].is_null());
+    for uri in ["/api/timeline?cursor=soon", "/api/sessions?cursor=1000.x"] {
+        let (status, _, _) = harness.get(uri).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+    }
+
+    let nearest = get_json("/api/timeline/nearest?at=1012".to_string()).await;
+    assert_eq!(nearest["captured_at_epoch"], 1_010);
+    let image_id = nearest["image_id"].as_u64().unwrap();
+    let next = get_json(format!(
+        "/api/timeline/adjacent?image_id={}&direction=next",
+        image_id
+    ))
+    .await;
+    assert_eq!(next["captured_at_epoch"], 1_020);
+    let previous = get_json(format!(
+        "/api/timeline/adjacent?image_id={}&direction=previous",
+        next["image_id"]
+    ))
+    .await;
+    assert_eq!(previous["image_id"].as_u64(), Some(image_id));
+
+    // a frame is served without markup when no text is given
+    let (status, _, body) = harness
+        .get(&format!("/api/image?image_id={}", image_id))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let rendered = image::load_from_memory(&body).unwrap();
+    assert!(rendered.get_pixel(10, 10)[0] > 180);
+}
+
+#[tokio::test]
+async fn semantic_and_hybrid_search_find_similar_lines() {
+    let harness = Harness::sqlite().await;
+    let lines = [
+        (1_000, 200, "error connection pool exhausted after timeout"),
+        (1_002, 202, "weekly report draft for the team"),
+        (1_004, 204, "lunch menu pasta salad"),
+    ];
+    for (epoch, shade, line) in lines {
+        let words = line
+            .split(' ')
+            .enumerate()
+            .map(|(i, it)| (it, MarkupBox::new(10 + 60 * i as u32, 20, 50, 16)))
+            .collect();
+        harness.script_frame(epoch, frame(shade), words).await;
+        harness.tick().await;
+    }
+    let harness = &harness;
+    let search = |uri: &'static str| async move {
+        let (status, headers, body) = harness.get(uri).await;
+        assert_eq!(status, StatusCode::OK, "{}", uri);
+        let results: Vec<SearchResult> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(headers["x-total-count"], results.len().to_string().as_str());
+        results
+    };
+
+    // the words differ from the line but share most of their letters
+    assert!(search("/api/search?text=pool%20exhaustion")
+        .await
+        .is_empty());
+    let semantic = search("/api/search?text=pool%20exhaustion&mode=semantic").await;
+    assert_eq!(semantic.len(), 1);
+    assert_eq!(semantic[0].captured_at_epoch, 1_000);
+    let words: Vec<&str> = semantic[0]
+        .texts
+        .iter()
+        .map(|it| it.text.as_str())
+        .collect();
+    assert_eq!(words, line_words(lines[0].2));
+    assert_eq!(semantic[0].snippets.len(), 1);
+
+    // keyword matches rank first when both rankings agree
+    let hybrid = search("/api/search?text=draft%20report&mode=hybrid").await;
+    assert_eq!(hybrid[0].captured_at_epoch, 1_002);
+    assert!(hybrid
+        .iter()
+        .all(|it| it.captured_at_epoch != 1_004 || it.score > hybrid[0].score));
+
+    let (status, _, _) = harness
+        .delete("/api/search?text=pool%20exhaustion&mode=semantic")
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+fn line_words(line: &str) -> Vec<&str> {
+    line.split(' ').collect()
+}
+
+#[tokio::test]
+async fn reocr_endpoint_reads_a_region_in_frame_coordinates() {
+    let harness = Harness::sqlite().await;
+    harness
+        .script_frame(
+            1_000,
+            frame(220),
+            vec![("invoice", MarkupBox::new(20, 20, 80, 16))],
+        )
+        .await;
+    harness.tick().await;
+    let image_id = harness.search("invoice").await.unwrap()[0].image_id;
+    // the region is read upscaled twice
+    let upscaled = DynamicImage::ImageLuma8(image::imageops::resize(
+        &frame(220).crop_imm(40, 30, 100, 50).to_luma8(),
+        200,
+        100,
+        image::imageops::FilterType::Lanczos3,
+    ));
+    harness
+        .ocr
+        .script(&upscaled, vec![("total", MarkupBox::new(20, 10, 61, 15))])
+        .await;
+
+    let uri = |region: &str, extra: &str| {
+        format!(
+            "/api/image/ocr?image_id={}&{}&scale=2{}",
+            image_id, region, extra
+        )
+    };
+    let (status, _, body) = harness
+        .request(Method::POST, &uri("left=40&top=30&width=100&height=50", ""))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let texts: Vec<EntityText> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(texts.len(), 1);
+    assert_eq!(texts[0].text, "total");
+    assert_eq!(
+        (texts[0].left, texts[0].top, texts[0].width, texts[0].height),
+        (50, 35, 31, 8)
+    );
+    assert!(harness.search("total").await.unwrap().is_empty());
+
+    let (status, _, _) = harness
+        .request(
+            Method::POST,
+            &uri("left=40&top=30&width=100&height=50", "&save=true"),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let found = harness.search("total").await.unwrap();
+    assert_eq!(found[0].image_id, image_id);
+    assert_eq!(found[0].texts[0].left, 50);
+
+    for region in [
+        "left=40&top=30&width=0&height=50",
+        "left=300&top=30&width=100&height=50",
+        "left=0&top=190&width=10&height=20",
+    ] {
+        let (status, _, _) = harness.request(Method::POST, &uri(region, "")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", region);
+    }
+    let (status, _, body) = harness
+        .request(
+            Method::POST,
+            &format!(
+                "/api/image/ocr?image_id={}&left=0&top=0&width=10&height=10&scale=9",
+                image_id
+            ),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(String::from_utf8_lossy(&body).contains("scale"));
+}
+
+/// Record a frame per epoch reading "draft", then script the same frames to read "final".
+async fn drafts_read_again(harness: &Harness, epochs: &[u64]) {
+    for (i, epoch) in epochs.iter().enumerate() {
+        harness
+            .script_frame(
+                *epoch,
+                frame(200 + i as u8),
+                vec![("draft", MarkupBox::new(20, 20, 60, 16))],
+            )
+            .await;
+        harness.tick().await;
+    }
+    for i in 0..epochs.len() {
+        harness
+            .ocr
+            .script(
+                &frame(200 + i as u8),
+                vec![("final", MarkupBox::new(20, 20, 60, 16))],
+            )
+            .await;
+    }
+}
+
+fn reindexer(harness: &Harness, checkpoint_path: &std::path::Path) -> Arc<Reindexer> {
+    Arc::new(Reindexer::new(
+        harness.analysis.clone(),
+        harness.repo.clone(),
+        checkpoint_path.to_string_lossy().to_string(),
+    ))
+}
+
+/// Wait for the running job to finish.
+async fn reindexed(reindexer: &Reindexer) -> ReindexProgress {
+    // the job is done once it no longer takes a cancel
+    while reindexer.progress().await.state == ReindexState::Running || reindexer.cancel().await {
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    }
+    reindexer.progress().await
+}
+
+async fn captured_epochs(harness: &Harness, text: &str) -> Vec<u64> {
+    let mut epochs: Vec<u64> = harness
+        .search(text)
+        .await
+        .unwrap()
+        .iter()
+        .map(|it| it.captured_at_epoch)
+        .collect();
+    epochs.sort();
+    epochs
+}
+
+#[tokio::test]
+async fn reindex_replaces_texts_within_the_range() {
+    let harness = Harness::sqlite().await;
+    drafts_read_again(&harness, &[1_000, 1_010, 1_020]).await;
+    let checkpoint_path =
+        std::env::temp_dir().join(format!("dejavu-{}.checkpoint", uuid::Uuid::new_v4()));
+    let reindexer = reindexer(&harness, &checkpoint_path);
+
+    let options = ReindexOptions {
+        from_epoch: Some(1_005),
+        to_epoch: Some(1_015),
+        ..ReindexOptions::default()
+    };
+    reindexer.clone().start(options).await.unwrap();
+    let progress = reindexed(&reindexer).await;
+    assert_eq!(progress.state, ReindexState::Completed);
+    assert_eq!(progress.processed_images, 1);
+    assert_eq!(captured_epochs(&harness, "final").await, vec![1_010]);
+    assert_eq!(captured_epochs(&harness, "draft").await, vec![1_000, 1_020]);
+
+    reindexer
+        .clone()
+        .start(ReindexOptions::default())
+        .await
+        .unwrap();
+    let progress = reindexed(&reindexer).await;
+    assert_eq!(progress.state, ReindexState::Completed);
+    assert_eq!((progress.processed_images, progress.saved_texts), (3, 3));
+    assert_eq!(
+        captured_epochs(&harness, "final").await,
+        vec![1_000, 1_010, 1_020]
+    );
+    assert!(captured_epochs(&harness, "draft").await.is_empty());
+    // a completed run leaves nothing to resume
+    assert!(!checkpoint_path.exists());
+}
+
+#[tokio::test]
+async fn cancelled_reindex_resumes_from_its_checkpoint() {
+    let harness = Harness::sqlite().await;
+    drafts_read_again(&harness, &[1_000, 1_010, 1_020]).await;
+    let checkpoint_path =
+        std::env::temp_dir().join(format!("dejavu-{}.checkpoint", uuid::Uuid::new_v4()));
+    let reindexer = reindexer(&harness, &checkpoint_path);
+    let first_image_id = harness.repo.scan_images(0, None, None, 1).await.unwrap()[0].id;
+
+    // the long pause after the first frame leaves the time to cancel
+    let slow = ReindexOptions {
+        throttle_millis: 60_000,
+        ..ReindexOptions::default()
+    };
+    let cancel_after_first_frame = |options: ReindexOptions| {
+        let reindexer = reindexer.clone();
+        async move {
+            let started = reindexer.clone().start(options).await.unwrap();
+            while reindexer.progress().await.processed_images == 0 {
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+            assert!(reindexer.cancel().await);
+            (started, reindexed(&reindexer).await)
+        }
+    };
+    let (_, progress) = cancel_after_first_frame(slow.clone()).await;
+    assert_eq!(progress.state, ReindexState::Cancelled);
+    assert_eq!(progress.processed_images, 1);
+    assert_eq!(progress.last_image_id, first_image_id);
+    assert_eq!(captured_epochs(&harness, "final").await, vec![1_000]);
+
+    // a checkpoint of another range is not resumed
+    let other_range = ReindexOptions {
+        from_epoch: Some(1_000),
+        resume: true,
+        ..slow.clone()
+    };
+    let (started, _) = cancel_after_first_frame(other_range).await;
+    assert_eq!(started.last_image_id, 0);
+
+    let resume = ReindexOptions {
+        resume: true,
+        ..ReindexOptions::default()
+    };
+    let started = reindexer.clone().start(resume.clone()).await.unwrap();
+    // the checkpoint now belongs to the other range, so the run starts over
+    assert_eq!(started.last_image_id, 0);
+    let progress = reindexed(&reindexer).await;
+    assert_eq!(progress.state, ReindexState::Completed);
+    assert_eq!(progress.processed_images, 3);
+
+    let (_, progress) = cancel_after_first_frame(slow.clone()).await;
+    assert_eq!(progress.last_image_id, first_image_id);
+    let started = reindexer.clone().start(resume).await.unwrap();
+    assert_eq!(started.last_image_id, first_image_id);
+    let progress = reindexed(&reindexer).await;
+    assert_eq!(progress.state, ReindexState::Completed);
+    // only the frames after the checkpoint
+    assert_eq!(progress.processed_images, 2);
+    assert_eq!(
+        captured_epochs(&harness, "final").await,
+        vec![1_000, 1_010, 1_020]
+    );
+    assert!(!checkpoint_path.exists());
+}
+
+#[tokio::test]
+async fn frames_are_segmented_into_sessions() {
+    let harness = Harness::sqlite().await;
+    let record = |epoch: u64, line: &'static str, top: u32| {
+        let harness = &harness;
+        async move {
+            let words = line_words(line)
+                .into_iter()
+                .enumerate()
+                .map(|(i, it)| (it, MarkupBox::new(10 + 90 * i as u32, top, 80, 16)))
+                .collect();
+            harness.script_frame(epoch, frame(top as u8), words).await;
+            harness.tick().await;
+        }
+    };
+    // the screen scrolls, switches to another window, then is left alone for a while
+    record(1_000, "budget meeting agenda", 20).await;
+    record(1_002, "budget meeting agenda notes", 40).await;
+    record(1_004, "compiler error borrow checker", 60).await;
+    record(1_400, "budget meeting agenda", 80).await;
+
+    let segmenter = Segmenter::new(harness.repo.clone(), SessionOptions::default());
+    assert_eq!(segmenter.segment(1_004).await.unwrap(), 3);
+    assert_eq!(segmenter.segment(2_000).await.unwrap(), 1);
+    assert_eq!(segmenter.segment(2_000).await.unwrap(), 0);
+    record(1_402, "budget meeting agenda", 100).await;
+    assert_eq!(segmenter.segment(2_000).await.unwrap(), 1);
+
+    let (status, _, body) = harness.get("/api/sessions").await;
+    assert_eq!(status, StatusCode::OK);
+    let page: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let sessions = page["sessions"].as_array().unwrap();
+    let spans: Vec<(u64, u64)> = sessions
+        .iter()
+        .map(|it| {
+            (
+                it["started_at_epoch"].as_u64().unwrap(),
+                it["ended_at_epoch"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(spans, vec![(1_000, 1_002), (1_004, 1_004), (1_400, 1_402)]);
+    // the frame with the most words stands for the session
+    assert_eq!(
+        sessions[0]["representative_image_id"],
+        sessions[0]["last_image_id"]
+    );
+
+    let every = harness.search("budget").await.unwrap();
+    assert_eq!(every.len(), 4);
+    assert!(every.iter().all(|it| it.session.is_some()));
+    let (status, headers, body) = harness
+        .get("/api/search?text=budget&group_by_session=true")
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["x-total-count"], "2");
+    let grouped: Vec<SearchResult> = serde_json::from_slice(&body).unwrap();
+    let mut epochs: Vec<u64> = grouped
+        .iter()
+        .map(|it| it.session.as_ref().unwrap().started_at_epoch)
+        .collect();
+    epochs.sort();
+    assert_eq!(epochs, vec![1_000, 1_400]);
+    assert!(grouped.iter().all(|it| it.session_image_ids.len() == 1));
+}
+
+#[tokio::test]
+async fn stats_endpoint_reports_archive_and_ingest() {
+    let harness = Harness::sqlite().await;
+    for (epoch, shade, word) in [(1_000, 200, "alpha"), (1_010, 210, "beta")] {
+        harness
+            .script_frame(
+                epoch,
+                frame(shade),
+                vec![(word, MarkupBox::new(10, 10, 60, 16))],
+            )
+            .await;
+        harness.tick().await;
+    }
+
+    let (status, _, body) = harness.get("/api/stats").await;
+    assert_eq!(status, StatusCode::OK);
+    let stats: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(stats["images"], 2);
+    assert_eq!(stats["texts"], 2);
+    assert!(stats["database_bytes"].as_u64().unwrap() > 0);
+    assert!(stats["archive_bytes"].as_u64().unwrap() > 0);
+    assert_eq!(
+        stats["daily_frames"],
+        serde_json::json!([{ "date": "1970-01-01", "screen_id": 0, "frames": 2 }])
+    );
+    assert_eq!(stats["ingest"]["recorded_frames"], 2);
+    assert_eq!(stats["ingest"]["failed_frames"], 0);
+    assert_eq!(stats["ingest"]["ocr_runs"], 2);
+    // the scripted recognizer has no cache
+    assert!(stats["ocr_cache"].is_null());
+}
+
+#[tokio::test]
+async fn export_endpoint_writes_jsonl_markdown_and_zip() {
+    let harness = Harness::sqlite().await;
+    for (epoch, shade, words) in [
+        (1_000, 200, vec!["deploy", "failed"]),
+        (1_010, 210, vec!["lunch", "menu"]),
+        (1_020, 220, vec!["deploy", "rolled", "back"]),
+    ] {
+        let boxes = words
+            .into_iter()
+            .enumerate()
+            .map(|(i, it)| (it, MarkupBox::new(10 + 90 * i as u32, 20, 80, 16)))
+            .collect();
+        harness.script_frame(epoch, frame(shade), boxes).await;
+        harness.tick().await;
+    }
+
+    let (status, headers, body) = harness.get("/api/export?format=jsonl&from=1005").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["content-type"], "application/x-ndjson");
+    assert_eq!(headers["x-total-count"], "2");
+    let frames: Vec<serde_json::Value> = String::from_utf8(body)
+        .unwrap()
+        .lines()
+        .map(|it| serde_json::from_str(it).unwrap())
+        .collect();
+    let epochs: Vec<u64> = frames
+        .iter()
+        .map(|it| it["captured_at_epoch"].as_u64().unwrap())
+        .collect();
+    assert_eq!(epochs, vec![1_010, 1_020]);
+    assert_eq!(frames[0]["captured_at"], "1970-01-01T00:16:50Z");
+    assert_eq!(frames[0]["texts"][1]["text"], "menu");
+    assert_eq!(frames[0]["texts"][1]["left"], 100);
+    assert!(frames[0].get("image").is_none());
+
+    let (status, _, body) = harness.get("/api/export?format=markdown&text=deploy").await;
+    assert_eq!(status, StatusCode::OK);
+    let transcript = String::from_utf8(body).unwrap();
+    assert!(transcript.starts_with("# Dejavu export\n"));
+    assert!(transcript.contains("- Frames: 2\n"));
+    assert!(transcript.contains("**deploy** failed  \n"));
+    assert!(transcript.contains("**deploy** rolled back  \n"));
+    assert!(!transcript.contains("lunch"));
+
+    let (status, headers, body) = harness.get("/api/export?format=zip&text=deploy").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        headers["content-disposition"],
+        "attachment; filename=\"dejavu-export.zip\""
+    );
+    let mut bundle = zip::ZipArchive::new(std::io::Cursor::new(body)).unwrap();
+    let mut names: Vec<String> = bundle.file_names().map(str::to_string).collect();
+    names.sort();
+    let image_names: Vec<&String> = names
+        .iter()
+        .filter(|it| it.starts_with("images/"))
+        .collect();
+    assert_eq!(image_names.len(), 2);
+    assert!(names.contains(&"frames.jsonl".to_string()));
+    let mut transcript = String::new();
+    std::io::Read::read_to_string(
+        &mut bundle.by_name("transcript.md").unwrap(),
+        &mut transcript,
+    )
+    .unwrap();
+    assert!(transcript.contains(&format!("]({})", image_names[0])));
+    let image = image::load_from_memory(&{
+        let mut bytes = Vec::new();
+        std::io::Read::read_to_end(&mut bundle.by_name(image_names[0]).unwrap(), &mut bytes)
+            .unwrap();
+        bytes
+    })
+    .unwrap();
+    assert_eq!(image.dimensions(), (320, 200));
+
+    let (status, _, _) = harness.get("/api/export?format=pdf").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn backup_bundle_restores_into_fresh_data_directory() {
+    let root = std::env::temp_dir().join(format!("dejavu-backup-{}", uuid::Uuid::new_v4()));
+    let image_dir = root.join("data").join("images");
+    std::fs::create_dir_all(&image_dir).unwrap();
+    let repo = repository::connect(&format!(
+        "{}?mode=rwc",
+        root.join("data").join("dejavu.db").display()
+    ))
+    .await
+    .unwrap();
+    let ocr = Arc::new(ScriptedRecognizer::new());
+    let analysis = Analysis::new(
+        ocr.clone(),
+        code_detector(),
+        repo.clone(),
+        Arc::new(FileSystemImageArchiver::new(
+            image_dir.to_string_lossy().to_string(),
+            90,
+        )),
+        None,
+    );
+    // archived files are named after the second of capture and the screen
+    let capturer = ScriptedCapturer::new();
+    ocr.script(
+        &frame(200),
+        vec![("invoice", MarkupBox::new(10, 10, 60, 16))],
+    )
+    .await;
+    ocr.script(
+        &frame(220),
+        vec![("receipt", MarkupBox::new(10, 10, 60, 16))],
+    )
+    .await;
+    capturer
+        .push(1_000, vec![(0, frame(200)), (1, frame(220))])
+        .await;
+    for item in capturer.capture().await.unwrap() {
+        analysis.record_screenshot(&item).await.unwrap();
+    }
+    // no partially written file is left behind
+    assert_eq!(std::fs::read_dir(&image_dir).unwrap().count(), 2);
+
+    let bundle = root.join("backup.zip");
+    let manifest = backup::backup(repo.clone(), &image_dir, &bundle)
+        .await
+        .unwrap();
+    assert_eq!(manifest.images, 2);
+    assert_eq!(manifest.files.len(), 2);
+    // the copy of the database is not left behind
+    assert_eq!(std::fs::read_dir(&root).unwrap().count(), 2);
+
+    let restored = root.join("restored");
+    let mut config = Config::default();
+    config.paths.data_dir = restored.to_string_lossy().to_string();
+    config.paths.database_file = "db/archive.sqlite".to_string();
+    config.paths.image_dir = "frames".to_string();
+    let manifest = backup::restore(&bundle, &config).await.unwrap();
+    assert_eq!(manifest.images, 2);
+    assert_eq!(
+        std::fs::read_dir(restored.join("frames")).unwrap().count(),
+        2
+    );
+    assert!(!restored.join("images").exists());
+    let restored_repo = repository::connect(&config.database_url()).await.unwrap();
+    let page = restored_repo
+        .search(&SearchOptions::new("receipt".to_string(), 10, 0, 0.0))
+        .await
+        .unwrap();
+    assert_eq!(page.results.len(), 1);
+
+    // a data directory holding data is left alone
+    assert!(backup::restore(&bundle, &config).await.is_err());
+    let bogus = root.join("bogus.zip");
+    std::fs::write(&bogus, b"not a bundle").unwrap();
+    let target = root.join("bogus");
+    config.paths.data_dir = target.to_string_lossy().to_string();
+    assert!(backup::restore(&bogus, &config).await.is_err());
+    assert!(!target.exists());
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+fn layered_config(
+    file: &str,
+    variables: &[(&str, &str)],
+    flags: &[(&str, &str)],
+) -> anyhow::Result<LoadedConfig> {
+    LoadedConfig::layer(
+        Some((std::path::Path::new("/etc/dejavu.toml"), file)),
+        variables
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string())),
+        flags
+            .iter()
+            .map(|(key, value)| {
+                (
+                    key.to_string(),
+                    value.to_string(),
+                    format!("flag --set {}", key),
+                )
+            })
+            .collect(),
+    )
+}
+
+#[tokio::test]
+async fn configuration_is_layered_and_names_invalid_keys() {
+    let loaded = layered_config(
+        "[capture]\ninterval_millis = 5000\n\n[http]\nbind = \"127.0.0.1:8080\"\n",
+        &[
+            ("DEJAVU_HTTP_BIND", "127.0.0.1:9090"),
+            ("DEJAVU_EMBEDDING", "none"),
+            ("DEJAVU_DATABASE_URL", "postgres://dejavu:secret@db/dejavu"),
+        ],
+        &[("capture.interval_millis", "3000")],
+    )
+    .unwrap();
+    assert_eq!(loaded.config.capture.interval_millis, 3000);
+    assert_eq!(loaded.config.http.bind, "127.0.0.1:9090");
+    assert_eq!(loaded.config.ocr.embedding, "none");
+    assert_eq!(loaded.config.archive.jpeg_quality, 90);
+    assert_eq!(
+        loaded.sources["capture.interval_millis"],
+        "flag --set capture.interval_millis"
+    );
+    assert_eq!(loaded.sources["http.bind"], "env DEJAVU_HTTP_BIND");
+    assert!(!loaded.sources.contains_key("archive.jpeg_quality"));
+    assert_eq!(
+        loaded.redacted().config.database.url,
+        "postgres://dejavu:***@db/dejavu"
+    );
+    let loaded = layered_config(
+        "",
+        &[(
+            "DEJAVU_DATABASE_URL",
+            "postgres://db/dejavu?user=dejavu&password=secret&sslmode=require",
+        )],
+        &[],
+    )
+    .unwrap();
+    assert_eq!(
+        loaded.redacted().config.database.url,
+        "postgres://db/dejavu?user=dejavu&password=***&sslmode=require"
+    );
+
+    let error = |file: &str, variables: &[(&str, &str)]| {
+        layered_config(file, variables, &[])
+            .unwrap_err()
+            .to_string()
+    };
+    assert_eq!(
+        error("[http]\nport = 80\n", &[]),
+        "unknown configuration key `http.port` from file /etc/dejavu.toml"
+    );
+    assert_eq!(
+        error("[http]\nbind = 8080\n", &[]),
+        "invalid `http.bind` from file /etc/dejavu.toml: expect string, got integer"
+    );
+    assert_eq!(
+        error("", &[("DEJAVU_CAPTURE_INTERVAL_MILLIS", "soon")]),
+        "invalid `capture.interval_millis` from env DEJAVU_CAPTURE_INTERVAL_MILLIS: expect a positive integer, got `soon`"
+    );
+    assert_eq!(
+        error("[capture]\ninterval_millis = 500\n", &[]),
+        "invalid `capture.interval_millis` from file /etc/dejavu.toml: must be at least 1000, got 500"
+    );
+    assert_eq!(
+        error("", &[("DEJAVU_ARCHIVE_JPEG_QUALITY", "120")]),
+        "invalid `archive.jpeg_quality` from env DEJAVU_ARCHIVE_JPEG_QUALITY: must be from 1 to 100, got 120"
+    );
+    assert!(error("[ocr]\ncache = \"tiles\"\n", &[])
+        .starts_with("invalid `ocr.cache` from file /etc/dejavu.toml: "));
+
+    let harness = Harness::in_memory();
+    let (status, _, body) = harness.get("/api/config").await;
+    assert_eq!(status, StatusCode::OK);
+    let effective: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(effective["config"]["http"]["bind"], "0.0.0.0:12333");
+    assert_eq!(effective["config"]["capture"]["interval_millis"], 2000);
+    assert_eq!(effective["sources"], serde_json::json!({}));
+}
+
+#[tokio::test]
+async fn gc_command_removes_only_stale_unreferenced_image_files() {
+    let root = std::env::temp_dir().join(format!("dejavu-gc-{}", uuid::Uuid::new_v4()));
+    let image_dir = root.join("images");
+    std::fs::create_dir_all(&image_dir).unwrap();
+    let repo = repository::connect(&format!("{}?mode=rwc", root.join("dejavu.db").display()))
+        .await
+        .unwrap();
+    let ocr = Arc::new(ScriptedRecognizer::new());
+    let analysis = Analysis::new(
+        ocr.clone(),
+        code_detector(),
+        repo.clone(),
+        Arc::new(FileSystemImageArchiver::new(
+            image_dir.to_string_lossy().to_string(),
+            90,
+        )),
+        None,
+    );
+    ocr.script(
+        &frame(200),
+        vec![("invoice", MarkupBox::new(10, 10, 60, 16))],
+    )
+    .await;
+    let capturer = ScriptedCapturer::new();
+    capturer.push(1_000, vec![(0, frame(200))]).await;
+    for item in capturer.capture().await.unwrap() {
+        analysis.record_screenshot(&item).await.unwrap();
+    }
+    let recorded: Vec<_> = std::fs::read_dir(&image_dir)
+        .unwrap()
+        .map(|it| it.unwrap().path())
+        .collect();
+    assert_eq!(recorded.len(), 1);
+
+    let stale = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+    for name in ["orphan.jpg", "notes.txt"] {
+        std::fs::File::create(image_dir.join(name))
+            .unwrap()
+            .set_modified(stale)
+            .unwrap();
+    }
+    std::fs::File::options()
+        .write(true)
+        .open(&recorded[0])
+        .unwrap()
+        .set_modified(stale)
+        .unwrap();
+    // a capture in progress writes its file before saving its frame
+    std::fs::write(image_dir.join("capturing.jpg"), b"").unwrap();
+
+    let config = root.join("config.toml");
+    std::fs::write(&config, "").unwrap();
+    let run = |args: &[&str]| {
+        let mut argv = vec![
+            "dejavu".to_string(),
+            "--config".to_string(),
+            config.to_string_lossy().to_string(),
+            "--data-dir".to_string(),
+            root.to_string_lossy().to_string(),
+        ];
+        argv.extend(args.iter().map(|it| it.to_string()));
+        cli::run(cli::Cli::try_parse_from(argv).unwrap())
+    };
+    run(&["gc", "--dry-run"]).await.unwrap();
+    assert!(image_dir.join("orphan.jpg").exists());
+    run(&["gc"]).await.unwrap();
+    let mut left: Vec<String> = std::fs::read_dir(&image_dir)
+        .unwrap()
+        .map(|it| it.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    left.sort();
+    let mut expected = vec![
+        "capturing.jpg".to_string(),
+        "notes.txt".to_string(),
+        recorded[0]
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string(),
+    ];
+    expected.sort();
+    assert_eq!(left, expected);
+
+    // the other commands share the wiring
+    run(&["search", "invoice", "--json"]).await.unwrap();
+    run(&["show", "1"]).await.unwrap();
+    assert!(run(&["show", "2"]).await.is_err());
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn unchanged_screen_texts_are_stored_once() {
+    let harness = Harness::sqlite().await;
+    for (epoch, shade, words) in [
+        (1_000, 200, vec!["inbox", "draft"]),
+        (1_002, 201, vec!["inbox", "draft"]),
+        (1_004, 202, vec!["inbox", "sent"]),
+    ] {
+        let boxes = words
+            .into_iter()
+            .enumerate()
+            .map(|(i, it)| (it, MarkupBox::new(20, 20 + 30 * i as u32, 80, 16)))
+            .collect();
+        harness.script_frame(epoch, frame(shade), boxes).await;
+        harness.tick().await;
+    }
+
+    let inbox = harness.search("inbox").await.unwrap();
+    assert_eq!(inbox.len(), 1);
+    assert_eq!(inbox[0].captured_at_epoch, 1_000);
+    let draft = harness.search("draft").await.unwrap();
+    assert_eq!(draft.len(), 1);
+    let frames = harness
+        .repo
+        .list_images(&TimelineOptions::new(10))
+        .await
+        .unwrap();
+    assert_eq!(frames.len(), 3);
+    assert_eq!(draft[0].texts[0].last_image_id, frames[1].id);
+
+    // each frame still lists every text seen in it
+    let texts = harness
+        .repo
+        .get_texts_by_image_ids(&[frames[2].id])
+        .await
+        .unwrap();
+    let words: Vec<&str> = texts.iter().map(|it| it.text.as_str()).collect();
+    assert_eq!(words, vec!["inbox", "sent"]);
+    assert_eq!(texts[0].id, inbox[0].texts[0].id);
+}
+
+#[tokio::test]
+async fn delete_endpoints_remove_frames_texts_and_archives() {
+    let harness = Harness::sqlite().await;
+    for (epoch, shade, word) in [
+        (1_000, 200, "secret"),
+        (1_010, 210, "public"),
+        (1_020, 220, "secret"),
+        (1_030, 230, "public"),
+        (1_040, 240, "public"),
+    ] {
+        harness
+            .script_frame(
+                epoch,
+                frame(shade),
+                vec![(word, MarkupBox::new(20, u32::from(shade) / 10, 80, 16))],
+            )
+            .await;
+        harness.tick().await;
+    }
+    let harness = &harness;
+    let delete = |uri: &'static str| async move {
+        let (status, _, body) = harness.delete(uri).await;
+        assert_eq!(status, StatusCode::OK, "{}", uri);
+        let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        result["deleted"].as_u64().unwrap()
+    };
+    let archived = || async { harness.archiver.storage.lock().await.len() };
+
+    assert_eq!(delete("/api/search?text=secret").await, 2);
+    assert!(harness.search("secret").await.unwrap().is_empty());
+    assert_eq!(archived().await, 3);
+
+    let public = harness.search("public").await.unwrap();
+    assert_eq!(public.len(), 3);
+    let (status, _, _) = harness
+        .delete(&format!("/api/image?image_id={}", public[0].image_id))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(harness
+        .repo
+        .get_image_by_id(public[0].image_id)
+        .await
+        .is_err());
+    assert_eq!(archived().await, 2);
+
+    assert_eq!(delete("/api/timeline?from=0&to=1035").await, 1);
+    let left = harness.search("public").await.unwrap();
+    assert_eq!(left.len(), 1);
+    assert_eq!(left[0].captured_at_epoch, 1_040);
+    assert_eq!(archived().await, 1);
+}
+
+#[tokio::test]
+async fn deleting_matches_keeps_frames_outside_the_range() {
+    let harness = Harness::sqlite().await;
+    // the text is seen from 1_000 to 1_030
+    for (epoch, shade) in [(1_000, 200), (1_010, 201), (1_020, 202), (1_030, 203)] {
+        harness
+            .script_frame(
+                epoch,
+                frame(shade),
+                vec![("secret", MarkupBox::new(20, 20, 80, 16))],
+            )
+            .await;
+        harness.tick().await;
+    }
+    assert_eq!(harness.search("secret").await.unwrap().len(), 1);
+
+    let (status, _, body) = harness
+        .delete("/api/search?text=secret&from=1005&to=1025")
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let result: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(result["deleted"], 2);
+    let left: Vec<u64> = harness
+        .repo
+        .list_images(&TimelineOptions::new(10))
+        .await
+        .unwrap()
+        .iter()
+        .map(|it| it.captured_at_epoch)
+        .collect();
+    assert_eq!(left, vec![1_000, 1_030]);
+    // the text is still seen in the frames left
+    let found = harness.search("secret").await.unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].captured_at_epoch, 1_000);
+    let (_, _, body) = harness.get("/api/search?text=secret&from=1005").await;
+    let found: Vec<SearchResult> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].captured_at_epoch, 1_030);
+    assert_eq!(harness.archiver.storage.lock().await.len(), 2);
+}
+
+#[tokio::test]
+async fn read_only_server_serves_archive_and_refuses_changes() {
+    let root = std::env::temp_dir().join(format!("dejavu-read-only-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&root).unwrap();
+    let database = root.join("dejavu.db");
+    let url = format!("{}?mode=rwc", database.display());
+    let writer = Harness::new(repository::connect(&url).await.unwrap());
+    writer
+        .script_frame(
+            1_000,
+            frame(200),
+            vec![("invoice", MarkupBox::new(10, 10, 60, 16))],
+        )
+        .await;
+    writer.tick().await;
+    drop(writer);
+
+    let harness = Harness::read_only(repository::connect_read_only(&url).await.unwrap());
+    let (status, _, body) = harness.get("/api/search?text=invoice").await;
+    assert_eq!(status, StatusCode::OK);
+    let results: Vec<SearchResult> = serde_json::from_slice(&body).unwrap();
+    let image_id = results[0].image_id;
+    let (status, _, _) = harness.get("/api/stats").await;
+    assert_eq!(status, StatusCode::OK);
+
+    for (method, uri) in [
+        (Method::DELETE, format!("/api/image?image_id={}", image_id)),
+        (Method::DELETE, "/api/search?text=invoice".to_string()),
+        (Method::DELETE, "/api/timeline?from=0&to=2000".to_string()),
+        (Method::POST, "/api/reindex".to_string()),
+        (
+            Method::POST,
+            format!(
+                "/api/image/ocr?image_id={}&left=0&top=0&width=10&height=10&save=true",
+                image_id
+            ),
+        ),
+        // no OCR runs on a read-only server
+        (
+            Method::POST,
+            format!(
+                "/api/image/ocr?image_id={}&left=0&top=0&width=10&height=10",
+                image_id
+            ),
+        ),
+    ] {
+        let (status, _, body) = harness.request(method.clone(), &uri).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{} {}", method, uri);
+        assert!(String::from_utf8_lossy(&body).contains("read-only"));
+    }
+    assert_eq!(harness.search("invoice").await.unwrap().len(), 1);
+    let (status, _, body) = harness.get("/api/reindex").await;
+    assert_eq!(status, StatusCode::OK);
+    let progress: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(progress["state"], "idle");
+    // the database itself refuses writes
+    assert!(harness.repo.delete_images(&[image_id]).await.is_err());
+    // and is never created
+    assert!(repository::connect_read_only(&format!(
+        "{}?mode=rwc",
+        root.join("missing.db").display()
+    ))
+    .await
+    .is_err());
+    assert!(!root.join("missing.db").exists());
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,53 @@
+use async_trait::async_trait;
+
+use super::{normalize, words, Embedder};
+
+/// Dimensions of the hashed vectors.
+const DIMENSIONS: usize = 256;
+
+/// Embedder without a model: words and their trigrams are hashed into the dimensions of the
+/// vector, so lines sharing words or parts of words like `exhausted` and `exhaustion` are close.
+///
+/// It does not know synonyms, a word vector model does, but needs nothing to be downloaded.
+pub struct HashingEmbedder {}
+
+impl HashingEmbedder {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    fn embed_one(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; DIMENSIONS];
+        for word in words(text) {
+            vector[bucket(&word)] += 1.0;
+            let chars: Vec<char> = format!("<{}>", word).chars().collect();
+            for trigram in chars.windows(3) {
+                vector[bucket(&trigram.iter().collect::<String>())] += 0.5;
+            }
+        }
+        normalize(&mut vector);
+        vector
+    }
+}
+
+#[async_trait]
+impl Embedder for HashingEmbedder {
+    fn model(&self) -> String {
+        format!("hashing-{}", DIMENSIONS)
+    }
+
+    async fn embed(&self, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+        Ok(texts.iter().map(|it| self.embed_one(it)).collect())
+    }
+}
+
+/// FNV-1a hash of the feature, stable across builds unlike the hasher of the standard library
+/// since stored vectors must keep their meaning.
+fn bucket(feature: &str) -> usize {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in feature.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    (hash % DIMENSIONS as u64) as usize
+}
@@ -0,0 +1,180 @@
+//! Embeddings of the lines of text seen on screen, for searching by meaning instead of by the
+//! exact words.
+//!
+//! A line is embedded once, in the image it first appears in, by an `Embedder`. The vectors are
+//! stored with the repository under the name of the model, so vectors of different models are
+//! never compared.
+
+use std::{collections::HashMap, path::PathBuf, str::FromStr, sync::Arc};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+
+use crate::repository::{EntityEmbedding, EntityImage, SearchOptions};
+
+pub mod hashing;
+pub mod word_vectors;
+
+/// Cosine similarity below which a line is not considered a match.
+pub const MIN_SIMILARITY: f32 = 0.25;
+
+#[async_trait]
+pub trait Embedder {
+    /// Name of the model, vectors are only compared with vectors of the same model.
+    fn model(&self) -> String;
+    /// Embed each text into a vector of unit length, or of zero length when nothing of the
+    /// text is known to the model.
+    async fn embed(&self, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>>;
+}
+
+/// Which embedder to build, see `Provider::build`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Provider {
+    /// No embeddings, semantic search is disabled.
+    None,
+    /// Hashed words and trigrams, see `hashing::HashingEmbedder`.
+    Hashing,
+    /// A local word vector model file, see `word_vectors::WordVectorEmbedder`.
+    WordVectors(PathBuf),
+}
+
+impl FromStr for Provider {
+    type Err = anyhow::Error;
+
+    /// Parse `none`, `hashing` or `word-vectors:<path of the model file>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Provider::None),
+            "hashing" => Ok(Provider::Hashing),
+            _ => match s.strip_prefix("word-vectors:") {
+                Some(path) if !path.is_empty() => Ok(Provider::WordVectors(path.into())),
+                _ => Err(anyhow!(
+                    "expect `none`, `hashing` or `word-vectors:<path>`, got `{}`",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl Provider {
+    /// Build the embedder, loading its model if any. `None` for `Provider::None`.
+    pub async fn build(&self) -> anyhow::Result<Option<Arc<dyn Embedder + Send + Sync>>> {
+        Ok(match self {
+            Provider::None => None,
+            Provider::Hashing => Some(Arc::new(hashing::HashingEmbedder::new())),
+            Provider::WordVectors(path) => Some(Arc::new(
+                word_vectors::WordVectorEmbedder::load(path).await?,
+            )),
+        })
+    }
+}
+
+/// Lowercased alphanumeric words of the text.
+pub fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|it| !it.is_empty())
+        .map(|it| it.to_lowercase())
+        .collect()
+}
+
+/// Scale the vector to unit length, a zero vector is left as is.
+pub fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|it| it * it).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|it| *it /= norm);
+    }
+}
+
+/// Cosine similarity of two vectors of unit length, 0 when their dimensions differ.
+pub fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// Little endian bytes of the vector, as stored by the repositories.
+pub fn encode_vector(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|it| it.to_le_bytes()).collect()
+}
+
+pub fn decode_vector(bytes: &[u8]) -> anyhow::Result<Vec<f32>> {
+    let chunks = bytes.chunks_exact(4);
+    if !chunks.remainder().is_empty() {
+        return Err(anyhow!("vector of {} bytes is truncated", bytes.len()));
+    }
+    Ok(chunks
+        .map(|it| f32::from_le_bytes([it[0], it[1], it[2], it[3]]))
+        .collect())
+}
+
+/// Comma separated text ids of a line, as stored by the repositories.
+pub fn encode_text_ids(text_ids: &[u32]) -> String {
+    text_ids
+        .iter()
+        .map(|it| it.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+pub fn decode_text_ids(text_ids: &str) -> anyhow::Result<Vec<u32>> {
+    text_ids
+        .split(',')
+        .filter(|it| !it.is_empty())
+        .map(|it| Ok(it.parse()?))
+        .collect()
+}
+
+/// An image whose lines are similar to the query.
+#[derive(Debug, Clone)]
+pub struct SemanticHit {
+    pub image: EntityImage,
+    /// Similarity of the closest line, blended with recency like the exact search, lower is
+    /// better.
+    pub score: f64,
+    /// Texts of the lines similar enough to the query.
+    pub text_ids: Vec<u32>,
+}
+
+/// Keep the lines similar enough to the query, group them by image and rank the images by
+/// their closest line, blended with recency like the exact search. Every hit is returned, the
+/// pagination of the options is left to the caller.
+pub fn rank(
+    options: &SearchOptions,
+    query: &[f32],
+    candidates: Vec<(EntityEmbedding, EntityImage)>,
+    now_epoch: u64,
+) -> Vec<SemanticHit> {
+    let mut hits: Vec<SemanticHit> = Vec::new();
+    let mut index_of_image: HashMap<u32, usize> = HashMap::new();
+    for (embedding, image) in candidates {
+        let similarity = similarity(query, &embedding.vector);
+        if similarity < MIN_SIMILARITY {
+            continue;
+        }
+        let age_days = now_epoch.saturating_sub(image.captured_at_epoch) as f64 / 86400.0;
+        let score = 1.0 - similarity as f64 + options.recency_weight * age_days;
+        match index_of_image.get(&image.id) {
+            Some(index) => {
+                let hit = &mut hits[*index];
+                hit.score = hit.score.min(score);
+                hit.text_ids.extend(embedding.text_ids);
+            }
+            None => {
+                index_of_image.insert(image.id, hits.len());
+                hits.push(SemanticHit {
+                    image,
+                    score,
+                    text_ids: embedding.text_ids,
+                });
+            }
+        }
+    }
+    hits.sort_by(|a, b| {
+        a.score
+            .total_cmp(&b.score)
+            .then(a.image.id.cmp(&b.image.id))
+    });
+    hits
+}
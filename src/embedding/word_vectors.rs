@@ -0,0 +1,91 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+
+use super::{normalize, words, Embedder};
+
+/// Embedder averaging the vectors of the words of a text, read from a local model file in the
+/// text format of word2vec, GloVe and fastText: a line per word, the word followed by its
+/// vector. An optional first line holds the number of words and the dimensions.
+///
+/// The model is held in memory and runs on the CPU, words unknown to it are skipped.
+pub struct WordVectorEmbedder {
+    name: String,
+    dimensions: usize,
+    vectors: HashMap<String, Vec<f32>>,
+}
+
+impl WordVectorEmbedder {
+    /// Read the model file, off the async runtime as large models take a while.
+    pub async fn load(path: &Path) -> anyhow::Result<Self> {
+        let name = path
+            .file_name()
+            .and_then(|it| it.to_str())
+            .ok_or_else(|| anyhow!("invalid word vector model path {}", path.display()))?
+            .to_string();
+        let content = tokio::fs::read_to_string(path).await?;
+        tokio::task::spawn_blocking(move || Self::parse(name, &content)).await?
+    }
+
+    pub fn parse(name: String, content: &str) -> anyhow::Result<Self> {
+        let mut dimensions = 0;
+        let mut vectors = HashMap::new();
+        for (index, line) in content.lines().enumerate() {
+            let mut fields = line.split_whitespace();
+            let Some(word) = fields.next() else {
+                continue;
+            };
+            let values = fields.map(str::parse).collect::<Result<Vec<f32>, _>>()?;
+            // the header of word2vec and fastText models has a single number after the count
+            if index == 0 && values.len() == 1 {
+                continue;
+            }
+            if dimensions == 0 {
+                dimensions = values.len();
+            }
+            if values.len() != dimensions || dimensions == 0 {
+                return Err(anyhow!(
+                    "line {} of the word vector model has {} dimensions instead of {}",
+                    index + 1,
+                    values.len(),
+                    dimensions
+                ));
+            }
+            vectors.insert(word.to_lowercase(), values);
+        }
+        if vectors.is_empty() {
+            return Err(anyhow!("word vector model {} has no word", name));
+        }
+        Ok(Self {
+            name,
+            dimensions,
+            vectors,
+        })
+    }
+
+    fn embed_one(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; self.dimensions];
+        for word in words(text) {
+            if let Some(known) = self.vectors.get(&word) {
+                vector
+                    .iter_mut()
+                    .zip(known)
+                    .for_each(|(sum, it)| *sum += it);
+            }
+        }
+        normalize(&mut vector);
+        vector
+    }
+}
+
+#[async_trait]
+impl Embedder for WordVectorEmbedder {
+    fn model(&self) -> String {
+        format!("word-vectors-{}-{}", self.name, self.dimensions)
+    }
+
+    async fn embed(&self, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+        Ok(texts.iter().map(|it| self.embed_one(it)).collect())
+    }
+}
@@ -0,0 +1,455 @@
+//! Export of frames, those of a time range or the matches of a search, as JSON lines, as a
+//! Markdown transcript, or as a ZIP bundle of both along with the images, so findings can be
+//! attached to reports.
+
+use std::{
+    io::{Seek, Write},
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Result};
+use image::ImageOutputFormat;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    image_archive::{ImageArchive, ImageArchiver},
+    repository::{
+        EntityText, Repository, SearchOptions, SearchResult, TimelineCursor, TimelineOptions,
+    },
+    snippet,
+};
+
+/// Upper bound of the frames of an export.
+pub const MAX_EXPORT_FRAMES: u32 = 10_000;
+/// Images or search results fetched from the repository per page.
+const PAGE_SIZE: u32 = 500;
+/// Frames whose texts are fetched at once.
+const TEXTS_BATCH: usize = 100;
+/// Format of the times of a transcript.
+pub const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S UTC";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// A JSON object per frame, with its texts and their boxes.
+    Jsonl,
+    /// A transcript of the frames in capture order.
+    Markdown,
+    /// The images, the JSON lines and the transcript in a ZIP archive.
+    Zip,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Jsonl => "application/x-ndjson",
+            ExportFormat::Markdown => "text/markdown; charset=utf-8",
+            ExportFormat::Zip => "application/zip",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Markdown => "md",
+            ExportFormat::Zip => "zip",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "jsonl" => Ok(ExportFormat::Jsonl),
+            "markdown" => Ok(ExportFormat::Markdown),
+            "zip" => Ok(ExportFormat::Zip),
+            _ => Err(anyhow!(
+                "unknown export format `{}`, expect `jsonl`, `markdown` or `zip`",
+                s
+            )),
+        }
+    }
+}
+
+/// The frames captured in `[from_epoch, to_epoch]` from the screen, only those matching the
+/// search when there is one.
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub text: Option<String>,
+    /// Tolerate typos and OCR errors in the search.
+    pub fuzzy: bool,
+    pub from_epoch: Option<u64>,
+    pub to_epoch: Option<u64>,
+    pub screen_id: Option<u32>,
+    /// Number of exported frames, the best matches of a search or the first frames of a range
+    /// are kept.
+    pub limit: u32,
+}
+
+/// An exported frame, a line of the JSON lines.
+#[derive(Debug, Serialize)]
+pub struct ExportedFrame {
+    pub image_id: u32,
+    pub captured_at_epoch: u64,
+    /// Capture time in UTC, as RFC 3339.
+    pub captured_at: String,
+    pub screen_id: Option<u32>,
+    /// Path of the image in a ZIP bundle, absent from the other formats.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// The texts matching the search, empty when exporting a range.
+    pub matched_text_ids: Vec<u32>,
+    /// Every text seen in the frame, in reading order.
+    pub texts: Vec<EntityText>,
+}
+
+/// Where an export is written.
+enum Output<W: Write + Seek> {
+    /// JSON lines or a transcript, written frame by frame.
+    Plain(W),
+    /// A ZIP bundle, the images are added frame by frame while the JSON lines and the transcript
+    /// are added last.
+    Bundle {
+        zip: Box<ZipWriter<W>>,
+        jsonl: Vec<u8>,
+        transcript: String,
+    },
+}
+
+pub struct Exporter {
+    repo: Arc<dyn Repository + Send + Sync>,
+    archiver: Arc<dyn ImageArchiver + Send + Sync>,
+}
+
+impl Exporter {
+    pub fn new(
+        repo: Arc<dyn Repository + Send + Sync>,
+        archiver: Arc<dyn ImageArchiver + Send + Sync>,
+    ) -> Self {
+        Self { repo, archiver }
+    }
+
+    /// Write the frames selected by the options in capture order, returns the number of
+    /// exported frames.
+    pub async fn export<W: Write + Seek + Send>(
+        &self,
+        options: &ExportOptions,
+        format: ExportFormat,
+        writer: W,
+    ) -> Result<u64> {
+        let frames = self.select(options).await?;
+        let header = transcript_header(options, frames.len());
+        let mut output = match format {
+            ExportFormat::Jsonl => Output::Plain(writer),
+            ExportFormat::Markdown => {
+                let mut writer = writer;
+                writer.write_all(header.as_bytes())?;
+                Output::Plain(writer)
+            }
+            ExportFormat::Zip => Output::Bundle {
+                zip: Box::new(ZipWriter::new(writer)),
+                jsonl: Vec::new(),
+                transcript: header,
+            },
+        };
+
+        for chunk in frames.chunks(TEXTS_BATCH) {
+            let image_ids: Vec<u32> = chunk.iter().map(|it| it.image_id).collect();
+            let texts = self.repo.get_texts_by_image_ids(&image_ids).await?;
+            for frame in chunk {
+                let image = match &mut output {
+                    Output::Bundle { zip, .. } => self.bundle_image(zip, frame.image_id).await?,
+                    Output::Plain(_) => None,
+                };
+                let exported = ExportedFrame {
+                    image_id: frame.image_id,
+                    captured_at_epoch: frame.captured_at_epoch,
+                    captured_at: format_epoch(frame.captured_at_epoch, "%Y-%m-%dT%H:%M:%SZ"),
+                    screen_id: frame.screen_id,
+                    image,
+                    matched_text_ids: frame.texts.iter().map(|it| it.id).collect(),
+                    texts: texts
+                        .iter()
+                        .filter(|it| it.image_id == frame.image_id)
+                        .cloned()
+                        .collect(),
+                };
+                match &mut output {
+                    Output::Plain(writer) if format == ExportFormat::Jsonl => {
+                        write_json_line(writer, &exported)?
+                    }
+                    Output::Plain(writer) => {
+                        writer.write_all(transcript_section(&exported).as_bytes())?
+                    }
+                    Output::Bundle {
+                        jsonl, transcript, ..
+                    } => {
+                        write_json_line(jsonl, &exported)?;
+                        transcript.push_str(&transcript_section(&exported));
+                    }
+                }
+            }
+        }
+
+        match output {
+            Output::Plain(mut writer) => writer.flush()?,
+            Output::Bundle {
+                mut zip,
+                jsonl,
+                transcript,
+            } => {
+                let options =
+                    FileOptions::default().compression_method(CompressionMethod::Deflated);
+                zip.start_file("frames.jsonl", options)?;
+                zip.write_all(&jsonl)?;
+                zip.start_file("transcript.md", options)?;
+                zip.write_all(transcript.as_bytes())?;
+                zip.finish()?.flush()?;
+            }
+        }
+        Ok(frames.len() as u64)
+    }
+
+    /// The selected frames in capture order, with the texts matching the search.
+    async fn select(&self, options: &ExportOptions) -> Result<Vec<SearchResult>> {
+        let limit = options.limit as usize;
+        let mut frames = Vec::new();
+        match options.text.as_deref().filter(|it| !it.trim().is_empty()) {
+            Some(text) => {
+                let mut search = SearchOptions {
+                    from_epoch: options.from_epoch,
+                    to_epoch: options.to_epoch,
+                    screen_id: options.screen_id,
+                    fuzzy: options.fuzzy,
+                    ..SearchOptions::new(text.to_string(), PAGE_SIZE, 0, 0.0)
+                };
+                // results are ranked, the best matches are kept
+                loop {
+                    let results = self.repo.search(&search).await?.results;
+                    let count = results.len();
+                    frames.extend(results);
+                    if count < PAGE_SIZE as usize || frames.len() >= limit {
+                        break;
+                    }
+                    search.offset += PAGE_SIZE;
+                }
+            }
+            None => {
+                let mut timeline = TimelineOptions {
+                    from_epoch: options.from_epoch,
+                    to_epoch: options.to_epoch,
+                    screen_id: options.screen_id,
+                    ..TimelineOptions::new(PAGE_SIZE)
+                };
+                loop {
+                    let images = self.repo.list_images(&timeline).await?;
+                    let Some(last) = images.last() else {
+                        break;
+                    };
+                    timeline.after = Some(TimelineCursor::of(last));
+                    let count = images.len();
+                    frames.extend(images.into_iter().map(|it| {
+                        SearchResult::new(it.id, it.captured_at_epoch, it.screen_id, 0.0, vec![])
+                    }));
+                    if count < PAGE_SIZE as usize || frames.len() >= limit {
+                        break;
+                    }
+                }
+            }
+        }
+        frames.truncate(limit);
+        frames.sort_by_key(|it| (it.captured_at_epoch, it.image_id));
+        Ok(frames)
+    }
+
+    /// Add the image to the bundle, returns its path in the bundle. An image whose archive
+    /// cannot be loaded is left out, the export goes on without it.
+    async fn bundle_image<W: Write + Seek>(
+        &self,
+        zip: &mut ZipWriter<W>,
+        image_id: u32,
+    ) -> Result<Option<String>> {
+        let entity_image = self.repo.get_image_by_id(image_id).await?;
+        let archive = ImageArchive::new(entity_image.archive_type, entity_image.archive_info);
+        let image = match self.archiver.load(&archive).await {
+            Ok(image) => image,
+            Err(err) => {
+                warn!("failed to load image {} for export: {}", image_id, err);
+                return Ok(None);
+            }
+        };
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut bytes, ImageOutputFormat::Jpeg(90))?;
+        let path = format!("images/{}.jpg", image_id);
+        // jpeg is compressed already
+        zip.start_file(
+            path.as_str(),
+            FileOptions::default().compression_method(CompressionMethod::Stored),
+        )?;
+        zip.write_all(bytes.get_ref())?;
+        Ok(Some(path))
+    }
+}
+
+fn write_json_line<W: Write>(writer: &mut W, frame: &ExportedFrame) -> Result<()> {
+    serde_json::to_writer(&mut *writer, frame)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+pub fn format_epoch(epoch: u64, format: &str) -> String {
+    chrono::DateTime::from_timestamp(epoch as i64, 0)
+        .map(|it| it.format(format).to_string())
+        .unwrap_or_default()
+}
+
+fn transcript_header(options: &ExportOptions, frames: usize) -> String {
+    let mut header = String::from("# Dejavu export\n\n");
+    if let Some(text) = options.text.as_deref().filter(|it| !it.trim().is_empty()) {
+        header.push_str(&format!("- Search: {}\n", escape_markdown(text)));
+    }
+    if let Some(from_epoch) = options.from_epoch {
+        header.push_str(&format!(
+            "- From: {}\n",
+            format_epoch(from_epoch, TIME_FORMAT)
+        ));
+    }
+    if let Some(to_epoch) = options.to_epoch {
+        header.push_str(&format!("- To: {}\n", format_epoch(to_epoch, TIME_FORMAT)));
+    }
+    if let Some(screen_id) = options.screen_id {
+        header.push_str(&format!("- Screen: {}\n", screen_id));
+    }
+    header.push_str(&format!("- Frames: {}\n", frames));
+    header
+}
+
+/// The frame in a transcript, its lines of text in reading order with the matching texts in
+/// bold.
+fn transcript_section(frame: &ExportedFrame) -> String {
+    let mut section = format!(
+        "\n## {}",
+        format_epoch(frame.captured_at_epoch, TIME_FORMAT)
+    );
+    if let Some(screen_id) = frame.screen_id {
+        section.push_str(&format!(", screen {}", screen_id));
+    }
+    section.push_str(&format!(" (frame {})\n\n", frame.image_id));
+    if let Some(image) = &frame.image {
+        section.push_str(&format!("![frame {}]({})\n\n", frame.image_id, image));
+    }
+    for line in snippet::lines(&frame.texts) {
+        let words: Vec<String> = line
+            .iter()
+            .map(|it| {
+                let text = escape_markdown(&it.text);
+                if frame.matched_text_ids.contains(&it.id) {
+                    format!("**{}**", text)
+                } else {
+                    text
+                }
+            })
+            .collect();
+        // a hard line break keeps the lines apart
+        section.push_str(&format!("{}  \n", words.join(" ")));
+    }
+    section
+}
+
+/// Escape the characters Markdown would take for formatting.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '|' | '!'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Arguments of the `export` command.
+#[derive(Debug, clap::Args)]
+pub struct ExportArgs {
+    /// Format of the export: `jsonl`, `markdown` or `zip`
+    #[arg(long, default_value = "jsonl")]
+    format: ExportFormat,
+    /// Only export the frames matching this search
+    #[arg(long)]
+    text: Option<String>,
+    /// Tolerate typos and OCR errors in the search
+    #[arg(long)]
+    fuzzy: bool,
+    /// Start of the range, as an epoch, an RFC 3339 time or a date in UTC
+    #[arg(long, value_parser = parse_time)]
+    from: Option<u64>,
+    /// End of the range, as an epoch, an RFC 3339 time or a date in UTC
+    #[arg(long, value_parser = parse_time)]
+    to: Option<u64>,
+    /// Only export the frames of this screen
+    #[arg(long)]
+    screen_id: Option<u32>,
+    /// Number of exported frames
+    #[arg(long, default_value_t = MAX_EXPORT_FRAMES)]
+    limit: u32,
+    /// File to write, the standard output when absent
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+/// Run the `export` command.
+pub async fn run(
+    args: ExportArgs,
+    repo: Arc<dyn Repository + Send + Sync>,
+    archiver: Arc<dyn ImageArchiver + Send + Sync>,
+) -> Result<()> {
+    let options = ExportOptions {
+        text: args.text,
+        fuzzy: args.fuzzy,
+        from_epoch: args.from,
+        to_epoch: args.to,
+        screen_id: args.screen_id,
+        limit: args.limit.min(MAX_EXPORT_FRAMES),
+    };
+    let exporter = Exporter::new(repo, archiver);
+    match args.output {
+        Some(path) => {
+            let file = std::fs::File::create(&path)?;
+            let count = exporter.export(&options, args.format, file).await?;
+            eprintln!("exported {} frames to {}", count, path.display());
+        }
+        None => {
+            // a ZIP archive is written with seeks, the export is buffered
+            let mut buffer = std::io::Cursor::new(Vec::new());
+            exporter.export(&options, args.format, &mut buffer).await?;
+            std::io::stdout().write_all(buffer.get_ref())?;
+        }
+    }
+    Ok(())
+}
+
+/// Parse an epoch, an RFC 3339 time, or a date standing for its midnight in UTC.
+pub fn parse_time(s: &str) -> Result<u64> {
+    if let Ok(epoch) = s.parse::<u64>() {
+        return Ok(epoch);
+    }
+    let time = match chrono::DateTime::parse_from_rfc3339(s) {
+        Ok(time) => time.timestamp(),
+        Err(_) => chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .map_err(|_| anyhow!("expect an epoch, an RFC 3339 time or a date, got `{}`", s))?
+            .and_hms_opt(0, 0, 0)
+            .expect("midnight is a valid time")
+            .and_utc()
+            .timestamp(),
+    };
+    u64::try_from(time).map_err(|_| anyhow!("time before the epoch: `{}`", s))
+}
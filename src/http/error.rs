@@ -3,13 +3,37 @@ use axum::{
     response::{IntoResponse, Response},
 };
 
-pub struct HttpError(anyhow::Error);
+pub struct HttpError {
+    status: StatusCode,
+    error: anyhow::Error,
+}
+
+impl HttpError {
+    /// A request the server refuses to carry out, answered with its reason.
+    pub fn forbidden(reason: &str) -> Self {
+        Self {
+            status: StatusCode::FORBIDDEN,
+            error: anyhow::anyhow!("{}", reason),
+        }
+    }
+
+    /// A request which is invalid, answered with what is wrong with it.
+    pub fn bad_request(reason: &str) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            error: anyhow::anyhow!("{}", reason),
+        }
+    }
+}
 
 impl IntoResponse for HttpError {
     fn into_response(self) -> Response {
+        if self.status != StatusCode::INTERNAL_SERVER_ERROR {
+            return (self.status, self.error.to_string()).into_response();
+        }
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Something went wrong: {}", self.0),
+            format!("Something went wrong: {}", self.error),
         )
             .into_response()
     }
@@ -20,6 +44,9 @@ where
     E: Into<anyhow::Error>,
 {
     fn from(err: E) -> Self {
-        Self(err.into())
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            error: err.into(),
+        }
     }
 }
@@ -1,29 +1,311 @@
 use self::{error::HttpError, service::Service};
-use crate::analysis::SearchResult;
-use axum::{extract::Query, http::header, response::IntoResponse, Extension, Json};
+use crate::{
+    config::LoadedConfig,
+    export::{ExportFormat, ExportOptions, MAX_EXPORT_FRAMES},
+    ocr::MarkupBox,
+    reindex::{ReindexOptions, ReindexProgress},
+    stats::Stats,
+    repository::{
+        EntityImage, EntitySession, EntityText, SearchMode, SearchOptions, TimelineCursor,
+        TimelineDirection, TimelineOptions,
+    },
+};
+use axum::{
+    extract::Query, http::header, response::IntoResponse, routing::{get, post}, Extension, Json, Router,
+};
 use image::{ImageOutputFormat};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 pub mod error;
 pub mod service;
 pub mod frontend;
+
+/// Routes of the api and the embedded frontend, sharing the given service.
+pub fn router(service: Arc<Service>) -> Router {
+    let api_router = Router::new()
+        .route("/search", get(search).delete(delete_search_matches))
+        .route("/timeline", get(timeline).delete(delete_time_range))
+        .route("/timeline/adjacent", get(adjacent_frame))
+        .route("/timeline/nearest", get(nearest_frame))
+        .route("/sessions", get(sessions))
+        .route("/stats", get(stats))
+        .route("/export", get(export))
+        .route("/config", get(config))
+        .route(
+            "/image",
+            get(fetch_image_with_markup).delete(delete_frame),
+        )
+        .route("/image/ocr", post(reocr_region))
+        .route(
+            "/reindex",
+            get(reindex_progress)
+                .post(start_reindex)
+                .delete(cancel_reindex),
+        );
+
+    Router::new()
+        .nest("/api", api_router)
+        .fallback(frontend::static_handler)
+        .layer(Extension(service))
+}
+
+/// Upper bound of the page size of a search.
+const MAX_SEARCH_LIMIT: u32 = 200;
+
+fn default_search_limit() -> u32 {
+    20
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct SearchQuery {
     text: String,
+    /// number of images per page, at most `MAX_SEARCH_LIMIT`
+    #[serde(default = "default_search_limit")]
+    limit: u32,
+    #[serde(default)]
+    offset: u32,
+    /// penalty per day of age blended into the relevance, 0 ranks by relevance only
+    #[serde(default)]
+    recency: f64,
+    /// only images captured at or after this epoch
+    from: Option<u64>,
+    /// only images captured at or before this epoch
+    to: Option<u64>,
+    /// only images captured from this screen
+    screen_id: Option<u32>,
+    /// tolerate typos and OCR errors
+    #[serde(default)]
+    fuzzy: bool,
+    /// match the words, the meaning of lines, or both
+    #[serde(default)]
+    mode: SearchMode,
+    /// only return the best image of each session of activity
+    #[serde(default)]
+    group_by_session: bool,
 }
 
+impl From<SearchQuery> for SearchOptions {
+    fn from(query: SearchQuery) -> Self {
+        SearchOptions {
+            from_epoch: query.from,
+            to_epoch: query.to,
+            screen_id: query.screen_id,
+            fuzzy: query.fuzzy,
+            mode: query.mode,
+            group_by_session: query.group_by_session,
+            ..SearchOptions::new(
+                query.text,
+                query.limit.min(MAX_SEARCH_LIMIT),
+                query.offset,
+                query.recency,
+            )
+        }
+    }
+}
+
+/// Search a page of images, the number of matching images across all pages is returned in the
+/// `X-Total-Count` header.
 pub async fn search(
     Extension(service): Extension<Arc<Service>>,
     Query(query): Query<SearchQuery>,
-) -> Result<Json<Vec<SearchResult>>, HttpError> {
-    let result = service.clone().search(&query.text).await?;
-    Ok(Json(result))
+) -> Result<impl IntoResponse, HttpError> {
+    let page = service.clone().search(&query.into()).await?;
+    Ok((
+        axum::response::AppendHeaders([("x-total-count", page.total.to_string())]),
+        Json(page.results),
+    ))
+}
+
+/// Outcome of a deletion.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeletionResult {
+    /// number of deleted images
+    deleted: u64,
+}
+
+/// Delete every image matching the search, along with its texts and archive.
+pub async fn delete_search_matches(
+    Extension(service): Extension<Arc<Service>>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<DeletionResult>, HttpError> {
+    let deleted = service.delete_matching(&query.into()).await?;
+    Ok(Json(DeletionResult { deleted }))
+}
+
+/// Upper bound of the page size of the timeline.
+const MAX_TIMELINE_LIMIT: u32 = 1000;
+
+fn default_timeline_limit() -> u32 {
+    100
+}
+
+/// A captured frame in the timeline, its image is served by `/api/image`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Frame {
+    image_id: u32,
+    captured_at_epoch: u64,
+    screen_id: Option<u32>,
+}
+
+impl From<EntityImage> for Frame {
+    fn from(image: EntityImage) -> Self {
+        Self {
+            image_id: image.id,
+            captured_at_epoch: image.captured_at_epoch,
+            screen_id: image.screen_id,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TimelineQuery {
+    /// only frames captured at or after this epoch
+    from: Option<u64>,
+    /// only frames captured at or before this epoch
+    to: Option<u64>,
+    /// only frames captured from this screen
+    screen_id: Option<u32>,
+    /// `next_cursor` of the previous page
+    cursor: Option<String>,
+    /// number of frames per page, at most `MAX_TIMELINE_LIMIT`
+    #[serde(default = "default_timeline_limit")]
+    limit: u32,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TimelinePage {
+    frames: Vec<Frame>,
+    /// cursor of the next page, absent on the last page
+    next_cursor: Option<String>,
+}
+
+/// The cursor of the page after which to list, an invalid cursor is a bad request.
+fn parse_cursor(cursor: Option<&str>) -> Result<Option<TimelineCursor>, HttpError> {
+    cursor
+        .map(|it| {
+            it.parse()
+                .map_err(|_| HttpError::bad_request(&format!("invalid cursor `{}`", it)))
+        })
+        .transpose()
+}
+
+/// List frames in capture order, one page at a time.
+pub async fn timeline(
+    Extension(service): Extension<Arc<Service>>,
+    Query(query): Query<TimelineQuery>,
+) -> Result<Json<TimelinePage>, HttpError> {
+    let options = TimelineOptions {
+        from_epoch: query.from,
+        to_epoch: query.to,
+        screen_id: query.screen_id,
+        after: parse_cursor(query.cursor.as_deref())?,
+        ..TimelineOptions::new(query.limit.clamp(1, MAX_TIMELINE_LIMIT))
+    };
+    let images = service.list_images(&options).await?;
+    let next_cursor = match images.last() {
+        Some(last) if images.len() == options.limit as usize => {
+            Some(TimelineCursor::of(last).to_string())
+        }
+        _ => None,
+    };
+    Ok(Json(TimelinePage {
+        frames: images.into_iter().map(Frame::from).collect(),
+        next_cursor,
+    }))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SessionPage {
+    sessions: Vec<EntitySession>,
+    /// cursor of the next page, absent on the last page
+    next_cursor: Option<String>,
+}
+
+/// List the sessions of activity overlapping the time range in start order, one page at a time.
+pub async fn sessions(
+    Extension(service): Extension<Arc<Service>>,
+    Query(query): Query<TimelineQuery>,
+) -> Result<Json<SessionPage>, HttpError> {
+    let options = TimelineOptions {
+        from_epoch: query.from,
+        to_epoch: query.to,
+        screen_id: query.screen_id,
+        after: parse_cursor(query.cursor.as_deref())?,
+        ..TimelineOptions::new(query.limit.clamp(1, MAX_TIMELINE_LIMIT))
+    };
+    let sessions = service.list_sessions(&options).await?;
+    let next_cursor = match sessions.last() {
+        Some(last) if sessions.len() == options.limit as usize => {
+            Some(last.cursor().to_string())
+        }
+        _ => None,
+    };
+    Ok(Json(SessionPage {
+        sessions,
+        next_cursor,
+    }))
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TimeRangeQuery {
+    /// first capture epoch of the range
+    from: u64,
+    /// last capture epoch of the range
+    to: u64,
+}
+
+/// Delete the images captured in the time range, along with their texts and archives.
+pub async fn delete_time_range(
+    Extension(service): Extension<Arc<Service>>,
+    Query(query): Query<TimeRangeQuery>,
+) -> Result<Json<DeletionResult>, HttpError> {
+    let deleted = service
+        .delete_captured_between(query.from, query.to)
+        .await?;
+    Ok(Json(DeletionResult { deleted }))
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct AdjacentFrameQuery {
+    image_id: u32,
+    direction: TimelineDirection,
+    /// only step through the frames of this screen
+    screen_id: Option<u32>,
+}
+
+/// The frame right before or after the given one, `null` at either end of the timeline.
+pub async fn adjacent_frame(
+    Extension(service): Extension<Arc<Service>>,
+    Query(query): Query<AdjacentFrameQuery>,
+) -> Result<Json<Option<Frame>>, HttpError> {
+    let image = service
+        .get_adjacent_image(query.image_id, query.direction, query.screen_id)
+        .await?;
+    Ok(Json(image.map(Frame::from)))
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct NearestFrameQuery {
+    /// epoch to look around
+    at: u64,
+    /// only frames captured from this screen
+    screen_id: Option<u32>,
+}
+
+/// The frame captured closest to the given time, `null` when nothing was captured.
+pub async fn nearest_frame(
+    Extension(service): Extension<Arc<Service>>,
+    Query(query): Query<NearestFrameQuery>,
+) -> Result<Json<Option<Frame>>, HttpError> {
+    let image = service.get_nearest_image(query.at, query.screen_id).await?;
+    Ok(Json(image.map(Frame::from)))
 }
 
 #[derive(Deserialize, Serialize)]
 pub struct ImageWithMarkupQuery {
     image_id: u32,
-    /// comma separated list of text ids
+    /// comma separated list of text ids, empty for the frame without markup
+    #[serde(default)]
     text_ids: String,
 }
 
@@ -34,8 +316,12 @@ pub async fn fetch_image_with_markup(
     let text_ids = query
         .text_ids
         .split(',')
-        .map(|id| id.parse::<u32>().unwrap())
-        .collect::<Vec<u32>>();
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            id.parse::<u32>()
+                .map_err(|_| HttpError::bad_request(&format!("invalid text id `{}`", id)))
+        })
+        .collect::<Result<Vec<u32>, HttpError>>()?;
     let marked = service
         .clone()
         .fetch_image_with_markup(query.image_id, &text_ids)
@@ -49,3 +335,133 @@ pub async fn fetch_image_with_markup(
         bytes,
     ))
 }
+
+#[derive(Deserialize, Serialize)]
+pub struct FrameQuery {
+    image_id: u32,
+}
+
+/// Delete an image along with its texts and archive.
+pub async fn delete_frame(
+    Extension(service): Extension<Arc<Service>>,
+    Query(query): Query<FrameQuery>,
+) -> Result<Json<DeletionResult>, HttpError> {
+    let deleted = service.delete_images(&[query.image_id]).await?;
+    Ok(Json(DeletionResult { deleted }))
+}
+
+fn default_reocr_scale() -> u32 {
+    3
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ReocrRegionQuery {
+    image_id: u32,
+    left: u32,
+    top: u32,
+    width: u32,
+    height: u32,
+    /// upscaling factor applied to the region before recognition
+    #[serde(default = "default_reocr_scale")]
+    scale: u32,
+    /// replace the stored texts inside the region with the new result
+    #[serde(default)]
+    save: bool,
+}
+
+pub async fn reocr_region(
+    Extension(service): Extension<Arc<Service>>,
+    Query(query): Query<ReocrRegionQuery>,
+) -> Result<Json<Vec<EntityText>>, HttpError> {
+    let region = MarkupBox::new(query.left, query.top, query.width, query.height);
+    let result = service
+        .reocr_region(query.image_id, &region, query.scale, query.save)
+        .await?;
+    Ok(Json(result))
+}
+
+fn default_export_limit() -> u32 {
+    MAX_EXPORT_FRAMES
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ExportQuery {
+    format: ExportFormat,
+    /// only frames matching this search, every frame of the range otherwise
+    text: Option<String>,
+    /// tolerate typos and OCR errors in the search
+    #[serde(default)]
+    fuzzy: bool,
+    /// only frames captured at or after this epoch
+    from: Option<u64>,
+    /// only frames captured at or before this epoch
+    to: Option<u64>,
+    /// only frames captured from this screen
+    screen_id: Option<u32>,
+    /// number of exported frames, at most `MAX_EXPORT_FRAMES`
+    #[serde(default = "default_export_limit")]
+    limit: u32,
+}
+
+/// Download the frames of a time range or matching a search as JSON lines, a Markdown
+/// transcript or a ZIP bundle with the images, the number of frames is returned in the
+/// `X-Total-Count` header.
+pub async fn export(
+    Extension(service): Extension<Arc<Service>>,
+    Query(query): Query<ExportQuery>,
+) -> Result<impl IntoResponse, HttpError> {
+    let options = ExportOptions {
+        text: query.text,
+        fuzzy: query.fuzzy,
+        from_epoch: query.from,
+        to_epoch: query.to,
+        screen_id: query.screen_id,
+        limit: query.limit.min(MAX_EXPORT_FRAMES),
+    };
+    let (bytes, count) = service.export(&options, query.format).await?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, query.format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"dejavu-export.{}\"",
+                    query.format.extension()
+                ),
+            ),
+            (
+                header::HeaderName::from_static("x-total-count"),
+                count.to_string(),
+            ),
+        ],
+        bytes,
+    ))
+}
+
+/// Counts and disk usage of the archive, frames per day and screen, and the ingest throughput.
+pub async fn stats(Extension(service): Extension<Arc<Service>>) -> Result<Json<Stats>, HttpError> {
+    Ok(Json(service.stats().await?))
+}
+
+/// The effective configuration, with where each key not left to its default was set.
+pub async fn config(Extension(service): Extension<Arc<Service>>) -> Json<LoadedConfig> {
+    Json(service.config())
+}
+
+pub async fn start_reindex(
+    Extension(service): Extension<Arc<Service>>,
+    Query(options): Query<ReindexOptions>,
+) -> Result<Json<ReindexProgress>, HttpError> {
+    let progress = service.start_reindex(options).await?;
+    Ok(Json(progress))
+}
+
+pub async fn reindex_progress(
+    Extension(service): Extension<Arc<Service>>,
+) -> Json<ReindexProgress> {
+    Json(service.reindex_progress().await)
+}
+
+pub async fn cancel_reindex(Extension(service): Extension<Arc<Service>>) -> Json<ReindexProgress> {
+    Json(service.cancel_reindex().await)
+}
@@ -3,12 +3,19 @@ use std::sync::Arc;
 use image::DynamicImage;
 
 use crate::{
-    analysis::{Analysis, SearchResult},
+    analysis::{Analysis, InvalidRegion},
+    config::LoadedConfig,
+    export::{ExportFormat, ExportOptions, Exporter},
     http::error::HttpError,
     image_archive::{ImageArchive, ImageArchiver},
     markup::ImageMarkupDecorator,
     ocr::MarkupBox,
-    repository::Repository,
+    reindex::{ReindexOptions, ReindexProgress, Reindexer},
+    repository::{
+        EntityImage, EntitySession, EntityText, Repository, SearchMode, SearchOptions, SearchPage,
+        TimelineDirection, TimelineOptions,
+    },
+    stats::Stats,
 };
 
 /// Adhoc service layer for web server
@@ -17,6 +24,11 @@ pub struct Service {
     markup_decorator: Arc<ImageMarkupDecorator>,
     repo: Arc<dyn Repository + Send + Sync>,
     image_archiver: Arc<dyn ImageArchiver + Send + Sync>,
+    /// Absent on a read-only server.
+    reindexer: Option<Arc<Reindexer>>,
+    config: Arc<LoadedConfig>,
+    /// Refuse the requests writing to the repository or the archive.
+    read_only: bool,
 }
 
 impl Service {
@@ -25,17 +37,100 @@ impl Service {
         markup_decorator: Arc<ImageMarkupDecorator>,
         repo: Arc<dyn Repository + Send + Sync>,
         image_archiver: Arc<dyn ImageArchiver + Send + Sync>,
+        reindexer: Option<Arc<Reindexer>>,
+        config: Arc<LoadedConfig>,
+        read_only: bool,
     ) -> Self {
         Self {
             analysis,
             markup_decorator,
             repo,
             image_archiver,
+            reindexer,
+            config,
+            read_only,
         }
     }
 
-    pub async fn search(&self, text: &str) -> Result<Vec<SearchResult>, HttpError> {
-        let result = self.analysis.search(text).await?;
+    fn ensure_writable(&self) -> Result<(), HttpError> {
+        if self.read_only {
+            return Err(HttpError::forbidden(
+                "the server is read-only, frames cannot be changed",
+            ));
+        }
+        Ok(())
+    }
+
+    pub async fn search(&self, options: &SearchOptions) -> Result<SearchPage, HttpError> {
+        let result = self.analysis.search(options).await?;
+        Ok(result)
+    }
+
+    pub async fn list_images(
+        &self,
+        options: &TimelineOptions,
+    ) -> Result<Vec<EntityImage>, HttpError> {
+        let result = self.repo.list_images(options).await?;
+        Ok(result)
+    }
+
+    pub async fn get_adjacent_image(
+        &self,
+        image_id: u32,
+        direction: TimelineDirection,
+        screen_id: Option<u32>,
+    ) -> Result<Option<EntityImage>, HttpError> {
+        let result = self
+            .repo
+            .get_adjacent_image(image_id, direction, screen_id)
+            .await?;
+        Ok(result)
+    }
+
+    pub async fn get_nearest_image(
+        &self,
+        epoch: u64,
+        screen_id: Option<u32>,
+    ) -> Result<Option<EntityImage>, HttpError> {
+        let result = self.repo.get_nearest_image(epoch, screen_id).await?;
+        Ok(result)
+    }
+
+    pub async fn list_sessions(
+        &self,
+        options: &TimelineOptions,
+    ) -> Result<Vec<EntitySession>, HttpError> {
+        let result = self.repo.list_sessions(options).await?;
+        Ok(result)
+    }
+
+    pub async fn delete_images(&self, image_ids: &[u32]) -> Result<u64, HttpError> {
+        self.ensure_writable()?;
+        let result = self.analysis.delete_images(image_ids).await?;
+        Ok(result)
+    }
+
+    pub async fn delete_captured_between(
+        &self,
+        from_epoch: u64,
+        to_epoch: u64,
+    ) -> Result<u64, HttpError> {
+        self.ensure_writable()?;
+        let result = self
+            .analysis
+            .delete_captured_between(from_epoch, to_epoch)
+            .await?;
+        Ok(result)
+    }
+
+    pub async fn delete_matching(&self, options: &SearchOptions) -> Result<u64, HttpError> {
+        self.ensure_writable()?;
+        if options.mode != SearchMode::Keyword {
+            return Err(HttpError::bad_request(
+                "only keyword matches can be deleted",
+            ));
+        }
+        let result = self.analysis.delete_matching(options).await?;
         Ok(result)
     }
 
@@ -47,6 +142,9 @@ impl Service {
         let entity_image = self.repo.get_image_by_id(image_id).await?;
         let image_archive = ImageArchive::new(entity_image.archive_type, entity_image.archive_info);
         let loaded = self.image_archiver.load(&image_archive).await?;
+        if text_ids.is_empty() {
+            return Ok(loaded);
+        }
         let mut markups = Vec::new();
 
         for text_id in text_ids {
@@ -65,4 +163,82 @@ impl Service {
 
         Ok(marked)
     }
+
+    pub async fn reocr_region(
+        &self,
+        image_id: u32,
+        region: &MarkupBox,
+        scale: u32,
+        save: bool,
+    ) -> Result<Vec<EntityText>, HttpError> {
+        if self.read_only {
+            // a read-only server runs no OCR, not even without saving
+            return Err(HttpError::forbidden(
+                "the server is read-only, regions cannot be read again",
+            ));
+        }
+        let result = self
+            .analysis
+            .reocr_region(image_id, region, scale, save)
+            .await
+            .map_err(|e| match e.downcast_ref::<InvalidRegion>() {
+                Some(invalid) => HttpError::bad_request(&invalid.0),
+                None => e.into(),
+            })?;
+        Ok(result)
+    }
+
+    /// The selected frames in the format, along with the number of exported frames.
+    pub async fn export(
+        &self,
+        options: &ExportOptions,
+        format: ExportFormat,
+    ) -> Result<(Vec<u8>, u64), HttpError> {
+        let exporter = Exporter::new(self.repo.clone(), self.image_archiver.clone());
+        let mut buffer = std::io::Cursor::new(Vec::new());
+        let count = exporter.export(options, format, &mut buffer).await?;
+        Ok((buffer.into_inner(), count))
+    }
+
+    pub async fn stats(&self) -> Result<Stats, HttpError> {
+        let repository = self.repo.get_stats().await?;
+        let archive_bytes = self.image_archiver.disk_usage().await?;
+        Ok(Stats::new(
+            repository,
+            archive_bytes,
+            self.analysis.ingest_stats(),
+            self.analysis.ocr_cache_stats(),
+        ))
+    }
+
+    /// The effective configuration, without the credentials it may hold.
+    pub fn config(&self) -> LoadedConfig {
+        self.config.redacted()
+    }
+
+    pub async fn start_reindex(
+        &self,
+        options: ReindexOptions,
+    ) -> Result<ReindexProgress, HttpError> {
+        self.ensure_writable()?;
+        let Some(reindexer) = &self.reindexer else {
+            return Err(HttpError::forbidden("the server runs no reindexing"));
+        };
+        let progress = reindexer.clone().start(options).await?;
+        Ok(progress)
+    }
+
+    pub async fn reindex_progress(&self) -> ReindexProgress {
+        match &self.reindexer {
+            Some(reindexer) => reindexer.progress().await,
+            None => ReindexProgress::idle(),
+        }
+    }
+
+    pub async fn cancel_reindex(&self) -> ReindexProgress {
+        if let Some(reindexer) = &self.reindexer {
+            reindexer.cancel().await;
+        }
+        self.reindex_progress().await
+    }
 }
@@ -1,15 +1,48 @@
 use async_trait::async_trait;
 use std::io::Cursor;
+use tokio::sync::Mutex;
 
 use crate::screenshot::Screenshot;
 
 use super::{ImageArchive, ImageArchiver};
+
+/// Suffix of a file being written by `FileSystemImageArchiver::archive`.
+const PARTIAL_SUFFIX: &str = ".partial";
+
 pub struct FileSystemImageArchiver {
     storage_path: String,
+    /// Quality of the JPEG encoding, from 1 to 100.
+    quality: u8,
+    /// Bytes of the files in the storage directory, summed up once then kept up to date by
+    /// `archive` and `delete`. `None` until first asked for.
+    usage: Mutex<Option<u64>>,
 }
 impl FileSystemImageArchiver {
-    pub fn new(storage_path: String) -> Self {
-        Self { storage_path }
+    pub fn new(storage_path: String, quality: u8) -> Self {
+        Self {
+            storage_path,
+            quality,
+            usage: Mutex::new(None),
+        }
+    }
+
+    async fn sum_file_sizes(&self) -> anyhow::Result<u64> {
+        let mut total = 0;
+        let mut entries = tokio::fs::read_dir(&self.storage_path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry
+                .file_name()
+                .to_string_lossy()
+                .ends_with(PARTIAL_SUFFIX)
+            {
+                continue;
+            }
+            let metadata = entry.metadata().await?;
+            if metadata.is_file() {
+                total += metadata.len();
+            }
+        }
+        Ok(total)
     }
 }
 
@@ -31,12 +64,53 @@ impl ImageArchiver for FileSystemImageArchiver {
         let mut buffer = Cursor::new(Vec::new());
         screenshot
             .image
-            .write_to(&mut buffer, image::ImageOutputFormat::Jpeg(90))?;
+            .write_to(&mut buffer, image::ImageOutputFormat::Jpeg(self.quality))?;
         let buffer = buffer.into_inner();
-        tokio::fs::write(path, buffer).await?;
+        let len = buffer.len() as u64;
+        // the file is written under a partial name skipped by the first count, then renamed
+        // while the usage is held, so that the count sees it once without waiting for the write
+        let partial_path = format!("{}{}", path, PARTIAL_SUFFIX);
+        tokio::fs::write(&partial_path, buffer).await?;
+        let mut usage = self.usage.lock().await;
+        if let Err(err) = tokio::fs::rename(&partial_path, path).await {
+            let _ = tokio::fs::remove_file(&partial_path).await;
+            return Err(err.into());
+        }
+        if let Some(usage) = usage.as_mut() {
+            *usage += len;
+        }
         Ok(ImageArchive {
             archive_type: "file_system".to_string(),
             archive_detail: filename,
         })
     }
+
+    async fn delete(&self, image_archive: &ImageArchive) -> anyhow::Result<()> {
+        let path = format!("{}/{}", self.storage_path, image_archive.archive_detail);
+        let mut usage = self.usage.lock().await;
+        let len = match tokio::fs::metadata(&path).await {
+            Ok(metadata) => metadata.len(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        match tokio::fs::remove_file(path).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => {
+                if let Some(usage) = usage.as_mut() {
+                    *usage = usage.saturating_sub(len);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    async fn disk_usage(&self) -> anyhow::Result<u64> {
+        let mut usage = self.usage.lock().await;
+        if let Some(usage) = *usage {
+            return Ok(usage);
+        }
+        let total = self.sum_file_sizes().await?;
+        *usage = Some(total);
+        Ok(total)
+    }
 }
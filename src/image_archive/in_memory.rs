@@ -1,4 +1,4 @@
-#[cfg(feature = "in-memory")]
+#[cfg(any(test, feature = "in-memory"))]
 use {
     super::{ImageArchive, ImageArchiver},
     crate::screenshot::Screenshot,
@@ -8,13 +8,13 @@ use {
     uuid::Uuid,
 };
 
-#[cfg(feature = "in-memory")]
+#[cfg(any(test, feature = "in-memory"))]
 pub struct InMemoryImageArchiver {
     // storage: HashMap<UUID, image::RgbImage>,
     pub storage: Mutex<HashMap<String, image::DynamicImage>>,
 }
 
-#[cfg(feature = "in-memory")]
+#[cfg(any(test, feature = "in-memory"))]
 impl InMemoryImageArchiver {
     pub fn new() -> Self {
         Self {
@@ -22,7 +22,7 @@ impl InMemoryImageArchiver {
         }
     }
 }
-#[cfg(feature = "in-memory")]
+#[cfg(any(test, feature = "in-memory"))]
 #[async_trait]
 impl ImageArchiver for InMemoryImageArchiver {
     async fn load(&self, image_archive: &ImageArchive) -> anyhow::Result<image::DynamicImage> {
@@ -43,4 +43,18 @@ impl ImageArchiver for InMemoryImageArchiver {
             archive_detail: uuid,
         })
     }
+
+    async fn delete(&self, image_archive: &ImageArchive) -> anyhow::Result<()> {
+        self.storage
+            .lock()
+            .await
+            .remove(&image_archive.archive_detail);
+        Ok(())
+    }
+
+    /// Bytes of the decoded images.
+    async fn disk_usage(&self) -> anyhow::Result<u64> {
+        let storage = self.storage.lock().await;
+        Ok(storage.values().map(|it| it.as_bytes().len() as u64).sum())
+    }
 }
@@ -18,4 +18,8 @@ impl ImageArchive {
 pub trait ImageArchiver {
     async fn load(&self, image_archive: &ImageArchive) -> anyhow::Result<image::DynamicImage>;
     async fn archive(&self, screenshot: &Screenshot) -> anyhow::Result<ImageArchive>;
+    /// Remove the archived image, an image already gone is not an error.
+    async fn delete(&self, image_archive: &ImageArchive) -> anyhow::Result<()>;
+    /// Bytes taken by the archived images.
+    async fn disk_usage(&self) -> anyhow::Result<u64>;
 }
@@ -0,0 +1,64 @@
+//! Periodic maintenance of the database, keeping the full text indexes compact and the free
+//! space of deleted records given back.
+
+use std::{sync::Arc, time::Duration};
+
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::repository::Repository;
+
+#[derive(Debug, Clone)]
+pub struct MaintenanceOptions {
+    /// Pause between two runs, the first run waits for a full pause after the start.
+    pub interval_secs: u64,
+}
+
+impl Default for MaintenanceOptions {
+    fn default() -> Self {
+        Self {
+            interval_secs: 6 * 3600,
+        }
+    }
+}
+
+/// Background job running `Repository::maintain`.
+pub struct Maintainer {
+    repo: Arc<dyn Repository + Send + Sync>,
+    options: MaintenanceOptions,
+}
+
+impl Maintainer {
+    pub fn new(repo: Arc<dyn Repository + Send + Sync>, options: MaintenanceOptions) -> Self {
+        Self { repo, options }
+    }
+
+    /// Maintain the database until cancelled, a failed run is retried with the next one.
+    pub async fn run(&self, token: CancellationToken) {
+        let period = Duration::from_secs(self.options.interval_secs);
+        // the start is busy enough without compacting the indexes
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => {
+                    info!("shutting down database maintenance");
+                    break;
+                },
+                _ = interval.tick() => self.maintain().await,
+            }
+        }
+    }
+
+    async fn maintain(&self) {
+        let started_at = std::time::Instant::now();
+        match self.repo.maintain().await {
+            Ok(report) => info!(
+                "maintained the database in {} ms, reclaimed {} bytes, {} bytes left",
+                started_at.elapsed().as_millis(),
+                report.reclaimed_bytes,
+                report.database_bytes
+            ),
+            Err(e) => warn!("failed to maintain the database: {}", e),
+        }
+    }
+}
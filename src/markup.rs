@@ -1,20 +1,8 @@
 use crate::ocr::MarkupBox;
 use colorsys::{Hsl, Rgb};
-use image::{DynamicImage, GenericImage};
+use image::DynamicImage;
 use imageproc::rect::Rect;
 
-fn safe_put_pixel(
-    origin_image: &mut DynamicImage,
-    x: u32,
-    y: u32,
-    color: image::Rgba<u8>,
-) -> anyhow::Result<()> {
-    if x < origin_image.width() && y < origin_image.height() {
-        origin_image.put_pixel(x, y, color);
-    }
-    Ok(())
-}
-
 pub struct ImageMarkupDecorator {}
 
 impl ImageMarkupDecorator {
@@ -58,15 +46,15 @@ impl ImageMarkupDecorator {
     ) -> anyhow::Result<()> {
         // top
         let rect_top = Rect::at(
-            markup.left as i32 - border as i32,
-            markup.top as i32 - border as i32,
+            markup.left as i32 - border,
+            markup.top as i32 - border,
         )
         .of_size(markup.width + border as u32 * 2, border as u32);
         imageproc::drawing::draw_filled_rect_mut(origin_image, rect_top, color);
 
         // bottom
         let rect_bottom = Rect::at(
-            markup.left as i32 - border as i32,
+            markup.left as i32 - border,
             markup.top as i32 + markup.height as i32,
         )
         .of_size(markup.width + border as u32 * 2, border as u32);
@@ -74,8 +62,8 @@ impl ImageMarkupDecorator {
 
         // left
         let rect_left = Rect::at(
-            markup.left as i32 - border as i32,
-            markup.top as i32 - border as i32,
+            markup.left as i32 - border,
+            markup.top as i32 - border,
         )
         .of_size(border as u32, markup.height + border as u32 * 2);
         imageproc::drawing::draw_filled_rect_mut(origin_image, rect_left, color);
@@ -83,7 +71,7 @@ impl ImageMarkupDecorator {
         // right
         let rect_right = Rect::at(
             markup.left as i32 + markup.width as i32,
-            markup.top as i32 - border as i32,
+            markup.top as i32 - border,
         )
         .of_size(border as u32, markup.height + border as u32 * 2);
         imageproc::drawing::draw_filled_rect_mut(origin_image, rect_right, color);
@@ -0,0 +1,272 @@
+use std::num::NonZeroUsize;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use image::DynamicImage;
+use lru::LruCache;
+use serde::Serialize;
+use tracing::debug;
+
+use super::{content_hash, CharacterRecognizer, MarkupBox, RecognizeItem};
+
+/// Which part of the frame a cache entry is keyed by.
+#[derive(Debug, Clone, Copy)]
+pub enum CacheGranularity {
+    /// The whole frame, only a completely unchanged screen hits the cache.
+    Frame,
+    /// A grid of tiles recognized independently, so unchanged regions like sidebars or tab bars
+    /// hit the cache while the rest of the screen changes. Words crossing a tile border are
+    /// recognized as two fragments.
+    Tiles { width: u32, height: u32 },
+}
+
+impl FromStr for CacheGranularity {
+    type Err = anyhow::Error;
+
+    /// Parse `frame`, or the tile size as `<width>x<height>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "frame" {
+            return Ok(CacheGranularity::Frame);
+        }
+        let (width, height) = s
+            .split_once('x')
+            .ok_or_else(|| anyhow!("expect `frame` or `<width>x<height>`, got `{}`", s))?;
+        let (width, height): (u32, u32) = (width.parse()?, height.parse()?);
+        if width == 0 || height == 0 {
+            return Err(anyhow!("tile size must not be zero, got `{}`", s));
+        }
+        Ok(CacheGranularity::Tiles { width, height })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: usize,
+    pub hit_rate: f64,
+}
+
+/// Recognizer skipping the wrapped one for pixels it has already seen.
+///
+/// Results are kept in a LRU cache keyed by the content hash of the frame or tile, so identical
+/// pixels are only recognized once as long as they are not evicted.
+pub struct CachedRecognizer {
+    inner: Arc<dyn CharacterRecognizer + Send + Sync>,
+    granularity: CacheGranularity,
+    cache: Mutex<LruCache<u64, Vec<RecognizeItem>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl CachedRecognizer {
+    pub fn new(
+        inner: Arc<dyn CharacterRecognizer + Send + Sync>,
+        granularity: CacheGranularity,
+        capacity: NonZeroUsize,
+    ) -> Self {
+        Self {
+            inner,
+            granularity,
+            cache: Mutex::new(LruCache::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+        CacheStats {
+            hits,
+            misses,
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entries: self.cache.lock().unwrap().len(),
+            hit_rate: if lookups == 0 {
+                0.0
+            } else {
+                hits as f64 / lookups as f64
+            },
+        }
+    }
+
+    async fn recognize_cached(&self, image: &DynamicImage) -> anyhow::Result<Vec<RecognizeItem>> {
+        let key = content_hash(image);
+        if let Some(items) = self.cache.lock().unwrap().get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(items.clone());
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let items = self.inner.recognize(image).await?;
+        let evicted = self.cache.lock().unwrap().push(key, items.clone());
+        if matches!(evicted, Some((evicted_key, _)) if evicted_key != key) {
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(items)
+    }
+}
+
+#[async_trait]
+impl CharacterRecognizer for CachedRecognizer {
+    async fn recognize(&self, image: &DynamicImage) -> anyhow::Result<Vec<RecognizeItem>> {
+        let result = match self.granularity {
+            CacheGranularity::Frame => self.recognize_cached(image).await?,
+            CacheGranularity::Tiles { width, height } => {
+                let mut result = Vec::new();
+                for top in (0..image.height()).step_by(height as usize) {
+                    for left in (0..image.width()).step_by(width as usize) {
+                        let tile = image.crop_imm(
+                            left,
+                            top,
+                            width.min(image.width() - left),
+                            height.min(image.height() - top),
+                        );
+                        let items = self.recognize_cached(&tile).await?;
+                        result.extend(items.into_iter().map(|mut it| {
+                            it.markup = MarkupBox::new(
+                                it.markup.left + left,
+                                it.markup.top + top,
+                                it.markup.width,
+                                it.markup.height,
+                            );
+                            it
+                        }));
+                    }
+                }
+                result
+            }
+        };
+        let stats = self.stats();
+        debug!(
+            "ocr cache: {} hits, {} misses, {} evictions, hit rate {:.2}",
+            stats.hits, stats.misses, stats.evictions, stats.hit_rate
+        );
+        Ok(result)
+    }
+
+    async fn recognize_thoroughly(
+        &self,
+        image: &DynamicImage,
+    ) -> anyhow::Result<Vec<RecognizeItem>> {
+        self.inner.recognize_thoroughly(image).await
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        Some(self.stats())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+    use crate::ocr::scripted::ScriptedRecognizer;
+
+    fn image(width: u32, height: u32, shade: u8) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+            width,
+            height,
+            Rgba([shade, shade, shade, 255]),
+        ))
+    }
+
+    fn cached(
+        inner: Arc<ScriptedRecognizer>,
+        granularity: CacheGranularity,
+        capacity: usize,
+    ) -> CachedRecognizer {
+        CachedRecognizer::new(inner, granularity, NonZeroUsize::new(capacity).unwrap())
+    }
+
+    #[tokio::test]
+    async fn identical_pixels_hit_the_cache() {
+        let inner = Arc::new(ScriptedRecognizer::new());
+        inner
+            .script(
+                &image(40, 20, 200),
+                vec![("hello", MarkupBox::new(1, 2, 30, 10))],
+            )
+            .await;
+        let recognizer = cached(inner, CacheGranularity::Frame, 4);
+
+        let first = recognizer.recognize(&image(40, 20, 200)).await.unwrap();
+        // another image with the same pixels
+        let second = recognizer.recognize(&image(40, 20, 200)).await.unwrap();
+        assert_eq!(first[0].text, "hello");
+        assert_eq!(second[0].text, "hello");
+        let stats = recognizer.cache_stats().unwrap();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+        assert_eq!(stats.hit_rate, 0.5);
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_entries_are_evicted_at_capacity() {
+        let recognizer = cached(
+            Arc::new(ScriptedRecognizer::new()),
+            CacheGranularity::Frame,
+            2,
+        );
+        for shade in [10, 20, 10, 30, 20] {
+            recognizer.recognize(&image(8, 8, shade)).await.unwrap();
+        }
+        // 10 is used again before 30 evicts 20, which then misses
+        let stats = recognizer.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 4);
+        assert_eq!(stats.evictions, 2);
+        assert_eq!(stats.entries, 2);
+    }
+
+    #[tokio::test]
+    async fn tiles_are_recognized_in_frame_coordinates() {
+        let mut frame = RgbaImage::from_pixel(250, 150, Rgba([200, 200, 200, 255]));
+        frame.put_pixel(150, 50, Rgba([0, 0, 0, 255]));
+        let frame = DynamicImage::ImageRgba8(frame);
+        let inner = Arc::new(ScriptedRecognizer::new());
+        inner
+            .script(
+                &frame.crop_imm(100, 0, 100, 100),
+                vec![("sidebar", MarkupBox::new(10, 20, 30, 8))],
+            )
+            .await;
+        inner
+            .script(
+                &frame.crop_imm(200, 100, 50, 50),
+                vec![("corner", MarkupBox::new(5, 6, 20, 8))],
+            )
+            .await;
+        let recognizer = cached(
+            inner,
+            CacheGranularity::Tiles {
+                width: 100,
+                height: 100,
+            },
+            16,
+        );
+
+        let items = recognizer.recognize(&frame).await.unwrap();
+        let found: Vec<(&str, u32, u32, u32, u32)> = items
+            .iter()
+            .map(|it| {
+                let markup = &it.markup;
+                let text = it.text.as_str();
+                (text, markup.left, markup.top, markup.width, markup.height)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![("sidebar", 110, 20, 30, 8), ("corner", 205, 106, 20, 8)]
+        );
+        // the two plain tiles of the second row share their pixels
+        assert_eq!(recognizer.stats().hits, 1);
+    }
+}
@@ -1,6 +1,12 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
 use anyhow::Ok;
 use async_trait::async_trait;
 
+pub mod cache;
+pub mod scripted;
+
 #[derive(Debug, Clone)]
 pub struct RecognizeItem {
     pub text: String,
@@ -48,6 +54,20 @@ impl MarkupBox {
 #[async_trait]
 pub trait CharacterRecognizer {
     async fn recognize(&self, image: &image::DynamicImage) -> anyhow::Result<Vec<RecognizeItem>>;
+
+    /// Recognize with slower settings finding as much text as possible, used when a region
+    /// is read again on demand. Defaults to `recognize`.
+    async fn recognize_thoroughly(
+        &self,
+        image: &image::DynamicImage,
+    ) -> anyhow::Result<Vec<RecognizeItem>> {
+        self.recognize(image).await
+    }
+
+    /// Hit rate of the cache in front of the engine, `None` when results are not cached.
+    fn cache_stats(&self) -> Option<cache::CacheStats> {
+        None
+    }
 }
 
 pub struct TesseractOCR {}
@@ -56,14 +76,14 @@ impl TesseractOCR {
     pub fn new() -> Self {
         Self {}
     }
-}
 
-#[async_trait]
-impl CharacterRecognizer for TesseractOCR {
-    async fn recognize(&self, image: &image::DynamicImage) -> anyhow::Result<Vec<RecognizeItem>> {
-        let default_args = rusty_tesseract::Args::default();
+    fn recognize_with_args(
+        &self,
+        image: &image::DynamicImage,
+        args: &rusty_tesseract::Args,
+    ) -> anyhow::Result<Vec<RecognizeItem>> {
         let ri = rusty_tesseract::Image::from_dynamic_image(image)?;
-        let output = rusty_tesseract::image_to_data(&ri, &default_args)?;
+        let output = rusty_tesseract::image_to_data(&ri, args)?;
         let result: Vec<RecognizeItem> = output
             .data
             .iter()
@@ -76,3 +96,33 @@ impl CharacterRecognizer for TesseractOCR {
         Ok(result)
     }
 }
+
+#[async_trait]
+impl CharacterRecognizer for TesseractOCR {
+    async fn recognize(&self, image: &image::DynamicImage) -> anyhow::Result<Vec<RecognizeItem>> {
+        self.recognize_with_args(image, &rusty_tesseract::Args::default())
+    }
+
+    async fn recognize_thoroughly(
+        &self,
+        image: &image::DynamicImage,
+    ) -> anyhow::Result<Vec<RecognizeItem>> {
+        // sparse text segmentation looks for as much text as possible in no particular order
+        let args = rusty_tesseract::Args {
+            dpi: Some(300),
+            psm: Some(11),
+            ..rusty_tesseract::Args::default()
+        };
+        self.recognize_with_args(image, &args)
+    }
+}
+
+/// Hash of the pixels of an image, identical images always share the same hash.
+pub fn content_hash(image: &image::DynamicImage) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    image.width().hash(&mut hasher);
+    image.height().hash(&mut hasher);
+    image.color().hash(&mut hasher);
+    image.as_bytes().hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,46 @@
+#[cfg(any(test, feature = "in-memory"))]
+use {
+    super::{content_hash, CharacterRecognizer, MarkupBox, RecognizeItem},
+    async_trait::async_trait,
+    image::DynamicImage,
+    std::collections::HashMap,
+    tokio::sync::Mutex,
+};
+
+/// Recognizer returning scripted results keyed by image content, for tests without tesseract.
+///
+/// Images without a script are recognized as containing no text.
+#[cfg(any(test, feature = "in-memory"))]
+pub struct ScriptedRecognizer {
+    script: Mutex<HashMap<u64, Vec<RecognizeItem>>>,
+}
+
+#[cfg(any(test, feature = "in-memory"))]
+impl ScriptedRecognizer {
+    pub fn new() -> Self {
+        Self {
+            script: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Recognize `words` whenever an image with the same pixels as `image` is given.
+    pub async fn script(&self, image: &DynamicImage, words: Vec<(&str, MarkupBox)>) {
+        let items = words
+            .into_iter()
+            .map(|(text, markup)| RecognizeItem::new(text.to_string(), markup, 5))
+            .collect();
+        self.script.lock().await.insert(content_hash(image), items);
+    }
+}
+
+#[cfg(any(test, feature = "in-memory"))]
+#[async_trait]
+impl CharacterRecognizer for ScriptedRecognizer {
+    async fn recognize(&self, image: &DynamicImage) -> anyhow::Result<Vec<RecognizeItem>> {
+        let script = self.script.lock().await;
+        Ok(script
+            .get(&content_hash(image))
+            .cloned()
+            .unwrap_or_default())
+    }
+}
@@ -0,0 +1,264 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::{analysis::Analysis, repository::Repository};
+
+/// Images fetched from the repository per scan.
+const BATCH_SIZE: u32 = 64;
+/// Emit a progress log line every this many images.
+const LOG_EVERY: u64 = 100;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ReindexOptions {
+    /// Only reindex images captured at or after this epoch.
+    pub from_epoch: Option<u64>,
+    /// Only reindex images captured at or before this epoch.
+    pub to_epoch: Option<u64>,
+    /// Continue after the last image recorded by an interrupted run.
+    #[serde(default)]
+    pub resume: bool,
+    /// Pause between two images, so reindexing does not starve the capture loop.
+    #[serde(default)]
+    pub throttle_millis: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReindexState {
+    Idle,
+    Running,
+    Cancelled,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReindexProgress {
+    pub state: ReindexState,
+    pub processed_images: u64,
+    pub failed_images: u64,
+    pub saved_texts: u64,
+    /// Id of the last handled image, a resumed run continues after it.
+    pub last_image_id: u32,
+    pub error: Option<String>,
+}
+
+/// Content of the checkpoint file. The range of the run is kept along with the last image, so a
+/// run resumed over another range starts over instead of skipping images.
+#[derive(Debug, Deserialize, Serialize)]
+struct Checkpoint {
+    from_epoch: Option<u64>,
+    to_epoch: Option<u64>,
+    last_image_id: u32,
+}
+
+impl ReindexProgress {
+    /// Progress of a reindexer which never ran.
+    pub fn idle() -> Self {
+        Self::new(ReindexState::Idle, 0)
+    }
+
+    fn new(state: ReindexState, last_image_id: u32) -> Self {
+        Self {
+            state,
+            processed_images: 0,
+            failed_images: 0,
+            saved_texts: 0,
+            last_image_id,
+            error: None,
+        }
+    }
+}
+
+/// Background job re-running OCR over the archived images.
+///
+/// The id of the last handled image is written to a checkpoint file along with the range of the
+/// run, so an interrupted or cancelled run could be resumed later. The checkpoint is removed
+/// once a run completes.
+pub struct Reindexer {
+    analysis: Arc<Analysis>,
+    repo: Arc<dyn Repository + Send + Sync>,
+    checkpoint_path: String,
+    progress: Mutex<ReindexProgress>,
+    running: Mutex<Option<CancellationToken>>,
+}
+
+impl Reindexer {
+    pub fn new(
+        analysis: Arc<Analysis>,
+        repo: Arc<dyn Repository + Send + Sync>,
+        checkpoint_path: String,
+    ) -> Self {
+        Self {
+            analysis,
+            repo,
+            checkpoint_path,
+            progress: Mutex::new(ReindexProgress::new(ReindexState::Idle, 0)),
+            running: Mutex::new(None),
+        }
+    }
+
+    pub async fn progress(&self) -> ReindexProgress {
+        self.progress.lock().await.clone()
+    }
+
+    /// Start reindexing in background, fails if there is already a running job.
+    pub async fn start(self: Arc<Self>, options: ReindexOptions) -> Result<ReindexProgress> {
+        let after_id = if options.resume {
+            self.read_checkpoint(&options).await?
+        } else {
+            0
+        };
+
+        let token = {
+            let mut running = self.running.lock().await;
+            if running.is_some() {
+                return Err(anyhow!("reindex is already running"));
+            }
+            let token = CancellationToken::new();
+            *running = Some(token.clone());
+            token
+        };
+        *self.progress.lock().await = ReindexProgress::new(ReindexState::Running, after_id);
+        info!(
+            "start reindexing after image {}, from {:?} to {:?}",
+            after_id, options.from_epoch, options.to_epoch
+        );
+
+        let reindexer = self.clone();
+        tokio::task::spawn(async move {
+            let result = reindexer.run(&options, after_id, &token).await;
+            let mut progress = reindexer.progress.lock().await;
+            match result {
+                Ok(state) => progress.state = state,
+                Err(e) => {
+                    warn!("reindex failed: {}", e);
+                    progress.state = ReindexState::Failed;
+                    progress.error = Some(e.to_string());
+                }
+            }
+            info!(
+                "reindex finished as {:?}, {} images processed, {} failed",
+                progress.state, progress.processed_images, progress.failed_images
+            );
+            reindexer.running.lock().await.take();
+        });
+
+        Ok(self.progress().await)
+    }
+
+    /// Ask the running job to stop after the current image, returns false if nothing is running.
+    pub async fn cancel(&self) -> bool {
+        match self.running.lock().await.as_ref() {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    async fn run(
+        &self,
+        options: &ReindexOptions,
+        after_id: u32,
+        token: &CancellationToken,
+    ) -> Result<ReindexState> {
+        let mut after_id = after_id;
+        loop {
+            let images = self
+                .repo
+                .scan_images(after_id, options.from_epoch, options.to_epoch, BATCH_SIZE)
+                .await?;
+            if images.is_empty() {
+                break;
+            }
+
+            for entity_image in images {
+                if token.is_cancelled() {
+                    return Ok(ReindexState::Cancelled);
+                }
+
+                let result = self.analysis.reindex_image(&entity_image).await;
+                after_id = entity_image.id;
+                {
+                    let mut progress = self.progress.lock().await;
+                    match result {
+                        Ok(saved) => {
+                            progress.processed_images += 1;
+                            progress.saved_texts += saved as u64;
+                        }
+                        Err(e) => {
+                            warn!("failed to reindex image {}: {}", entity_image.id, e);
+                            progress.failed_images += 1;
+                        }
+                    }
+                    progress.last_image_id = after_id;
+                    let handled = progress.processed_images + progress.failed_images;
+                    if handled % LOG_EVERY == 0 {
+                        info!("reindexed {} images, last image {}", handled, after_id);
+                    }
+                }
+                self.write_checkpoint(options, after_id).await?;
+
+                if options.throttle_millis > 0 {
+                    tokio::select! {
+                        _ = token.cancelled() => {},
+                        _ = tokio::time::sleep(Duration::from_millis(options.throttle_millis)) => {},
+                    }
+                }
+            }
+        }
+
+        self.remove_checkpoint().await?;
+        Ok(ReindexState::Completed)
+    }
+
+    /// The last image of the checkpoint, or 0 to start over when there is none or it was left
+    /// by a run over another range.
+    async fn read_checkpoint(&self, options: &ReindexOptions) -> Result<u32> {
+        let content = match tokio::fs::read_to_string(&self.checkpoint_path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let checkpoint: Checkpoint = match serde_json::from_str(&content) {
+            Ok(checkpoint) => checkpoint,
+            Err(e) => {
+                warn!("ignoring unreadable reindex checkpoint: {}", e);
+                return Ok(0);
+            }
+        };
+        if (checkpoint.from_epoch, checkpoint.to_epoch) != (options.from_epoch, options.to_epoch) {
+            info!(
+                "ignoring the reindex checkpoint of the range from {:?} to {:?}",
+                checkpoint.from_epoch, checkpoint.to_epoch
+            );
+            return Ok(0);
+        }
+        Ok(checkpoint.last_image_id)
+    }
+
+    async fn write_checkpoint(&self, options: &ReindexOptions, last_image_id: u32) -> Result<()> {
+        let checkpoint = Checkpoint {
+            from_epoch: options.from_epoch,
+            to_epoch: options.to_epoch,
+            last_image_id,
+        };
+        tokio::fs::write(&self.checkpoint_path, serde_json::to_string(&checkpoint)?).await?;
+        Ok(())
+    }
+
+    async fn remove_checkpoint(&self) -> Result<()> {
+        match tokio::fs::remove_file(&self.checkpoint_path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,1332 @@
+//! Behavior every `Repository` implementation must share, run against each backend by
+//! `conformance_tests!`.
+
+use sqlx_sqlite::SqlitePoolOptions;
+
+use super::{
+    in_memory::InMemoryRepository, sqlite::SqliteRepository, EntityEmbedding, EntityImage,
+    EntitySession, EntityText, FrameCount, Repository, SearchOptions, TextKind, TimelineCursor,
+    TimelineDirection, TimelineOptions,
+};
+use crate::ocr::MarkupBox;
+
+/// Generate a test per check for the backend built by the async constructor `$make`. The
+/// constructor of an `optional` backend returns `None` to skip the tests when the backend is not
+/// available.
+macro_rules! conformance_tests {
+    ($backend:ident, $make:path) => {
+        conformance_tests!($backend, $make, required);
+    };
+    ($backend:ident, $make:path, $mode:ident) => {
+        conformance_tests!(
+            @tests $backend,
+            $make,
+            $mode,
+            save_frame_assigns_ids,
+            save_frame_without_texts,
+            texts_are_stored_verbatim,
+            texts_of_images_are_ordered_by_position,
+            scan_images_pages_in_id_order,
+            continued_texts_are_stored_once,
+            timeline_pages_in_capture_order,
+            adjacent_images_follow_capture_order,
+            nearest_image_is_closest_in_time,
+            search_matches_tokens_case_insensitively,
+            search_groups_texts_by_image,
+            search_ranks_and_paginates,
+            search_blends_recency,
+            search_filters_by_time_and_screen,
+            search_matches_occurrences_overlapping_the_range,
+            fuzzy_search_tolerates_typos,
+            delete_images_removes_texts_and_search_entries,
+            delete_images_captured_between_keeps_the_rest,
+            replace_texts_replaces_every_text,
+            replace_texts_in_region_keeps_texts_outside_and_codes,
+            replace_texts_splits_occurrences,
+            delete_images_trims_occurrences,
+            embeddings_are_replaced_per_image,
+            sessions_are_saved_and_listed,
+            delete_images_trims_sessions,
+            stats_follow_saved_and_deleted_records,
+            maintenance_keeps_records
+        );
+    };
+    (@tests $backend:ident, $make:path, $mode:ident, $($check:ident),*) => {
+        mod $backend {
+            $(
+                #[tokio::test]
+                async fn $check() {
+                    conformance_tests!(@make repo, $make, $mode);
+                    super::$check(&repo).await;
+                }
+            )*
+        }
+    };
+    (@make $repo:ident, $make:path, required) => {
+        let $repo = $make().await;
+    };
+    (@make $repo:ident, $make:path, optional) => {
+        let Some($repo) = $make().await else {
+            return;
+        };
+    };
+}
+
+async fn in_memory_repository() -> InMemoryRepository {
+    InMemoryRepository::new()
+}
+
+async fn sqlite_repository() -> SqliteRepository {
+    let pool = SqlitePoolOptions::new()
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    let repo = SqliteRepository::new(pool);
+    repo.initialize().await.unwrap();
+    repo
+}
+
+/// A repository in a schema of its own in the database of `DEJAVU_TEST_POSTGRES_URL`, `None`
+/// when the variable is not set.
+#[cfg(feature = "postgres")]
+async fn postgres_repository() -> Option<super::postgres::PostgresRepository> {
+    use sqlx_postgres::{PgConnectOptions, PgPoolOptions};
+
+    let url = std::env::var("DEJAVU_TEST_POSTGRES_URL").ok()?;
+    let schema = format!("test_{}", uuid::Uuid::new_v4().simple());
+    let options: PgConnectOptions = url.parse().unwrap();
+    let admin = PgPoolOptions::new()
+        .max_connections(1)
+        .connect_with(options.clone())
+        .await
+        .unwrap();
+    sqlx::query(&format!("CREATE SCHEMA {}", schema))
+        .execute(&admin)
+        .await
+        .unwrap();
+    admin.close().await;
+
+    let pool = PgPoolOptions::new()
+        .connect_with(options.options([("search_path", format!("{},public", schema))]))
+        .await
+        .unwrap();
+    let repo = super::postgres::PostgresRepository::new(pool);
+    repo.initialize().await.unwrap();
+    Some(repo)
+}
+
+conformance_tests!(in_memory, super::in_memory_repository);
+conformance_tests!(sqlite, super::sqlite_repository);
+#[cfg(feature = "postgres")]
+conformance_tests!(postgres, super::postgres_repository, optional);
+
+const DAY: u64 = 86400;
+
+fn frame_image(captured_at_epoch: u64, screen_id: Option<u32>) -> EntityImage {
+    EntityImage::new(
+        0,
+        "in-memory".to_string(),
+        uuid::Uuid::new_v4().to_string(),
+        captured_at_epoch,
+        screen_id,
+    )
+}
+
+fn text(word: &str, left: u32, top: u32) -> EntityText {
+    EntityText::new(0, 0, TextKind::Ocr, word.to_string(), left, top, 40, 12)
+}
+
+fn now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+fn options(text: &str) -> SearchOptions {
+    SearchOptions::new(text.to_string(), 100, 0, 0.0)
+}
+
+/// Save frames of the screen seeing the texts of the first frame in each of them.
+async fn unchanged_frames(
+    repo: &impl Repository,
+    words: &[&str],
+    count: usize,
+) -> (Vec<EntityImage>, Vec<EntityText>) {
+    let texts: Vec<EntityText> = words
+        .iter()
+        .enumerate()
+        .map(|(i, it)| text(it, 0, 20 * i as u32))
+        .collect();
+    let (first, texts) = repo
+        .save_frame(&frame_image(1_000, Some(1)), &texts)
+        .await
+        .unwrap();
+    let ids: Vec<u32> = texts.iter().map(|it| it.id).collect();
+    let mut images = vec![first];
+    for i in 1..count {
+        let (image, _) = repo
+            .save_frame_continuing(&frame_image(1_000 + 2 * i as u64, Some(1)), &[], &ids)
+            .await
+            .unwrap();
+        images.push(image);
+    }
+    (images, texts)
+}
+
+/// Words of the texts seen in the image, in position order.
+async fn words_of(repo: &impl Repository, image_id: u32) -> Vec<String> {
+    let texts = repo.get_texts_by_image_ids(&[image_id]).await.unwrap();
+    assert!(texts.iter().all(|it| it.image_id == image_id));
+    texts.into_iter().map(|it| it.text).collect()
+}
+
+/// Ids of the images of the first page matching the text.
+async fn search_image_ids(repo: &impl Repository, text: &str) -> Vec<u32> {
+    let page = repo.search(&options(text)).await.unwrap();
+    page.results.iter().map(|it| it.image_id).collect()
+}
+
+async fn save_frame_assigns_ids(repo: &impl Repository) {
+    let (first, texts) = repo
+        .save_frame(
+            &frame_image(1_000, Some(1)),
+            &[text("hello", 0, 0), text("world", 50, 0)],
+        )
+        .await
+        .unwrap();
+    assert!(first.id > 0);
+    assert_eq!(texts.len(), 2);
+    assert!(texts.iter().all(|it| it.id > 0 && it.image_id == first.id));
+    assert_ne!(texts[0].id, texts[1].id);
+    assert_eq!(texts[0].text, "hello");
+    assert_eq!(texts[1].left, 50);
+
+    let loaded = repo.get_image_by_id(first.id).await.unwrap();
+    assert_eq!(loaded.archive_info, first.archive_info);
+    assert_eq!(loaded.captured_at_epoch, 1_000);
+    assert_eq!(loaded.screen_id, Some(1));
+    let loaded = repo.get_text_by_id(texts[0].id).await.unwrap();
+    assert_eq!(loaded.image_id, first.id);
+    assert_eq!(loaded.text, "hello");
+    assert_eq!(loaded.kind, TextKind::Ocr);
+
+    let (second, second_texts) = repo
+        .save_frame(&frame_image(1_002, None), &[text("again", 0, 0)])
+        .await
+        .unwrap();
+    assert!(second.id > first.id);
+    assert!(texts.iter().all(|it| it.id < second_texts[0].id));
+    assert_eq!(
+        repo.get_image_by_id(second.id).await.unwrap().screen_id,
+        None
+    );
+
+    assert!(repo.get_image_by_id(second.id + 100).await.is_err());
+    assert!(repo.get_text_by_id(second_texts[0].id + 100).await.is_err());
+}
+
+async fn save_frame_without_texts(repo: &impl Repository) {
+    let (image, texts) = repo
+        .save_frame(&frame_image(1_000, None), &[])
+        .await
+        .unwrap();
+    assert!(texts.is_empty());
+    assert_eq!(repo.get_image_by_id(image.id).await.unwrap().id, image.id);
+    assert!(repo
+        .get_texts_by_image_ids(&[image.id])
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+async fn texts_are_stored_verbatim(repo: &impl Repository) {
+    let words = [
+        "don't",
+        "O'Reilly'); DROP TABLE texts; --",
+        "\"quoted\"",
+        "日本語",
+        "naïve 🙂",
+    ];
+    let mut entities: Vec<EntityText> = words.iter().map(|it| text(it, 0, 0)).collect();
+    entities.push(EntityText::new(
+        0,
+        0,
+        TextKind::QrCode,
+        "https://example.com/?a=1&b='2'".to_string(),
+        100,
+        100,
+        80,
+        80,
+    ));
+    let (_, saved) = repo
+        .save_frame(&frame_image(1_000, None), &entities)
+        .await
+        .unwrap();
+    for (saved, expected) in saved.iter().zip(entities.iter()) {
+        let loaded = repo.get_text_by_id(saved.id).await.unwrap();
+        assert_eq!(loaded.text, expected.text);
+        assert_eq!(loaded.kind, expected.kind);
+    }
+}
+
+async fn texts_of_images_are_ordered_by_position(repo: &impl Repository) {
+    let (first, _) = repo
+        .save_frame(
+            &frame_image(1_000, None),
+            &[
+                text("third", 0, 40),
+                text("second", 60, 0),
+                text("first", 0, 0),
+            ],
+        )
+        .await
+        .unwrap();
+    let (second, _) = repo
+        .save_frame(&frame_image(1_002, None), &[text("other", 0, 0)])
+        .await
+        .unwrap();
+
+    let texts = repo.get_texts_by_image_ids(&[first.id]).await.unwrap();
+    let words: Vec<&str> = texts.iter().map(|it| it.text.as_str()).collect();
+    assert_eq!(words, vec!["first", "second", "third"]);
+
+    let texts = repo
+        .get_texts_by_image_ids(&[second.id, first.id])
+        .await
+        .unwrap();
+    assert_eq!(texts.len(), 4);
+    assert_eq!(texts[3].image_id, second.id);
+    assert!(repo.get_texts_by_image_ids(&[]).await.unwrap().is_empty());
+}
+
+async fn scan_images_pages_in_id_order(repo: &impl Repository) {
+    let mut ids = vec![];
+    for epoch in [1_000, 1_010, 1_020, 1_030, 1_040] {
+        let (image, _) = repo
+            .save_frame(&frame_image(epoch, None), &[])
+            .await
+            .unwrap();
+        ids.push(image.id);
+    }
+
+    let page: Vec<u32> = repo
+        .scan_images(0, None, None, 2)
+        .await
+        .unwrap()
+        .iter()
+        .map(|it| it.id)
+        .collect();
+    assert_eq!(page, ids[..2]);
+    let page: Vec<u32> = repo
+        .scan_images(ids[1], None, None, 10)
+        .await
+        .unwrap()
+        .iter()
+        .map(|it| it.id)
+        .collect();
+    assert_eq!(page, ids[2..]);
+
+    let in_range: Vec<u64> = repo
+        .scan_images(0, Some(1_010), Some(1_030), 10)
+        .await
+        .unwrap()
+        .iter()
+        .map(|it| it.captured_at_epoch)
+        .collect();
+    assert_eq!(in_range, vec![1_010, 1_020, 1_030]);
+}
+
+/// Save frames captured out of order, the timeline is
+/// `[100 @1] [200 @2] [200 @1] [300 @1] [400 @2]` with capture time and screen.
+async fn continued_texts_are_stored_once(repo: &impl Repository) {
+    let (first, texts) = repo
+        .save_frame(
+            &frame_image(1_000, Some(1)),
+            &[text("hello", 0, 0), text("world", 0, 20)],
+        )
+        .await
+        .unwrap();
+    let (hello, world) = (&texts[0], &texts[1]);
+    let (other, _) = repo
+        .save_frame(&frame_image(1_001, Some(2)), &[text("other", 0, 0)])
+        .await
+        .unwrap();
+
+    let (third, saved) = repo
+        .save_frame_continuing(
+            &frame_image(1_002, Some(1)),
+            &[text("new", 0, 40)],
+            &[hello.id],
+        )
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 2);
+    assert_eq!(saved[0].text, "new");
+    assert_eq!(saved[1].id, hello.id);
+    assert!(saved.iter().all(|it| it.image_id == third.id));
+    assert_eq!(words_of(repo, third.id).await, vec!["hello", "new"]);
+    assert_eq!(words_of(repo, first.id).await, vec!["hello", "world"]);
+    assert_eq!(words_of(repo, other.id).await, vec!["other"]);
+
+    // an occurrence is found once, in the image it was first seen in
+    let loaded = repo.get_text_by_id(hello.id).await.unwrap();
+    assert_eq!(
+        (loaded.image_id, loaded.last_image_id),
+        (first.id, third.id)
+    );
+    assert_eq!(search_image_ids(repo, "hello").await, vec![first.id]);
+
+    // a text not seen in the previous frame of the screen is stored again
+    let (fourth, saved) = repo
+        .save_frame_continuing(&frame_image(1_004, Some(1)), &[], &[world.id])
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_ne!(saved[0].id, world.id);
+    assert_eq!(saved[0].text, "world");
+    assert_eq!(words_of(repo, fourth.id).await, vec!["world"]);
+    let mut found = search_image_ids(repo, "world").await;
+    found.sort();
+    assert_eq!(found, vec![first.id, fourth.id]);
+
+    // frames without a screen are never continued
+    let (unscreened, _) = repo
+        .save_frame(&frame_image(1_006, None), &[text("alone", 0, 0)])
+        .await
+        .unwrap();
+    let alone = repo.get_texts_by_image_ids(&[unscreened.id]).await.unwrap();
+    let (next, saved) = repo
+        .save_frame_continuing(&frame_image(1_008, None), &[], &[alone[0].id])
+        .await
+        .unwrap();
+    assert_ne!(saved[0].id, alone[0].id);
+    assert_eq!(words_of(repo, next.id).await, vec!["alone"]);
+    assert_eq!(words_of(repo, unscreened.id).await, vec!["alone"]);
+}
+
+async fn timeline_frames(repo: &impl Repository) -> Vec<EntityImage> {
+    let mut images = vec![];
+    for (epoch, screen_id) in [(300, 1), (100, 1), (200, 2), (200, 1), (400, 2)] {
+        let (image, _) = repo
+            .save_frame(&frame_image(epoch, Some(screen_id)), &[])
+            .await
+            .unwrap();
+        images.push(image);
+    }
+    // in capture order, images captured at the same time in id order
+    vec![
+        images[1].clone(),
+        images[2].clone(),
+        images[3].clone(),
+        images[0].clone(),
+        images[4].clone(),
+    ]
+}
+
+async fn timeline_pages_in_capture_order(repo: &impl Repository) {
+    let timeline = timeline_frames(repo).await;
+    let ids = |images: &[EntityImage]| images.iter().map(|it| it.id).collect::<Vec<_>>();
+
+    let mut pages = vec![];
+    let mut options = TimelineOptions::new(2);
+    loop {
+        let page = repo.list_images(&options).await.unwrap();
+        let Some(last) = page.last() else {
+            break;
+        };
+        options.after = Some(TimelineCursor::of(last));
+        pages.push(ids(&page));
+    }
+    let expected = ids(&timeline);
+    assert_eq!(
+        pages,
+        vec![
+            expected[..2].to_vec(),
+            expected[2..4].to_vec(),
+            expected[4..].to_vec()
+        ]
+    );
+
+    let first_screen = TimelineOptions {
+        from_epoch: Some(150),
+        screen_id: Some(1),
+        ..TimelineOptions::new(10)
+    };
+    let page = repo.list_images(&first_screen).await.unwrap();
+    assert_eq!(ids(&page), vec![timeline[2].id, timeline[3].id]);
+
+    let until = TimelineOptions {
+        to_epoch: Some(200),
+        ..TimelineOptions::new(10)
+    };
+    let page = repo.list_images(&until).await.unwrap();
+    assert_eq!(ids(&page), ids(&timeline[..3]));
+}
+
+async fn adjacent_images_follow_capture_order(repo: &impl Repository) {
+    let timeline = timeline_frames(repo).await;
+    let adjacent = |image: &EntityImage, direction, screen_id| {
+        let id = image.id;
+        async move {
+            repo.get_adjacent_image(id, direction, screen_id)
+                .await
+                .unwrap()
+                .map(|it| it.id)
+        }
+    };
+
+    for pair in timeline.windows(2) {
+        assert_eq!(
+            adjacent(&pair[0], TimelineDirection::Next, None).await,
+            Some(pair[1].id)
+        );
+        assert_eq!(
+            adjacent(&pair[1], TimelineDirection::Previous, None).await,
+            Some(pair[0].id)
+        );
+    }
+    assert_eq!(
+        adjacent(&timeline[0], TimelineDirection::Previous, None).await,
+        None
+    );
+    assert_eq!(
+        adjacent(&timeline[4], TimelineDirection::Next, None).await,
+        None
+    );
+
+    // steps skip the frames of other screens
+    assert_eq!(
+        adjacent(&timeline[0], TimelineDirection::Next, Some(1)).await,
+        Some(timeline[2].id)
+    );
+    assert_eq!(
+        adjacent(&timeline[4], TimelineDirection::Previous, Some(2)).await,
+        Some(timeline[1].id)
+    );
+    assert!(repo
+        .get_adjacent_image(u32::MAX, TimelineDirection::Next, None)
+        .await
+        .is_err());
+}
+
+async fn nearest_image_is_closest_in_time(repo: &impl Repository) {
+    assert!(repo.get_nearest_image(100, None).await.unwrap().is_none());
+    let timeline = timeline_frames(repo).await;
+    let nearest = |epoch, screen_id| async move {
+        repo.get_nearest_image(epoch, screen_id)
+            .await
+            .unwrap()
+            .map(|it| it.id)
+    };
+
+    assert_eq!(nearest(0, None).await, Some(timeline[0].id));
+    assert_eq!(nearest(260, None).await, Some(timeline[3].id));
+    assert_eq!(nearest(10_000, None).await, Some(timeline[4].id));
+    // the earlier frame wins a tie, the last one of the frames captured at the same time
+    assert_eq!(nearest(250, None).await, Some(timeline[2].id));
+    assert_eq!(nearest(330, Some(2)).await, Some(timeline[4].id));
+    assert_eq!(nearest(260, Some(2)).await, Some(timeline[1].id));
+}
+
+async fn search_matches_tokens_case_insensitively(repo: &impl Repository) {
+    let (image, _) = repo
+        .save_frame(
+            &frame_image(1_000, None),
+            &[text("Hello", 0, 0), text("world", 50, 0)],
+        )
+        .await
+        .unwrap();
+
+    let page = repo.search(&options("hello")).await.unwrap();
+    assert_eq!(page.total, 1);
+    assert_eq!(page.results[0].image_id, image.id);
+    assert_eq!(page.results[0].captured_at_epoch, 1_000);
+    assert_eq!(page.results[0].texts.len(), 1);
+    assert_eq!(page.results[0].texts[0].text, "Hello");
+
+    assert!(search_image_ids(repo, "hel").await.is_empty());
+    assert_eq!(search_image_ids(repo, "hel*").await, vec![image.id]);
+    let page = repo.search(&options("missing")).await.unwrap();
+    assert_eq!(page.total, 0);
+    assert!(page.results.is_empty());
+}
+
+async fn search_groups_texts_by_image(repo: &impl Repository) {
+    let (image, saved) = repo
+        .save_frame(
+            &frame_image(1_000, None),
+            &[
+                text("report", 0, 0),
+                text("other", 50, 0),
+                text("report", 0, 40),
+            ],
+        )
+        .await
+        .unwrap();
+
+    let page = repo.search(&options("report")).await.unwrap();
+    assert_eq!(page.total, 1);
+    let ids: Vec<u32> = page.results[0].texts.iter().map(|it| it.id).collect();
+    assert_eq!(ids, vec![saved[0].id, saved[2].id]);
+    assert!(page.results[0]
+        .texts
+        .iter()
+        .all(|it| it.image_id == image.id));
+}
+
+async fn search_ranks_and_paginates(repo: &impl Repository) {
+    let mut ids = vec![];
+    for words in [
+        "quarterly report draft for the team",
+        "report",
+        "annual report of the company",
+    ] {
+        let (image, _) = repo
+            .save_frame(&frame_image(1_000, None), &[text(words, 0, 0)])
+            .await
+            .unwrap();
+        ids.push(image.id);
+    }
+
+    let first = repo
+        .search(&SearchOptions::new("report".to_string(), 2, 0, 0.0))
+        .await
+        .unwrap();
+    assert_eq!(first.total, 3);
+    assert_eq!(first.results.len(), 2);
+    // the shortest matching text is the most relevant
+    assert_eq!(first.results[0].image_id, ids[1]);
+    assert!(first.results[0].score <= first.results[1].score);
+
+    let second = repo
+        .search(&SearchOptions::new("report".to_string(), 2, 2, 0.0))
+        .await
+        .unwrap();
+    assert_eq!(second.total, 3);
+    assert_eq!(second.results.len(), 1);
+    let mut all: Vec<u32> = first
+        .results
+        .iter()
+        .chain(second.results.iter())
+        .map(|it| it.image_id)
+        .collect();
+    all.sort();
+    assert_eq!(all, ids);
+
+    let past_end = repo
+        .search(&SearchOptions::new("report".to_string(), 2, 10, 0.0))
+        .await
+        .unwrap();
+    assert_eq!(past_end.total, 3);
+    assert!(past_end.results.is_empty());
+}
+
+async fn search_blends_recency(repo: &impl Repository) {
+    let (old, _) = repo
+        .save_frame(
+            &frame_image(now() - 30 * DAY, None),
+            &[text("invoice", 0, 0)],
+        )
+        .await
+        .unwrap();
+    let (recent, _) = repo
+        .save_frame(&frame_image(now() - DAY, None), &[text("invoice", 0, 0)])
+        .await
+        .unwrap();
+
+    // equally relevant images are ordered by id
+    assert_eq!(
+        search_image_ids(repo, "invoice").await,
+        vec![old.id, recent.id]
+    );
+    let page = repo
+        .search(&SearchOptions::new("invoice".to_string(), 10, 0, 1.0))
+        .await
+        .unwrap();
+    let ids: Vec<u32> = page.results.iter().map(|it| it.image_id).collect();
+    assert_eq!(ids, vec![recent.id, old.id]);
+}
+
+async fn search_filters_by_time_and_screen(repo: &impl Repository) {
+    let mut ids = vec![];
+    for (epoch, screen_id) in [(1_000, 1), (1_000, 2), (2_000, 1), (2_000, 2)] {
+        let (image, _) = repo
+            .save_frame(
+                &frame_image(epoch, Some(screen_id)),
+                &[text("meeting", 0, 0)],
+            )
+            .await
+            .unwrap();
+        ids.push(image.id);
+    }
+
+    let search = |from_epoch, to_epoch, screen_id| {
+        let options = SearchOptions {
+            from_epoch,
+            to_epoch,
+            screen_id,
+            ..options("meeting")
+        };
+        async move {
+            let page = repo.search(&options).await.unwrap();
+            assert_eq!(page.total, page.results.len() as u64);
+            let mut ids: Vec<u32> = page.results.iter().map(|it| it.image_id).collect();
+            ids.sort();
+            ids
+        }
+    };
+    assert_eq!(search(None, None, None).await, ids);
+    assert_eq!(search(Some(1_500), None, None).await, ids[2..]);
+    assert_eq!(search(None, Some(1_000), None).await, ids[..2]);
+    assert_eq!(search(None, None, Some(2)).await, vec![ids[1], ids[3]]);
+    assert_eq!(
+        search(Some(1_500), Some(2_500), Some(1)).await,
+        vec![ids[2]]
+    );
+    assert!(search(Some(3_000), None, None).await.is_empty());
+}
+
+async fn search_matches_occurrences_overlapping_the_range(repo: &impl Repository) {
+    // seen from 1_000 to 1_004, the result is its first frame in the range
+    let (images, texts) = unchanged_frames(repo, &["standup"], 3).await;
+    repo.replace_embeddings(
+        images[0].id,
+        &[EntityEmbedding::new(
+            0,
+            "small".to_string(),
+            vec![texts[0].id],
+            vec![1.0],
+        )],
+    )
+    .await
+    .unwrap();
+
+    let within = |from_epoch, to_epoch, fuzzy| SearchOptions {
+        from_epoch,
+        to_epoch,
+        fuzzy,
+        ..options("standup")
+    };
+    for fuzzy in [false, true] {
+        let page = repo
+            .search(&within(Some(1_003), None, fuzzy))
+            .await
+            .unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.results[0].image_id, images[2].id);
+        assert_eq!(page.results[0].captured_at_epoch, 1_004);
+        assert_eq!(page.results[0].texts[0].id, texts[0].id);
+        assert_eq!(page.results[0].texts[0].image_id, images[2].id);
+        let page = repo
+            .search(&within(Some(1_001), Some(1_003), fuzzy))
+            .await
+            .unwrap();
+        assert_eq!(page.results.len(), 1);
+        assert_eq!(page.results[0].image_id, images[1].id);
+        for (from_epoch, to_epoch) in [(Some(1_003), Some(1_003)), (Some(1_005), None)] {
+            assert_eq!(
+                repo.search(&within(from_epoch, to_epoch, fuzzy))
+                    .await
+                    .unwrap()
+                    .total,
+                0
+            );
+        }
+        assert_eq!(
+            repo.search(&within(None, Some(999), fuzzy))
+                .await
+                .unwrap()
+                .total,
+            0
+        );
+    }
+    // a page past the end counts the frames in the range
+    let page = repo
+        .search(&SearchOptions {
+            offset: 1,
+            ..within(Some(1_003), None, false)
+        })
+        .await
+        .unwrap();
+    assert_eq!((page.total, page.results.len()), (1, 0));
+
+    let found = repo
+        .get_embeddings("small", &within(Some(1_003), None, false), 100)
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].0.image_id, images[0].id);
+    assert_eq!(found[0].1.id, images[2].id);
+    for (from_epoch, to_epoch) in [(Some(1_003), Some(1_003)), (Some(1_005), None)] {
+        assert!(repo
+            .get_embeddings("small", &within(from_epoch, to_epoch, false), 100)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
+
+async fn fuzzy_search_tolerates_typos(repo: &impl Repository) {
+    let mut ids = vec![];
+    for word in ["rnodern", "modern", "modular", "cat"] {
+        let (image, _) = repo
+            .save_frame(&frame_image(1_000, None), &[text(word, 0, 0)])
+            .await
+            .unwrap();
+        ids.push(image.id);
+    }
+
+    let fuzzy = |text: &str| SearchOptions {
+        fuzzy: true,
+        ..options(text)
+    };
+    let page = repo.search(&fuzzy("Modern")).await.unwrap();
+    assert_eq!(page.total, 2);
+    // the exact match ranks first
+    assert_eq!(page.results[0].image_id, ids[1]);
+    assert_eq!(page.results[1].image_id, ids[0]);
+    assert!(page.results[0].score < page.results[1].score);
+
+    // terms shorter than a trigram fall back to the exact search
+    let page = repo.search(&fuzzy("ca")).await.unwrap();
+    assert_eq!(page.total, 0);
+    let page = repo.search(&fuzzy("cat")).await.unwrap();
+    assert_eq!(page.results[0].image_id, ids[3]);
+}
+
+async fn delete_images_removes_texts_and_search_entries(repo: &impl Repository) {
+    let mut images = vec![];
+    for epoch in [1_000, 1_002, 1_004] {
+        let (image, texts) = repo
+            .save_frame(
+                &frame_image(epoch, None),
+                &[text("password", 0, 0), text("hunter", 50, 0)],
+            )
+            .await
+            .unwrap();
+        images.push((image, texts));
+    }
+    let (first, first_texts) = &images[0];
+    let (second, second_texts) = &images[1];
+
+    let deleted = repo
+        .delete_images(&[second.id, first.id, u32::MAX])
+        .await
+        .unwrap();
+    let deleted_ids: Vec<u32> = deleted.iter().map(|it| it.id).collect();
+    assert_eq!(deleted_ids, vec![first.id, second.id]);
+    assert_eq!(deleted[0].archive_info, first.archive_info);
+
+    let kept = images[2].0.id;
+    assert!(repo.get_image_by_id(first.id).await.is_err());
+    assert!(repo.get_text_by_id(second_texts[0].id).await.is_err());
+    assert!(repo
+        .get_texts_by_image_ids(&[first.id, second.id])
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(search_image_ids(repo, "password").await, vec![kept]);
+    let fuzzy = SearchOptions {
+        fuzzy: true,
+        ..options("pasword")
+    };
+    let page = repo.search(&fuzzy).await.unwrap();
+    assert_eq!(page.total, 1);
+    assert_eq!(page.results[0].image_id, kept);
+    assert!(first_texts
+        .iter()
+        .all(|it| page.results[0].texts.iter().all(|text| text.id != it.id)));
+
+    assert!(repo.delete_images(&[]).await.unwrap().is_empty());
+    assert!(repo.delete_images(&[first.id]).await.unwrap().is_empty());
+}
+
+async fn delete_images_captured_between_keeps_the_rest(repo: &impl Repository) {
+    for epoch in [100, 200, 300, 400] {
+        repo.save_frame(&frame_image(epoch, None), &[text("ledger", 0, 0)])
+            .await
+            .unwrap();
+    }
+
+    let deleted: Vec<u64> = repo
+        .delete_images_captured_between(200, 300)
+        .await
+        .unwrap()
+        .iter()
+        .map(|it| it.captured_at_epoch)
+        .collect();
+    assert_eq!(deleted, vec![200, 300]);
+
+    let kept = repo.list_images(&TimelineOptions::new(10)).await.unwrap();
+    let kept_epochs: Vec<u64> = kept.iter().map(|it| it.captured_at_epoch).collect();
+    assert_eq!(kept_epochs, vec![100, 400]);
+    let mut found = search_image_ids(repo, "ledger").await;
+    found.sort();
+    assert_eq!(found, kept.iter().map(|it| it.id).collect::<Vec<_>>());
+    assert!(repo
+        .delete_images_captured_between(201, 299)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+async fn replace_texts_replaces_every_text(repo: &impl Repository) {
+    let (image, old) = repo
+        .save_frame(
+            &frame_image(1_000, None),
+            &[text("before", 0, 0), text("stale", 50, 0)],
+        )
+        .await
+        .unwrap();
+    let (other, _) = repo
+        .save_frame(&frame_image(1_002, None), &[text("before", 0, 0)])
+        .await
+        .unwrap();
+
+    let new = repo
+        .replace_texts(image.id, &[text("after", 0, 0)])
+        .await
+        .unwrap();
+    assert_eq!(new.len(), 1);
+    assert_eq!(new[0].image_id, image.id);
+
+    assert!(search_image_ids(repo, "stale").await.is_empty());
+    assert_eq!(search_image_ids(repo, "before").await, vec![other.id]);
+    assert_eq!(search_image_ids(repo, "after").await, vec![image.id]);
+    let texts = repo.get_texts_by_image_ids(&[image.id]).await.unwrap();
+    assert_eq!(texts.len(), 1);
+    assert_eq!(texts[0].text, "after");
+    assert!(old
+        .iter()
+        .filter(|it| it.id != new[0].id)
+        .all(|it| texts.iter().all(|text| text.id != it.id)));
+}
+
+async fn replace_texts_in_region_keeps_texts_outside_and_codes(repo: &impl Repository) {
+    let code = EntityText::new(
+        0,
+        0,
+        TextKind::QrCode,
+        "https://example.com".to_string(),
+        20,
+        20,
+        30,
+        30,
+    );
+    let (image, _) = repo
+        .save_frame(
+            &frame_image(1_000, None),
+            &[
+                text("inside", 10, 10),
+                text("straddling", 80, 10),
+                text("outside", 200, 100),
+                code,
+            ],
+        )
+        .await
+        .unwrap();
+
+    let region = MarkupBox::new(0, 0, 100, 60);
+    let new = repo
+        .replace_texts_in_region(image.id, &region, &[text("reread", 10, 10)])
+        .await
+        .unwrap();
+    assert_eq!(new[0].image_id, image.id);
+
+    let mut words: Vec<String> = repo
+        .get_texts_by_image_ids(&[image.id])
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|it| it.text)
+        .collect();
+    words.sort();
+    assert_eq!(
+        words,
+        vec!["https://example.com", "outside", "reread", "straddling"]
+    );
+    assert!(search_image_ids(repo, "inside").await.is_empty());
+    assert_eq!(search_image_ids(repo, "reread").await, vec![image.id]);
+}
+
+async fn replace_texts_splits_occurrences(repo: &impl Repository) {
+    let (images, texts) = unchanged_frames(repo, &["kept", "typo"], 3).await;
+    let middle = images[1].id;
+
+    let region = MarkupBox::new(0, 15, 100, 20);
+    repo.replace_texts_in_region(middle, &region, &[text("fixed", 0, 20)])
+        .await
+        .unwrap();
+    assert_eq!(words_of(repo, images[0].id).await, vec!["kept", "typo"]);
+    assert_eq!(words_of(repo, middle).await, vec!["kept", "fixed"]);
+    assert_eq!(words_of(repo, images[2].id).await, vec!["kept", "typo"]);
+    assert_eq!(
+        search_image_ids(repo, "typo").await,
+        vec![images[0].id, images[2].id]
+    );
+    assert_eq!(search_image_ids(repo, "kept").await, vec![images[0].id]);
+    let typo = repo.get_text_by_id(texts[1].id).await.unwrap();
+    assert_eq!(
+        (typo.image_id, typo.last_image_id),
+        (images[0].id, images[0].id)
+    );
+
+    repo.replace_texts(images[2].id, &[text("last", 0, 0)])
+        .await
+        .unwrap();
+    assert_eq!(words_of(repo, images[2].id).await, vec!["last"]);
+    assert_eq!(words_of(repo, middle).await, vec!["kept", "fixed"]);
+    assert_eq!(search_image_ids(repo, "typo").await, vec![images[0].id]);
+}
+
+async fn delete_images_trims_occurrences(repo: &impl Repository) {
+    let (images, texts) = unchanged_frames(repo, &["still"], 3).await;
+    let id = texts[0].id;
+
+    repo.delete_images(&[images[0].id]).await.unwrap();
+    let text = repo.get_text_by_id(id).await.unwrap();
+    assert_eq!(
+        (text.image_id, text.last_image_id),
+        (images[1].id, images[2].id)
+    );
+    assert_eq!(search_image_ids(repo, "still").await, vec![images[1].id]);
+
+    repo.delete_images_captured_between(1_004, 1_004)
+        .await
+        .unwrap();
+    let text = repo.get_text_by_id(id).await.unwrap();
+    assert_eq!(
+        (text.image_id, text.last_image_id),
+        (images[1].id, images[1].id)
+    );
+    assert_eq!(words_of(repo, images[1].id).await, vec!["still"]);
+
+    repo.delete_images(&[images[1].id]).await.unwrap();
+    assert!(repo.get_text_by_id(id).await.is_err());
+    assert!(search_image_ids(repo, "still").await.is_empty());
+}
+
+async fn embeddings_are_replaced_per_image(repo: &impl Repository) {
+    let (first, first_texts) = repo
+        .save_frame(&frame_image(1_000, Some(1)), &[text("alpha", 0, 0)])
+        .await
+        .unwrap();
+    let (second, _) = repo
+        .save_frame(&frame_image(2_000, Some(2)), &[text("beta", 0, 0)])
+        .await
+        .unwrap();
+    let line = |model: &str, vector: Vec<f32>| {
+        // the image id is assigned by `replace_embeddings`
+        EntityEmbedding::new(0, model.to_string(), vec![first_texts[0].id, 7], vector)
+    };
+    repo.replace_embeddings(
+        first.id,
+        &[
+            line("small", vec![1.0, 0.0]),
+            line("small", vec![0.0, -0.5]),
+            line("other", vec![1.0]),
+        ],
+    )
+    .await
+    .unwrap();
+    repo.replace_embeddings(second.id, &[line("small", vec![0.25, 0.75])])
+        .await
+        .unwrap();
+
+    // the latest image first
+    let all = repo
+        .get_embeddings("small", &options(""), 100)
+        .await
+        .unwrap();
+    let vectors: Vec<&Vec<f32>> = all.iter().map(|(it, _)| &it.vector).collect();
+    assert_eq!(
+        vectors,
+        [&vec![0.25, 0.75], &vec![1.0, 0.0], &vec![0.0, -0.5]]
+    );
+    let (embedding, image) = &all[1];
+    assert_eq!(embedding.image_id, first.id);
+    assert_eq!(embedding.model, "small");
+    assert_eq!(embedding.text_ids, vec![first_texts[0].id, 7]);
+    assert_eq!(image.id, first.id);
+    assert_eq!(image.captured_at_epoch, 1_000);
+    assert_eq!(image.screen_id, Some(1));
+    let latest = repo.get_embeddings("small", &options(""), 2).await.unwrap();
+    let vectors: Vec<&Vec<f32>> = latest.iter().map(|(it, _)| &it.vector).collect();
+    assert_eq!(vectors, [&vec![0.25, 0.75], &vec![1.0, 0.0]]);
+
+    let on_second_screen = SearchOptions {
+        screen_id: Some(2),
+        ..options("")
+    };
+    let found = repo
+        .get_embeddings("small", &on_second_screen, 100)
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].1.id, second.id);
+    let early = SearchOptions {
+        to_epoch: Some(1_500),
+        ..options("")
+    };
+    assert_eq!(
+        repo.get_embeddings("small", &early, 100)
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+    assert!(repo
+        .get_embeddings("missing", &options(""), 100)
+        .await
+        .unwrap()
+        .is_empty());
+
+    repo.replace_embeddings(first.id, &[line("small", vec![0.5, 0.5])])
+        .await
+        .unwrap();
+    assert_eq!(
+        repo.get_embeddings("small", &early, 100)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    assert!(repo
+        .get_embeddings("other", &options(""), 100)
+        .await
+        .unwrap()
+        .is_empty());
+
+    repo.delete_images(&[second.id]).await.unwrap();
+    let remaining = repo
+        .get_embeddings("small", &options(""), 100)
+        .await
+        .unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].0.vector, vec![0.5, 0.5]);
+}
+
+/// Save an image of the screen without texts.
+async fn blank_frame(
+    repo: &impl Repository,
+    captured_at_epoch: u64,
+    screen_id: u32,
+) -> EntityImage {
+    let (image, _) = repo
+        .save_frame(&frame_image(captured_at_epoch, Some(screen_id)), &[])
+        .await
+        .unwrap();
+    image
+}
+
+async fn sessions_are_saved_and_listed(repo: &impl Repository) {
+    let first = blank_frame(repo, 1_000, 1).await;
+    let other = blank_frame(repo, 1_001, 2).await;
+    let second = blank_frame(repo, 1_002, 1).await;
+    let last_other = blank_frame(repo, 1_003, 2).await;
+    let third = blank_frame(repo, 1_004, 1).await;
+    let outside = blank_frame(repo, 1_005, 1).await;
+
+    let session = repo
+        .save_session(&EntitySession::new(0, 1, first.id, second.id, first.id))
+        .await
+        .unwrap();
+    assert_ne!(session.id, 0);
+    assert_eq!(
+        (session.started_at_epoch, session.ended_at_epoch),
+        (1_000, 1_002)
+    );
+    let extended = repo
+        .save_session(&EntitySession {
+            last_image_id: third.id,
+            representative_image_id: second.id,
+            ..session.clone()
+        })
+        .await
+        .unwrap();
+    assert_eq!(extended.id, session.id);
+    assert_eq!(extended.ended_at_epoch, 1_004);
+    let earlier = repo
+        .save_session(&EntitySession::new(0, 2, other.id, other.id, other.id))
+        .await
+        .unwrap();
+    let later = repo
+        .save_session(&EntitySession::new(
+            0,
+            2,
+            last_other.id,
+            last_other.id,
+            last_other.id,
+        ))
+        .await
+        .unwrap();
+    assert!(repo
+        .save_session(&EntitySession::new(999, 1, first.id, first.id, first.id))
+        .await
+        .is_err());
+
+    assert_eq!(
+        repo.get_last_sessions().await.unwrap(),
+        vec![extended.clone(), later.clone()]
+    );
+
+    let mut listed = vec![];
+    let mut after = None;
+    loop {
+        let options = TimelineOptions {
+            after,
+            ..TimelineOptions::new(2)
+        };
+        let page = repo.list_sessions(&options).await.unwrap();
+        let Some(last) = page.last() else {
+            break;
+        };
+        after = Some(last.cursor());
+        listed.extend(page);
+    }
+    assert_eq!(listed, vec![extended.clone(), earlier, later.clone()]);
+    let overlapping = TimelineOptions {
+        from_epoch: Some(1_002),
+        to_epoch: Some(1_003),
+        screen_id: Some(2),
+        ..TimelineOptions::new(10)
+    };
+    assert_eq!(repo.list_sessions(&overlapping).await.unwrap(), vec![later]);
+    let ended = TimelineOptions {
+        from_epoch: Some(1_004),
+        ..TimelineOptions::new(10)
+    };
+    assert_eq!(
+        repo.list_sessions(&ended).await.unwrap(),
+        vec![extended.clone()]
+    );
+
+    let of_images = repo
+        .get_sessions_of_images(&[third.id, other.id, outside.id, first.id])
+        .await
+        .unwrap();
+    let ids: Vec<(u32, u32)> = of_images
+        .iter()
+        .map(|(image_id, session)| (*image_id, session.id))
+        .collect();
+    assert_eq!(
+        ids,
+        vec![
+            (first.id, extended.id),
+            (other.id, listed[1].id),
+            (third.id, extended.id)
+        ]
+    );
+    assert_eq!(of_images[0].1, extended);
+}
+
+async fn delete_images_trims_sessions(repo: &impl Repository) {
+    let first = blank_frame(repo, 1_000, 1).await;
+    // within the ids of the session, but on another screen
+    let other = blank_frame(repo, 1_001, 2).await;
+    let middle = blank_frame(repo, 1_002, 1).await;
+    let last = blank_frame(repo, 1_004, 1).await;
+    let session = repo
+        .save_session(&EntitySession::new(0, 1, first.id, last.id, middle.id))
+        .await
+        .unwrap();
+    assert!(repo
+        .get_sessions_of_images(&[other.id])
+        .await
+        .unwrap()
+        .is_empty());
+
+    repo.delete_images(&[middle.id]).await.unwrap();
+    let trimmed = &repo.get_last_sessions().await.unwrap()[0];
+    assert_eq!(
+        (trimmed.first_image_id, trimmed.last_image_id),
+        (first.id, last.id)
+    );
+    assert_eq!(trimmed.representative_image_id, first.id);
+
+    repo.delete_images(&[last.id, other.id]).await.unwrap();
+    let trimmed = &repo.get_last_sessions().await.unwrap()[0];
+    assert_eq!(trimmed.id, session.id);
+    assert_eq!(
+        (trimmed.first_image_id, trimmed.last_image_id),
+        (first.id, first.id)
+    );
+    assert_eq!(trimmed.ended_at_epoch, 1_000);
+
+    repo.delete_images_captured_between(1_000, 1_000)
+        .await
+        .unwrap();
+    assert!(repo.get_last_sessions().await.unwrap().is_empty());
+    assert!(repo
+        .list_sessions(&TimelineOptions::new(10))
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+async fn stats_follow_saved_and_deleted_records(repo: &impl Repository) {
+    let stats = repo.get_stats().await.unwrap();
+    assert_eq!((stats.images, stats.texts), (0, 0));
+    assert!(stats.frame_counts.is_empty());
+
+    let (images, _) = unchanged_frames(repo, &["kept", "again"], 3).await;
+    repo.save_frame(&frame_image(DAY + 5, Some(2)), &[text("later", 0, 0)])
+        .await
+        .unwrap();
+    let (legacy, _) = repo
+        .save_frame(&frame_image(DAY + 10, None), &[])
+        .await
+        .unwrap();
+    let count = |day: u64, screen_id: Option<u32>, frames: u64| FrameCount {
+        day,
+        screen_id,
+        frames,
+    };
+    let stats = repo.get_stats().await.unwrap();
+    assert_eq!((stats.images, stats.texts), (5, 3));
+    assert_eq!(
+        stats.frame_counts,
+        vec![
+            count(0, Some(1), 3),
+            count(1, None, 1),
+            count(1, Some(2), 1)
+        ]
+    );
+
+    repo.replace_texts(images[1].id, &[text("new", 0, 0)])
+        .await
+        .unwrap();
+    repo.delete_images(&[images[0].id, legacy.id])
+        .await
+        .unwrap();
+    let stats = repo.get_stats().await.unwrap();
+    // the texts of the first image remain in the last one, the second image has its own
+    assert_eq!((stats.images, stats.texts), (3, 4));
+    assert_eq!(
+        stats.frame_counts,
+        vec![count(0, Some(1), 2), count(1, Some(2), 1)]
+    );
+}
+
+async fn maintenance_keeps_records(repo: &impl Repository) {
+    let mut image_ids = vec![];
+    for i in 0..20 {
+        let (image, _) = repo
+            .save_frame(
+                &frame_image(DAY + i, Some(1)),
+                &[text(&format!("word{}", i), 0, 0), text("shared", 50, 0)],
+            )
+            .await
+            .unwrap();
+        image_ids.push(image.id);
+    }
+    repo.delete_images(&image_ids[..10]).await.unwrap();
+
+    let report = repo.maintain().await.unwrap();
+    let stats = repo.get_stats().await.unwrap();
+    assert_eq!(report.database_bytes, stats.database_bytes);
+    assert_eq!(stats.images, 10);
+    assert_eq!(search_image_ids(repo, "shared").await.len(), 10);
+    assert!(search_image_ids(repo, "word3").await.is_empty());
+    assert_eq!(search_image_ids(repo, "word15").await, vec![image_ids[15]]);
+    // running again finds nothing left to do
+    repo.maintain().await.unwrap();
+}
@@ -0,0 +1,134 @@
+//! Typo tolerant matching shared by the repositories: candidates sharing trigrams with the query
+//! are re-ranked by their edit distance to the query terms.
+
+use std::collections::HashMap;
+
+use super::{EntityText, SearchOptions, SearchPage, SearchResult};
+
+/// Upper bound of the candidate texts re-ranked for a fuzzy search.
+pub const MAX_CANDIDATES: u32 = 2000;
+
+/// A text sharing trigrams with the query, along with its image.
+pub struct Candidate {
+    pub text: EntityText,
+    pub captured_at_epoch: u64,
+    pub screen_id: Option<u32>,
+}
+
+/// Lowercased terms of the query.
+pub fn terms(query: &str) -> Vec<String> {
+    query
+        .split_whitespace()
+        .map(|it| it.to_lowercase())
+        .collect()
+}
+
+/// Distinct lowercased trigrams of the terms of the text.
+pub fn trigrams(text: &str) -> Vec<String> {
+    let mut trigrams: Vec<String> = terms(text)
+        .iter()
+        .flat_map(|term| {
+            let chars: Vec<char> = term.chars().collect();
+            chars
+                .windows(3)
+                .map(|it| it.iter().collect::<String>())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    trigrams.sort();
+    trigrams.dedup();
+    trigrams
+}
+
+/// FTS5 query matching any trigram of the query terms, `None` when no term is long enough to
+/// have a trigram.
+pub fn trigram_query(query: &str) -> Option<String> {
+    let trigrams = trigrams(query);
+    if trigrams.is_empty() {
+        return None;
+    }
+    let quoted: Vec<String> = trigrams
+        .iter()
+        .map(|it| format!("\"{}\"", it.replace('"', "\"\"")))
+        .collect();
+    Some(quoted.join(" OR "))
+}
+
+/// Edit distance tolerated for a term, about one typo per three characters.
+pub fn max_distance(term: &str) -> usize {
+    (term.chars().count() / 3).max(1)
+}
+
+/// Levenshtein distance between two strings, counted in characters.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// Distance of the text to its closest query term, `None` when too far from every term.
+pub fn distance(terms: &[String], text: &str) -> Option<usize> {
+    let text = text.to_lowercase();
+    terms
+        .iter()
+        .map(|term| (edit_distance(term, &text), max_distance(term)))
+        .filter(|(distance, max)| distance <= max)
+        .map(|(distance, _)| distance)
+        .min()
+}
+
+/// Keep the candidates close enough to the query, group them by image and rank the images by
+/// their closest text, blended with recency like the exact search.
+pub fn rank(options: &SearchOptions, candidates: Vec<Candidate>, now_epoch: u64) -> SearchPage {
+    let terms = terms(&options.text);
+    let mut results: Vec<SearchResult> = Vec::new();
+    let mut index_of_image: HashMap<u32, usize> = HashMap::new();
+    for candidate in candidates {
+        let Some(distance) = distance(&terms, &candidate.text.text) else {
+            continue;
+        };
+        let age_days = now_epoch.saturating_sub(candidate.captured_at_epoch) as f64 / 86400.0;
+        let score = distance as f64 + options.recency_weight * age_days;
+        match index_of_image.get(&candidate.text.image_id) {
+            Some(index) => {
+                let result = &mut results[*index];
+                result.score = result.score.min(score);
+                result.texts.push(candidate.text);
+            }
+            None => {
+                index_of_image.insert(candidate.text.image_id, results.len());
+                results.push(SearchResult::new(
+                    candidate.text.image_id,
+                    candidate.captured_at_epoch,
+                    candidate.screen_id,
+                    score,
+                    vec![candidate.text],
+                ));
+            }
+        }
+    }
+    results.sort_by(|a, b| {
+        a.score
+            .total_cmp(&b.score)
+            .then(a.image_id.cmp(&b.image_id))
+    });
+    for result in results.iter_mut() {
+        result.texts.sort_by_key(|it| it.id);
+    }
+    let total = results.len() as u64;
+    let results = results
+        .into_iter()
+        .skip(options.offset as usize)
+        .take(options.limit as usize)
+        .collect();
+    SearchPage { total, results }
+}
@@ -1,31 +1,134 @@
-#[cfg(feature = "in-memory")]
-use {async_trait::async_trait, tokio::sync::Mutex, super::{EntityImage, EntityText, Repository}};
+#[cfg(any(test, feature = "in-memory"))]
+use {
+    super::{
+        fuzzy, nearest_image, EntityEmbedding, EntityImage, EntitySession, EntityText, FrameCount,
+        MaintenanceReport, Repository, RepositoryStats, SearchOptions, SearchPage, SearchResult,
+        TextKind, TimelineCursor, TimelineDirection, TimelineOptions, MAX_OCCURRENCE_SPAN,
+    },
+    crate::ocr::MarkupBox,
+    async_trait::async_trait,
+    std::collections::BTreeMap,
+    tokio::sync::Mutex,
+};
 
-#[cfg(feature = "in-memory")]
+#[cfg(any(test, feature = "in-memory"))]
 pub struct InMemoryRepository {
     images: Mutex<Vec<EntityImage>>,
     texts: Mutex<Vec<EntityText>>,
+    embeddings: Mutex<Vec<EntityEmbedding>>,
+    sessions: Mutex<Vec<EntitySession>>,
 }
 
-#[cfg(feature = "in-memory")]
+#[cfg(any(test, feature = "in-memory"))]
 impl InMemoryRepository {
     pub fn new() -> Self {
         Self {
             images: Mutex::new(vec![]),
             texts: Mutex::new(vec![]),
+            embeddings: Mutex::new(vec![]),
+            sessions: Mutex::new(vec![]),
         }
     }
+
+    async fn delete_images_where<F>(&self, predicate: F) -> anyhow::Result<Vec<EntityImage>>
+    where
+        F: Fn(&EntityImage) -> bool,
+    {
+        let mut images = self.images.lock().await;
+        let mut texts = self.texts.lock().await;
+        let mut embeddings = self.embeddings.lock().await;
+        let mut sessions = self.sessions.lock().await;
+        let (deleted, kept): (Vec<EntityImage>, Vec<EntityImage>) =
+            images.drain(..).partition(|it| predicate(it));
+        *images = kept;
+        embeddings.retain(|it| deleted.iter().all(|image| image.id != it.image_id));
+        // sessions are trimmed to their remaining images, like occurrences
+        sessions.retain_mut(|session| {
+            let remaining = images
+                .iter()
+                .filter(|it| in_session(it, session))
+                .map(|it| it.id)
+                .collect::<Vec<_>>();
+            match (remaining.iter().min(), remaining.iter().max()) {
+                (Some(first), Some(last)) => {
+                    session.first_image_id = *first;
+                    session.last_image_id = *last;
+                    if !remaining.contains(&session.representative_image_id) {
+                        session.representative_image_id = *first;
+                    }
+                    true
+                }
+                _ => false,
+            }
+        });
+        // occurrences are trimmed to the remaining images they were seen in
+        texts.retain_mut(|text| {
+            let Some(first) = images
+                .iter()
+                .chain(deleted.iter())
+                .find(|it| it.id == text.image_id)
+            else {
+                return true;
+            };
+            let remaining = images
+                .iter()
+                .filter(|it| {
+                    it.id == text.image_id
+                        || (first.screen_id.is_some() && it.screen_id == first.screen_id)
+                })
+                .filter(|it| it.id >= text.image_id && it.id <= text.last_image_id)
+                .map(|it| it.id)
+                .collect::<Vec<_>>();
+            match (remaining.iter().min(), remaining.iter().max()) {
+                (Some(next), Some(last)) => {
+                    text.image_id = *next;
+                    text.last_image_id = *last;
+                    true
+                }
+                _ => false,
+            }
+        });
+        Ok(deleted)
+    }
 }
 
 // implement Repository trait for InMemoryRepository
-#[cfg(feature = "in-memory")]
+#[cfg(any(test, feature = "in-memory"))]
 #[async_trait]
 impl Repository for InMemoryRepository {
-    async fn save_image(&self, entity: &EntityImage) -> anyhow::Result<EntityImage> {
-        let mut entity = entity.clone();
-        let length = self.images.lock().await.len();
-        entity.id = length as u32;
-        Ok(entity)
+    async fn save_frame_continuing(
+        &self,
+        image: &EntityImage,
+        texts: &[EntityText],
+        continued: &[u32],
+    ) -> anyhow::Result<(EntityImage, Vec<EntityText>)> {
+        // hold both locks so readers never see the image without its texts
+        let mut images = self.images.lock().await;
+        let mut guard = self.texts.lock().await;
+        let mut image = image.clone();
+        image.id = next_id(images.iter().map(|it| it.id));
+        let previous = images
+            .iter()
+            .filter(|it| image.screen_id.is_some() && it.screen_id == image.screen_id)
+            .map(|it| it.id)
+            .max();
+        images.push(image.clone());
+
+        let mut new_texts = texts.to_vec();
+        let mut extended = vec![];
+        for text in guard.iter_mut().filter(|it| continued.contains(&it.id)) {
+            if previous == Some(text.last_image_id)
+                && image.id - text.image_id <= MAX_OCCURRENCE_SPAN
+            {
+                text.last_image_id = image.id;
+                extended.push(seen_in(text, image.id));
+            } else {
+                new_texts.push(text.clone());
+            }
+        }
+        let mut result = insert_texts(&mut guard, image.id, &new_texts);
+        result.extend(extended);
+        Ok((image, result))
     }
 
     async fn get_image_by_id(&self, id: u32) -> anyhow::Result<EntityImage> {
@@ -37,25 +140,6 @@ impl Repository for InMemoryRepository {
         Ok(entity.clone())
     }
 
-    async fn save_text(&self, entity: &EntityText) -> anyhow::Result<EntityText> {
-        let mut entity = entity.clone();
-        let mut guard = self.texts.lock().await;
-        entity.id = guard.len() as u32;
-        guard.push(entity.clone());
-        Ok(entity)
-    }
-
-    async fn save_texts(&self, entities: &[EntityText]) -> anyhow::Result<Vec<EntityText>> {
-        let mut result = Vec::new();
-        for entity in entities.iter() {
-            let mut guard = self.texts.lock().await;
-            let mut entity = entity.clone();
-            entity.id = guard.len() as u32;
-            guard.push(entity.clone());
-            result.push(entity);
-        }
-        Ok(result)
-    }
     async fn get_text_by_id(&self, id: u32) -> anyhow::Result<EntityText> {
         let entity = self
             .texts
@@ -68,16 +152,574 @@ impl Repository for InMemoryRepository {
         Ok(entity)
     }
 
-    /// it's not a real full text search, just a simple filter for demo
-    async fn full_text_search(&self, text: &str) -> anyhow::Result<Vec<EntityText>> {
-        let entities = self
-            .texts
+    async fn get_texts_by_image_ids(&self, image_ids: &[u32]) -> anyhow::Result<Vec<EntityText>> {
+        let images = self.images.lock().await;
+        let texts = self.texts.lock().await;
+        let mut entities: Vec<EntityText> = images
+            .iter()
+            .filter(|it| image_ids.contains(&it.id))
+            .flat_map(|image| {
+                texts
+                    .iter()
+                    .filter(|text| seen(text, image, &images))
+                    .map(|text| seen_in(text, image.id))
+            })
+            .collect();
+        entities.sort_by_key(|it| (it.image_id, it.top, it.left, it.id));
+        Ok(entities)
+    }
+
+    /// Emulates the full text search of SQLite: texts are split into lowercased alphanumeric
+    /// tokens, every term of the query must match a token, and a term ending with `*` matches
+    /// as a prefix. Shorter texts rank first, standing in for bm25. Operators like `OR` are not
+    /// supported.
+    async fn search(&self, options: &SearchOptions) -> anyhow::Result<SearchPage> {
+        let images = self.images.lock().await;
+        let texts = self.texts.lock().await;
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        // a text matches the range from the first frame of its occurrence in it
+        let framed = texts.iter().filter_map(|text| {
+            let image = images.iter().find(|it| it.id == text.image_id)?;
+            let frame = first_frame_from(&images, image, text.last_image_id, options.from_epoch)?;
+            matches_timeline(frame, None, options.to_epoch, options.screen_id)
+                .then(|| (seen_in(text, frame.id), frame))
+        });
+        let trigrams = fuzzy::trigrams(&options.text);
+        if options.fuzzy && !trigrams.is_empty() {
+            // candidates share a trigram with the query, like in the trigram index
+            let candidates = framed
+                .filter(|(text, _)| {
+                    let text = text.text.to_lowercase();
+                    trigrams
+                        .iter()
+                        .any(|trigram| text.contains(trigram.as_str()))
+                })
+                .map(|(text, frame)| fuzzy::Candidate {
+                    text,
+                    captured_at_epoch: frame.captured_at_epoch,
+                    screen_id: frame.screen_id,
+                })
+                .collect();
+            return Ok(fuzzy::rank(options, candidates, now));
+        }
+        let mut matched_by_frame: BTreeMap<u32, (&EntityImage, Vec<EntityText>)> = BTreeMap::new();
+        for (text, frame) in framed.filter(|(it, _)| matches(&options.text, &it.text)) {
+            matched_by_frame
+                .entry(frame.id)
+                .or_insert((frame, vec![]))
+                .1
+                .push(text);
+        }
+        let mut results: Vec<SearchResult> = matched_by_frame
+            .into_values()
+            .map(|(image, mut matched)| {
+                matched.sort_by_key(|it| it.id);
+                let relevance = matched
+                    .iter()
+                    .map(|it| -1.0 / tokens(&it.text).len() as f64)
+                    .fold(f64::MAX, f64::min);
+                let age_days = now.saturating_sub(image.captured_at_epoch) as f64 / 86400.0;
+                SearchResult::new(
+                    image.id,
+                    image.captured_at_epoch,
+                    image.screen_id,
+                    relevance + options.recency_weight * age_days,
+                    matched,
+                )
+            })
+            .collect();
+        results.sort_by(|a, b| {
+            a.score
+                .total_cmp(&b.score)
+                .then(a.image_id.cmp(&b.image_id))
+        });
+        let total = results.len() as u64;
+        let results = results
+            .into_iter()
+            .skip(options.offset as usize)
+            .take(options.limit as usize)
+            .collect();
+        Ok(SearchPage { total, results })
+    }
+
+    async fn scan_images(
+        &self,
+        after_id: u32,
+        from_epoch: Option<u64>,
+        to_epoch: Option<u64>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<EntityImage>> {
+        let mut entities: Vec<EntityImage> = self
+            .images
+            .lock()
+            .await
+            .iter()
+            .filter(|it| it.id > after_id)
+            .filter(|it| matches_timeline(it, from_epoch, to_epoch, None))
+            .cloned()
+            .collect();
+        entities.sort_by_key(|it| it.id);
+        entities.truncate(limit as usize);
+        Ok(entities)
+    }
+
+    async fn list_images(&self, options: &TimelineOptions) -> anyhow::Result<Vec<EntityImage>> {
+        let mut entities: Vec<EntityImage> = self
+            .images
             .lock()
             .await
             .iter()
-            .filter(|it| it.text.contains(text))
+            .filter(|it| is_after(TimelineCursor::of(it), options.after))
+            .filter(|it| {
+                matches_timeline(it, options.from_epoch, options.to_epoch, options.screen_id)
+            })
             .cloned()
             .collect();
+        entities.sort_by_key(TimelineCursor::of);
+        entities.truncate(options.limit as usize);
+        Ok(entities)
+    }
+
+    async fn get_adjacent_image(
+        &self,
+        image_id: u32,
+        direction: TimelineDirection,
+        screen_id: Option<u32>,
+    ) -> anyhow::Result<Option<EntityImage>> {
+        let guard = self.images.lock().await;
+        let origin = guard
+            .iter()
+            .find(|it| it.id == image_id)
+            .map(TimelineCursor::of)
+            .ok_or(anyhow::anyhow!("not found"))?;
+        let candidates = guard
+            .iter()
+            .filter(|it| matches_timeline(it, None, None, screen_id));
+        let adjacent = match direction {
+            TimelineDirection::Previous => candidates
+                .filter(|it| TimelineCursor::of(it) < origin)
+                .max_by_key(|it| TimelineCursor::of(it)),
+            TimelineDirection::Next => candidates
+                .filter(|it| TimelineCursor::of(it) > origin)
+                .min_by_key(|it| TimelineCursor::of(it)),
+        };
+        Ok(adjacent.cloned())
+    }
+
+    async fn get_nearest_image(
+        &self,
+        epoch: u64,
+        screen_id: Option<u32>,
+    ) -> anyhow::Result<Option<EntityImage>> {
+        let guard = self.images.lock().await;
+        let candidates = guard
+            .iter()
+            .filter(|it| matches_timeline(it, None, None, screen_id));
+        let before = candidates
+            .clone()
+            .filter(|it| it.captured_at_epoch <= epoch)
+            .max_by_key(|it| TimelineCursor::of(it));
+        let after = candidates
+            .filter(|it| it.captured_at_epoch > epoch)
+            .min_by_key(|it| TimelineCursor::of(it));
+        Ok(nearest_image(epoch, before.cloned(), after.cloned()))
+    }
+
+    async fn delete_images(&self, image_ids: &[u32]) -> anyhow::Result<Vec<EntityImage>> {
+        self.delete_images_where(|it| image_ids.contains(&it.id))
+            .await
+    }
+
+    async fn delete_images_captured_between(
+        &self,
+        from_epoch: u64,
+        to_epoch: u64,
+    ) -> anyhow::Result<Vec<EntityImage>> {
+        self.delete_images_where(|it| (from_epoch..=to_epoch).contains(&it.captured_at_epoch))
+            .await
+    }
+
+    async fn replace_texts(
+        &self,
+        image_id: u32,
+        entities: &[EntityText],
+    ) -> anyhow::Result<Vec<EntityText>> {
+        let images = self.images.lock().await;
+        let mut guard = self.texts.lock().await;
+        isolate_texts(&mut guard, &images, image_id, |_| true);
+        guard.retain(|it| it.image_id != image_id);
+        Ok(insert_texts(&mut guard, image_id, entities))
+    }
+
+    async fn replace_texts_in_region(
+        &self,
+        image_id: u32,
+        region: &MarkupBox,
+        entities: &[EntityText],
+    ) -> anyhow::Result<Vec<EntityText>> {
+        let images = self.images.lock().await;
+        let mut guard = self.texts.lock().await;
+        let inside = |it: &EntityText| {
+            it.kind == TextKind::Ocr
+                && it.left >= region.left
+                && it.top >= region.top
+                && it.left + it.width <= region.left + region.width
+                && it.top + it.height <= region.top + region.height
+        };
+        isolate_texts(&mut guard, &images, image_id, inside);
+        guard.retain(|it| it.image_id != image_id || !inside(it));
+        Ok(insert_texts(&mut guard, image_id, entities))
+    }
+
+    async fn replace_embeddings(
+        &self,
+        image_id: u32,
+        embeddings: &[EntityEmbedding],
+    ) -> anyhow::Result<()> {
+        let mut guard = self.embeddings.lock().await;
+        guard.retain(|it| it.image_id != image_id);
+        guard.extend(embeddings.iter().map(|it| EntityEmbedding {
+            image_id,
+            ..it.clone()
+        }));
+        Ok(())
+    }
+
+    async fn get_embeddings(
+        &self,
+        model: &str,
+        options: &SearchOptions,
+        limit: u32,
+    ) -> anyhow::Result<Vec<(EntityEmbedding, EntityImage)>> {
+        let images = self.images.lock().await;
+        let texts = self.texts.lock().await;
+        let guard = self.embeddings.lock().await;
+        let mut latest: Vec<&EntityEmbedding> =
+            guard.iter().filter(|it| it.model == model).collect();
+        // stable, keeping the lines of an image in insertion order
+        latest.sort_by_key(|it| std::cmp::Reverse(it.image_id));
+        let mut result = vec![];
+        for embedding in latest {
+            if result.len() == limit as usize {
+                break;
+            }
+            let Some(image) = images.iter().find(|it| it.id == embedding.image_id) else {
+                continue;
+            };
+            // a line lasts until the last frame of its texts
+            let last_image_id = texts
+                .iter()
+                .filter(|it| embedding.text_ids.contains(&it.id))
+                .map(|it| it.last_image_id)
+                .max()
+                .unwrap_or(image.id);
+            let Some(frame) = first_frame_from(&images, image, last_image_id, options.from_epoch)
+            else {
+                continue;
+            };
+            if matches_timeline(frame, None, options.to_epoch, options.screen_id) {
+                result.push((embedding.clone(), frame.clone()));
+            }
+        }
+        Ok(result)
+    }
+
+    async fn save_session(&self, session: &EntitySession) -> anyhow::Result<EntitySession> {
+        let images = self.images.lock().await;
+        let mut guard = self.sessions.lock().await;
+        let mut session = session.clone();
+        if session.id == 0 {
+            session.id = next_id(guard.iter().map(|it| it.id));
+            guard.push(session.clone());
+        } else {
+            let stored = guard
+                .iter_mut()
+                .find(|it| it.id == session.id)
+                .ok_or(anyhow::anyhow!("not found"))?;
+            *stored = session.clone();
+        }
+        with_epochs(&session, &images)
+    }
+
+    async fn get_last_sessions(&self) -> anyhow::Result<Vec<EntitySession>> {
+        let images = self.images.lock().await;
+        let guard = self.sessions.lock().await;
+        let mut last: Vec<&EntitySession> = vec![];
+        for session in guard.iter() {
+            match last.iter_mut().find(|it| it.screen_id == session.screen_id) {
+                Some(it) if it.last_image_id < session.last_image_id => *it = session,
+                Some(_) => {}
+                None => last.push(session),
+            }
+        }
+        last.sort_by_key(|it| it.screen_id);
+        last.into_iter()
+            .map(|it| with_epochs(it, &images))
+            .collect()
+    }
+
+    async fn list_sessions(&self, options: &TimelineOptions) -> anyhow::Result<Vec<EntitySession>> {
+        let images = self.images.lock().await;
+        let guard = self.sessions.lock().await;
+        let mut entities = guard
+            .iter()
+            .map(|it| with_epochs(it, &images))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        entities.retain(|it| {
+            is_after(it.cursor(), options.after)
+                && it.ended_at_epoch >= options.from_epoch.unwrap_or(0)
+                && it.started_at_epoch <= options.to_epoch.unwrap_or(u64::MAX)
+                && (options.screen_id.is_none() || options.screen_id == Some(it.screen_id))
+        });
+        entities.sort_by_key(EntitySession::cursor);
+        entities.truncate(options.limit as usize);
         Ok(entities)
     }
+
+    async fn get_sessions_of_images(
+        &self,
+        image_ids: &[u32],
+    ) -> anyhow::Result<Vec<(u32, EntitySession)>> {
+        let images = self.images.lock().await;
+        let guard = self.sessions.lock().await;
+        let mut result = vec![];
+        for image in images.iter().filter(|it| image_ids.contains(&it.id)) {
+            if let Some(session) = guard.iter().find(|it| in_session(image, it)) {
+                result.push((image.id, with_epochs(session, &images)?));
+            }
+        }
+        result.sort_by_key(|it| it.0);
+        Ok(result)
+    }
+
+    /// Counts the records, there is no database on disk.
+    async fn get_stats(&self) -> anyhow::Result<RepositoryStats> {
+        let images = self.images.lock().await;
+        let texts = self.texts.lock().await;
+        let mut frame_counts: Vec<FrameCount> = vec![];
+        for image in images.iter() {
+            let day = image.captured_at_epoch / 86400;
+            match frame_counts
+                .iter_mut()
+                .find(|it| it.day == day && it.screen_id == image.screen_id)
+            {
+                Some(count) => count.frames += 1,
+                None => frame_counts.push(FrameCount {
+                    day,
+                    screen_id: image.screen_id,
+                    frames: 1,
+                }),
+            }
+        }
+        // images without a screen sort first, like their -1 of the SQL repositories
+        frame_counts.sort_by_key(|it| (it.day, it.screen_id));
+        Ok(RepositoryStats {
+            images: images.len() as u64,
+            texts: texts.len() as u64,
+            database_bytes: 0,
+            frame_counts,
+        })
+    }
+
+    async fn backup_database(&self, _path: &str) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "an in-memory repository has no database to back up"
+        ))
+    }
+
+    async fn maintain(&self) -> anyhow::Result<MaintenanceReport> {
+        Ok(MaintenanceReport::default())
+    }
+}
+
+/// Whether the image is one of the images of the session.
+#[cfg(any(test, feature = "in-memory"))]
+fn in_session(image: &EntityImage, session: &EntitySession) -> bool {
+    image.screen_id == Some(session.screen_id)
+        && (session.first_image_id..=session.last_image_id).contains(&image.id)
+}
+
+/// The session with the capture times of its first and last images.
+#[cfg(any(test, feature = "in-memory"))]
+fn with_epochs(session: &EntitySession, images: &[EntityImage]) -> anyhow::Result<EntitySession> {
+    let epoch_of = |id: u32| {
+        images
+            .iter()
+            .find(|it| it.id == id)
+            .map(|it| it.captured_at_epoch)
+            .ok_or(anyhow::anyhow!(
+                "image {} of session {} not found",
+                id,
+                session.id
+            ))
+    };
+    Ok(EntitySession {
+        started_at_epoch: epoch_of(session.first_image_id)?,
+        ended_at_epoch: epoch_of(session.last_image_id)?,
+        ..session.clone()
+    })
+}
+
+/// Next id after the greatest one in use, like an integer primary key of SQLite.
+#[cfg(any(test, feature = "in-memory"))]
+fn next_id(ids: impl Iterator<Item = u32>) -> u32 {
+    ids.max().unwrap_or(0) + 1
+}
+
+#[cfg(any(test, feature = "in-memory"))]
+fn insert_texts(
+    texts: &mut Vec<EntityText>,
+    image_id: u32,
+    entities: &[EntityText],
+) -> Vec<EntityText> {
+    let first_id = next_id(texts.iter().map(|it| it.id));
+    let mut result = Vec::new();
+    for (offset, entity) in entities.iter().enumerate() {
+        let mut entity = entity.clone();
+        entity.id = first_id + offset as u32;
+        entity.image_id = image_id;
+        entity.last_image_id = image_id;
+        texts.push(entity.clone());
+        result.push(entity);
+    }
+    result
+}
+
+/// Whether the occurrence spans the image, on the screen of the image.
+#[cfg(any(test, feature = "in-memory"))]
+fn seen(text: &EntityText, image: &EntityImage, images: &[EntityImage]) -> bool {
+    if text.image_id == image.id {
+        return true;
+    }
+    text.image_id < image.id
+        && image.id <= text.last_image_id
+        && image.screen_id.is_some()
+        && images
+            .iter()
+            .any(|it| it.id == text.image_id && it.screen_id == image.screen_id)
+}
+
+/// The occurrence as listed among the texts of the image.
+#[cfg(any(test, feature = "in-memory"))]
+fn seen_in(text: &EntityText, image_id: u32) -> EntityText {
+    EntityText {
+        image_id,
+        ..text.clone()
+    }
+}
+
+/// Split the occurrences seen in the image and in other images too, among the texts matching
+/// the predicate, so that those seen in the image are only seen in it.
+#[cfg(any(test, feature = "in-memory"))]
+fn isolate_texts<F>(
+    texts: &mut Vec<EntityText>,
+    images: &[EntityImage],
+    image_id: u32,
+    predicate: F,
+) where
+    F: Fn(&EntityText) -> bool,
+{
+    let Some(image) = images.iter().find(|it| it.id == image_id) else {
+        return;
+    };
+    let screen = images
+        .iter()
+        .filter(|it| image.screen_id.is_some() && it.screen_id == image.screen_id);
+    let previous = screen
+        .clone()
+        .map(|it| it.id)
+        .filter(|it| *it < image_id)
+        .max();
+    let next = screen.map(|it| it.id).filter(|it| *it > image_id).min();
+
+    let mut first_id = next_id(texts.iter().map(|it| it.id));
+    let mut rest = vec![];
+    for text in texts.iter_mut() {
+        if text.image_id == text.last_image_id || !seen(text, image, images) || !predicate(text) {
+            continue;
+        }
+        // a span is made of images of the screen, the neighbours exist within it
+        let previous = previous.unwrap_or(text.image_id);
+        let next = next.unwrap_or(text.last_image_id);
+        if text.image_id < image_id && image_id < text.last_image_id {
+            rest.push(EntityText {
+                id: first_id,
+                image_id: next,
+                ..text.clone()
+            });
+            first_id += 1;
+        }
+        if text.image_id < image_id {
+            text.last_image_id = previous;
+        } else {
+            text.image_id = next;
+        }
+    }
+    texts.extend(rest);
+}
+
+/// Lowercased alphanumeric tokens, like the default tokenizer of FTS5.
+#[cfg(any(test, feature = "in-memory"))]
+fn tokens(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|it| !it.is_empty())
+        .map(|it| it.to_lowercase())
+        .collect()
+}
+
+/// Whether every term of the query matches a token of the text.
+#[cfg(any(test, feature = "in-memory"))]
+fn matches(query: &str, text: &str) -> bool {
+    let text_tokens = tokens(text);
+    let terms: Vec<&str> = query.split_whitespace().collect();
+    !terms.is_empty()
+        && terms.iter().all(|term| {
+            let prefix = term.ends_with('*');
+            tokens(term).iter().all(|token| {
+                text_tokens
+                    .iter()
+                    .any(|it| it == token || (prefix && it.starts_with(token.as_str())))
+            })
+        })
+}
+
+/// The first frame captured from `from_epoch` of the occurrence first seen in the image and last
+/// seen in the image of `last_image_id`, `None` when it ends before.
+#[cfg(any(test, feature = "in-memory"))]
+fn first_frame_from<'a>(
+    images: &'a [EntityImage],
+    image: &'a EntityImage,
+    last_image_id: u32,
+    from_epoch: Option<u64>,
+) -> Option<&'a EntityImage> {
+    match from_epoch {
+        Some(from) if image.captured_at_epoch < from => images
+            .iter()
+            .filter(|it| it.id > image.id && it.id <= last_image_id)
+            .filter(|it| it.screen_id.is_some() && it.screen_id == image.screen_id)
+            .filter(|it| it.captured_at_epoch >= from)
+            .min_by_key(|it| it.id),
+        _ => Some(image),
+    }
+}
+
+#[cfg(any(test, feature = "in-memory"))]
+fn matches_timeline(
+    image: &EntityImage,
+    from_epoch: Option<u64>,
+    to_epoch: Option<u64>,
+    screen_id: Option<u32>,
+) -> bool {
+    image.captured_at_epoch >= from_epoch.unwrap_or(0)
+        && image.captured_at_epoch <= to_epoch.unwrap_or(u64::MAX)
+        && (screen_id.is_none() || image.screen_id == screen_id)
+}
+
+/// Whether the cursor comes after `after`, any does when there is none.
+#[cfg(any(test, feature = "in-memory"))]
+fn is_after<T: PartialOrd>(cursor: T, after: Option<T>) -> bool {
+    match after {
+        Some(after) => cursor > after,
+        None => true,
+    }
 }
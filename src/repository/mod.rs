@@ -1,6 +1,14 @@
+use std::{str::FromStr, sync::Arc};
+
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+
+use crate::{ocr::MarkupBox, snippet::Snippet};
+#[cfg(test)]
+mod conformance;
+pub mod fuzzy;
 pub mod in_memory;
+pub mod postgres;
 pub mod sqlite;
 
 #[derive(Debug, Clone)]
@@ -9,6 +17,8 @@ pub struct EntityImage {
     pub archive_type: String,
     pub archive_info: String,
     pub captured_at_epoch: u64,
+    /// The screen the image was captured from, unknown for images recorded by older versions.
+    pub screen_id: Option<u32>,
 }
 
 impl EntityImage {
@@ -17,31 +27,83 @@ impl EntityImage {
         archive_type: String,
         archive_info: String,
         captured_at_epoch: u64,
+        screen_id: Option<u32>,
     ) -> Self {
         Self {
             id,
             archive_type,
             archive_info,
             captured_at_epoch,
+            screen_id,
         }
     }
 }
 
+/// Where the content of a text comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextKind {
+    /// A word recognized by OCR.
+    Ocr,
+    /// The payload of a decoded QR code.
+    QrCode,
+    /// The payload of a decoded barcode.
+    Barcode,
+}
+
+impl TextKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TextKind::Ocr => "ocr",
+            TextKind::QrCode => "qr_code",
+            TextKind::Barcode => "barcode",
+        }
+    }
+}
+
+impl FromStr for TextKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "ocr" => Ok(TextKind::Ocr),
+            "qr_code" => Ok(TextKind::QrCode),
+            "barcode" => Ok(TextKind::Barcode),
+            _ => Err(anyhow::anyhow!("unknown text kind `{}`", s)),
+        }
+    }
+}
+
+/// Upper bound of `last_image_id - image_id` of a text, a text seen longer is stored again.
+/// Looking up the texts of an image only scans the texts first seen within this many ids.
+pub const MAX_OCCURRENCE_SPAN: u32 = 4096;
+
+/// An occurrence of a text, seen at the same position in consecutive images of a screen from
+/// `image_id` to `last_image_id`.
+///
+/// Texts listed by image, see `Repository::get_texts_by_image_ids`, carry the listed image as
+/// `image_id`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EntityText {
     pub id: u32,
     pub image_id: u32,
+    pub kind: TextKind,
     pub text: String,
     pub left: u32,
     pub top: u32,
     pub width: u32,
     pub height: u32,
+    /// The last image the text was seen in, `image_id` for a text of a single image.
+    #[serde(default)]
+    pub last_image_id: u32,
 }
 
 impl EntityText {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: u32,
         image_id: u32,
+        kind: TextKind,
         text: String,
         left: u32,
         top: u32,
@@ -51,13 +113,23 @@ impl EntityText {
         Self {
             id,
             image_id,
+            kind,
             text,
             left,
             top,
             width,
             height,
+            last_image_id: image_id,
         }
     }
+
+    /// Whether the text is at the same position as the other one, with the same content.
+    pub fn same_as(&self, other: &EntityText) -> bool {
+        self.kind == other.kind
+            && self.text == other.text
+            && (self.left, self.top, self.width, self.height)
+                == (other.left, other.top, other.width, other.height)
+    }
 }
 
 impl TryFrom<&crate::ocr::RecognizeItem> for EntityText {
@@ -68,6 +140,7 @@ impl TryFrom<&crate::ocr::RecognizeItem> for EntityText {
         Ok(Self::new(
             0,
             0,
+            TextKind::Ocr,
             value.text,
             value.markup.left,
             value.markup.top,
@@ -77,12 +150,518 @@ impl TryFrom<&crate::ocr::RecognizeItem> for EntityText {
     }
 }
 
+/// Parameters of `Repository::search`.
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    /// Full text search query.
+    pub text: String,
+    /// Maximum number of images in the page.
+    pub limit: u32,
+    /// Number of ranked images skipped before the page.
+    pub offset: u32,
+    /// Penalty per day of age added to the relevance score, 0 ranks by relevance only.
+    pub recency_weight: f64,
+    /// Only images captured at or after this epoch.
+    pub from_epoch: Option<u64>,
+    /// Only images captured at or before this epoch.
+    pub to_epoch: Option<u64>,
+    /// Only images captured from this screen.
+    pub screen_id: Option<u32>,
+    /// Tolerate typos and OCR errors, ranking by edit distance instead of relevance.
+    pub fuzzy: bool,
+    /// How the text is matched, `Repository::search` only matches keywords.
+    pub mode: SearchMode,
+    /// Return the best image of each session instead of every image, `Repository::search`
+    /// ignores it.
+    pub group_by_session: bool,
+}
+
+impl SearchOptions {
+    pub fn new(text: String, limit: u32, offset: u32, recency_weight: f64) -> Self {
+        Self {
+            text,
+            limit,
+            offset,
+            recency_weight,
+            from_epoch: None,
+            to_epoch: None,
+            screen_id: None,
+            fuzzy: false,
+            mode: SearchMode::Keyword,
+            group_by_session: false,
+        }
+    }
+}
+
+/// How a search matches its text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchMode {
+    /// The words of the query, with the full text index.
+    #[default]
+    Keyword,
+    /// Lines similar in meaning to the query, with the embeddings of the lines.
+    Semantic,
+    /// Both, their rankings are merged.
+    Hybrid,
+}
+
+impl FromStr for SearchMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "keyword" => Ok(SearchMode::Keyword),
+            "semantic" => Ok(SearchMode::Semantic),
+            "hybrid" => Ok(SearchMode::Hybrid),
+            _ => Err(anyhow::anyhow!("unknown search mode `{}`", s)),
+        }
+    }
+}
+
+/// Embedding of a line of text by a model, stored with the image the line first appears in.
+#[derive(Debug, Clone)]
+pub struct EntityEmbedding {
+    /// The image the line was embedded in, searches report the first image of the line in
+    /// their time range instead.
+    #[allow(dead_code)]
+    pub image_id: u32,
+    /// Name of the model, see `crate::embedding::Embedder::model`.
+    pub model: String,
+    /// The texts of the line.
+    pub text_ids: Vec<u32>,
+    pub vector: Vec<f32>,
+}
+
+impl EntityEmbedding {
+    pub fn new(image_id: u32, model: String, text_ids: Vec<u32>, vector: Vec<f32>) -> Self {
+        Self {
+            image_id,
+            model,
+            text_ids,
+            vector,
+        }
+    }
+}
+
+/// A session of activity on a screen, made of the images of the screen from `first_image_id`
+/// to `last_image_id`, see `crate::session::Segmenter`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntitySession {
+    pub id: u32,
+    pub screen_id: u32,
+    pub first_image_id: u32,
+    pub last_image_id: u32,
+    /// The image standing for the session, one of its images.
+    pub representative_image_id: u32,
+    /// Capture time of the first image, filled in by the repository.
+    pub started_at_epoch: u64,
+    /// Capture time of the last image, filled in by the repository.
+    pub ended_at_epoch: u64,
+}
+
+impl EntitySession {
+    pub fn new(
+        id: u32,
+        screen_id: u32,
+        first_image_id: u32,
+        last_image_id: u32,
+        representative_image_id: u32,
+    ) -> Self {
+        Self {
+            id,
+            screen_id,
+            first_image_id,
+            last_image_id,
+            representative_image_id,
+            started_at_epoch: 0,
+            ended_at_epoch: 0,
+        }
+    }
+
+    /// Position of the session in the timeline of sessions, that of its first image.
+    pub fn cursor(&self) -> TimelineCursor {
+        TimelineCursor {
+            captured_at_epoch: self.started_at_epoch,
+            image_id: self.first_image_id,
+        }
+    }
+}
+
+/// Counts of the stored records, see `Repository::get_stats`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RepositoryStats {
+    pub images: u64,
+    /// Stored texts, a text seen in consecutive images counts once.
+    pub texts: u64,
+    /// Size of the database, 0 for a repository without one.
+    pub database_bytes: u64,
+    /// Images per day and screen, by day then screen.
+    pub frame_counts: Vec<FrameCount>,
+}
+
+/// Outcome of `Repository::maintain`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MaintenanceReport {
+    /// Bytes of the database given back to the file system.
+    pub reclaimed_bytes: u64,
+    /// Size of the database after the maintenance, 0 for a repository without one.
+    pub database_bytes: u64,
+}
+
+/// Number of images captured from a screen during a day.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameCount {
+    /// Days since the epoch, in UTC.
+    pub day: u64,
+    pub screen_id: Option<u32>,
+    pub frames: u64,
+}
+
+/// An image matching a search, with its matching texts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub image_id: u32,
+    pub captured_at_epoch: u64,
+    pub screen_id: Option<u32>,
+    /// Relevance of the image, lower is better.
+    pub score: f64,
+    pub texts: Vec<EntityText>,
+    /// The lines of text around the matching texts, filled in by `Analysis::search`.
+    #[serde(default)]
+    pub snippets: Vec<Snippet>,
+    /// The session of the image, filled in by `Analysis::search` once the image is segmented.
+    #[serde(default)]
+    pub session: Option<EntitySession>,
+    /// The other matching images of the session, best first, filled in by `Analysis::search`
+    /// when grouping by session.
+    #[serde(default)]
+    pub session_image_ids: Vec<u32>,
+}
+
+impl SearchResult {
+    pub fn new(
+        image_id: u32,
+        captured_at_epoch: u64,
+        screen_id: Option<u32>,
+        score: f64,
+        texts: Vec<EntityText>,
+    ) -> Self {
+        Self {
+            image_id,
+            captured_at_epoch,
+            screen_id,
+            score,
+            texts,
+            snippets: vec![],
+            session: None,
+            session_image_ids: vec![],
+        }
+    }
+}
+
+/// A page of search results, best ranked first.
+#[derive(Debug, Clone)]
+pub struct SearchPage {
+    /// Number of matching images across all pages.
+    pub total: u64,
+    pub results: Vec<SearchResult>,
+}
+
+/// Position of an image in the timeline, images are ordered by capture time then id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimelineCursor {
+    pub captured_at_epoch: u64,
+    pub image_id: u32,
+}
+
+impl TimelineCursor {
+    pub fn of(image: &EntityImage) -> Self {
+        Self {
+            captured_at_epoch: image.captured_at_epoch,
+            image_id: image.id,
+        }
+    }
+}
+
+impl std::fmt::Display for TimelineCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.captured_at_epoch, self.image_id)
+    }
+}
+
+impl FromStr for TimelineCursor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (captured_at_epoch, image_id) = s
+            .split_once('.')
+            .ok_or_else(|| anyhow::anyhow!("invalid timeline cursor `{}`", s))?;
+        Ok(Self {
+            captured_at_epoch: captured_at_epoch.parse()?,
+            image_id: image_id.parse()?,
+        })
+    }
+}
+
+/// A page of the timeline, the images captured in `[from_epoch, to_epoch]` from the screen after
+/// the cursor.
+#[derive(Debug, Clone)]
+pub struct TimelineOptions {
+    pub from_epoch: Option<u64>,
+    pub to_epoch: Option<u64>,
+    pub screen_id: Option<u32>,
+    /// Exclusive start of the page, the first page when `None`.
+    pub after: Option<TimelineCursor>,
+    pub limit: u32,
+}
+
+impl TimelineOptions {
+    pub fn new(limit: u32) -> Self {
+        Self {
+            from_epoch: None,
+            to_epoch: None,
+            screen_id: None,
+            after: None,
+            limit,
+        }
+    }
+}
+
+/// The closer of the last image captured at or before the epoch and the first one captured after
+/// it, the earlier one on a tie.
+fn nearest_image(
+    epoch: u64,
+    before: Option<EntityImage>,
+    after: Option<EntityImage>,
+) -> Option<EntityImage> {
+    match (before, after) {
+        (Some(before), Some(after))
+            if after.captured_at_epoch - epoch < epoch - before.captured_at_epoch =>
+        {
+            Some(after)
+        }
+        (Some(before), _) => Some(before),
+        (None, after) => after,
+    }
+}
+
+/// Direction of a step in the timeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelineDirection {
+    Previous,
+    Next,
+}
+
 #[async_trait]
-pub trait Repository {
-    async fn save_image(&self, entity: &EntityImage) -> anyhow::Result<EntityImage>;
+pub trait Repository: Sync {
+    /// Atomically save a captured image together with its texts, either all of them are stored
+    /// or none. The `id` of the given entities and the `image_id` of the texts are assigned by the
+    /// repository.
+    async fn save_frame(
+        &self,
+        image: &EntityImage,
+        texts: &[EntityText],
+    ) -> anyhow::Result<(EntityImage, Vec<EntityText>)> {
+        self.save_frame_continuing(image, texts, &[]).await
+    }
+    /// Atomically save a captured image with its new texts like `save_frame`, and extend to it
+    /// the occurrences of the `continued` texts still on the screen.
+    ///
+    /// An occurrence is only extended when it was last seen in the previous image of the screen
+    /// and stays within `MAX_OCCURRENCE_SPAN`, it is stored again as a new text otherwise.
+    /// Returns the new texts followed by the extended ones.
+    async fn save_frame_continuing(
+        &self,
+        image: &EntityImage,
+        texts: &[EntityText],
+        continued: &[u32],
+    ) -> anyhow::Result<(EntityImage, Vec<EntityText>)>;
     async fn get_image_by_id(&self, id: u32) -> anyhow::Result<EntityImage>;
-    async fn save_text(&self, entity: &EntityText) -> anyhow::Result<EntityText>;
-    async fn save_texts(&self, entities: &[EntityText]) -> anyhow::Result<Vec<EntityText>>;
     async fn get_text_by_id(&self, id: u32) -> anyhow::Result<EntityText>;
-    async fn full_text_search(&self, text: &str) -> anyhow::Result<Vec<EntityText>>;
+    /// All texts seen in the images, ordered by image and position.
+    async fn get_texts_by_image_ids(&self, image_ids: &[u32]) -> anyhow::Result<Vec<EntityText>>;
+    /// Find the images with texts matching the query, ranked by relevance and optionally by
+    /// recency, one page at a time. A text seen in several images matches in the first of them
+    /// captured in the time range of the options.
+    async fn search(&self, options: &SearchOptions) -> anyhow::Result<SearchPage>;
+    /// List images with id greater than `after_id` in ascending id order, optionally restricted
+    /// to the capture time range `[from_epoch, to_epoch]`.
+    async fn scan_images(
+        &self,
+        after_id: u32,
+        from_epoch: Option<u64>,
+        to_epoch: Option<u64>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<EntityImage>>;
+    /// List images in capture order, one page at a time.
+    async fn list_images(&self, options: &TimelineOptions) -> anyhow::Result<Vec<EntityImage>>;
+    /// The image right before or after the given one in capture order, optionally only among the
+    /// images of a screen. `None` at either end of the timeline.
+    async fn get_adjacent_image(
+        &self,
+        image_id: u32,
+        direction: TimelineDirection,
+        screen_id: Option<u32>,
+    ) -> anyhow::Result<Option<EntityImage>>;
+    /// The image captured closest to the epoch, optionally only among the images of a screen. On a
+    /// tie the earlier image wins.
+    async fn get_nearest_image(
+        &self,
+        epoch: u64,
+        screen_id: Option<u32>,
+    ) -> anyhow::Result<Option<EntityImage>>;
+    /// Atomically delete the images along with their texts and search index entries, ids of
+    /// missing images are ignored. Occurrences also seen in other images are kept for them.
+    /// Returns the deleted images, whose archives are left to the caller.
+    async fn delete_images(&self, image_ids: &[u32]) -> anyhow::Result<Vec<EntityImage>>;
+    /// Atomically delete the images captured in `[from_epoch, to_epoch]` like `delete_images`.
+    async fn delete_images_captured_between(
+        &self,
+        from_epoch: u64,
+        to_epoch: u64,
+    ) -> anyhow::Result<Vec<EntityImage>>;
+    /// Atomically replace all texts of the image, including their full text search entries.
+    /// Occurrences spanning other images are split to keep their texts in those images.
+    async fn replace_texts(
+        &self,
+        image_id: u32,
+        entities: &[EntityText],
+    ) -> anyhow::Result<Vec<EntityText>>;
+    /// Atomically replace the OCR texts of the image lying entirely inside `region`, texts only
+    /// partially covered by the region and decoded codes are kept.
+    async fn replace_texts_in_region(
+        &self,
+        image_id: u32,
+        region: &MarkupBox,
+        entities: &[EntityText],
+    ) -> anyhow::Result<Vec<EntityText>>;
+    /// Atomically replace the embeddings of the image, their `image_id` is ignored. Deleting an
+    /// image deletes its embeddings.
+    async fn replace_embeddings(
+        &self,
+        image_id: u32,
+        embeddings: &[EntityEmbedding],
+    ) -> anyhow::Result<()>;
+    /// The embeddings of the model along with the first image of their line captured in the
+    /// time range of the options, restricted to its screen. At most `limit` of them, latest
+    /// image first and in insertion order within an image.
+    async fn get_embeddings(
+        &self,
+        model: &str,
+        options: &SearchOptions,
+        limit: u32,
+    ) -> anyhow::Result<Vec<(EntityEmbedding, EntityImage)>>;
+    /// Insert the session when its `id` is 0, update it otherwise. Returns the session with the
+    /// id and capture times assigned by the repository. Deleting images trims the sessions to
+    /// their remaining images, a session without any is deleted.
+    async fn save_session(&self, session: &EntitySession) -> anyhow::Result<EntitySession>;
+    /// The latest session of each screen, in screen order.
+    async fn get_last_sessions(&self) -> anyhow::Result<Vec<EntitySession>>;
+    /// List sessions overlapping `[from_epoch, to_epoch]` in start order, one page at a time.
+    /// The cursor of a session is `EntitySession::cursor`.
+    async fn list_sessions(&self, options: &TimelineOptions) -> anyhow::Result<Vec<EntitySession>>;
+    /// The sessions of the images along with the id of each image, images outside of any
+    /// session are left out.
+    async fn get_sessions_of_images(
+        &self,
+        image_ids: &[u32],
+    ) -> anyhow::Result<Vec<(u32, EntitySession)>>;
+    /// Counts of the stored records, from running counts instead of scanning the records.
+    async fn get_stats(&self) -> anyhow::Result<RepositoryStats>;
+    /// Write a consistent copy of the database to a new file at `path` while it stays in use.
+    async fn backup_database(&self, path: &str) -> anyhow::Result<()>;
+    /// Compact the indexes, refresh the statistics of the query planner and give the free space
+    /// back to the file system. Records are left untouched.
+    async fn maintain(&self) -> anyhow::Result<MaintenanceReport>;
+}
+
+/// Open and initialize the repository of the database URL, PostgreSQL for `postgres://` and
+/// `postgresql://` URLs when built with the `postgres` feature, SQLite otherwise.
+pub async fn connect(url: &str) -> anyhow::Result<Arc<dyn Repository + Send + Sync>> {
+    if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        #[cfg(feature = "postgres")]
+        {
+            let pool = sqlx_postgres::PgPoolOptions::new().connect(url).await?;
+            let repo = postgres::PostgresRepository::new(pool);
+            repo.initialize().await?;
+            return Ok(Arc::new(repo));
+        }
+        #[cfg(not(feature = "postgres"))]
+        return Err(anyhow::anyhow!(
+            "dejavu is built without PostgreSQL support, enable the `postgres` feature"
+        ));
+    }
+    let options = sqlx_sqlite::SqliteConnectOptions::from_str(url)?
+        // readers no longer wait for the writer, the capture and the web ui stop contending
+        .journal_mode(sqlx_sqlite::SqliteJournalMode::Wal)
+        // in WAL mode a crash may only lose the last commits, never corrupt the database
+        .synchronous(sqlx_sqlite::SqliteSynchronous::Normal)
+        .busy_timeout(std::time::Duration::from_secs(5))
+        // only takes effect for new databases, see `SqliteRepository::maintain`
+        .auto_vacuum(sqlx_sqlite::SqliteAutoVacuum::Incremental);
+    let pool = sqlx_sqlite::SqlitePoolOptions::new()
+        .connect_with(options)
+        .await?;
+    let repo = sqlite::SqliteRepository::new(pool);
+    repo.initialize().await?;
+    Ok(Arc::new(repo))
+}
+
+/// Open the repository of the database URL like `connect`, without ever writing to it. The
+/// schema is not migrated, so it must be the one of this binary. PostgreSQL sessions only run
+/// read-only transactions, SQLite databases are opened read-only.
+pub async fn connect_read_only(url: &str) -> anyhow::Result<Arc<dyn Repository + Send + Sync>> {
+    if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        #[cfg(feature = "postgres")]
+        {
+            let options = sqlx_postgres::PgConnectOptions::from_str(url)?
+                .options([("default_transaction_read_only", "on")]);
+            let pool = sqlx_postgres::PgPoolOptions::new()
+                .connect_with(options)
+                .await?;
+            check_read_only_schema(
+                postgres::migration::current_version(&pool).await?,
+                postgres::migration::latest_version(),
+            )?;
+            return Ok(Arc::new(postgres::PostgresRepository::new(pool)));
+        }
+        #[cfg(not(feature = "postgres"))]
+        return Err(anyhow::anyhow!(
+            "dejavu is built without PostgreSQL support, enable the `postgres` feature"
+        ));
+    }
+    // the journal mode is left to the database, changing it writes to the file
+    let options = sqlx_sqlite::SqliteConnectOptions::from_str(url)?
+        .read_only(true)
+        .busy_timeout(std::time::Duration::from_secs(5));
+    let file = options.clone().get_filename();
+    let pool = sqlx_sqlite::SqlitePoolOptions::new()
+        .connect_with(options)
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to open {} read-only: {}", file.display(), e))?;
+    check_read_only_schema(
+        sqlite::migration::current_version(&pool).await?,
+        sqlite::migration::latest_version(),
+    )?;
+    Ok(Arc::new(sqlite::SqliteRepository::new(pool)))
+}
+
+fn check_read_only_schema(current: u32, latest: u32) -> anyhow::Result<()> {
+    if current > latest {
+        return Err(anyhow::anyhow!(
+            "database schema version {} is newer than {} supported by this binary, please upgrade dejavu",
+            current,
+            latest
+        ));
+    }
+    if current < latest {
+        return Err(anyhow::anyhow!(
+            "database schema version {} is older than {}, a database opened read-only is never migrated, open it once without `--read-only` to migrate it",
+            current,
+            latest
+        ));
+    }
+    Ok(())
 }
@@ -0,0 +1,121 @@
+#[cfg(feature = "postgres")]
+use {
+    anyhow::{anyhow, Result},
+    sqlx::{Executor, Row},
+    sqlx_postgres::Postgres,
+    tracing::info,
+};
+
+#[cfg(feature = "postgres")]
+use crate::repository::sqlite::migration::Migration;
+
+/// Ordered up-migrations of the PostgreSQL schema, released migrations are never edited.
+#[cfg(feature = "postgres")]
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        sql: include_str!("migrations/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        description: "texts seen across consecutive images",
+        sql: include_str!("migrations/0002_texts_last_image_id.sql"),
+    },
+    Migration {
+        version: 3,
+        description: "embeddings of lines",
+        sql: include_str!("migrations/0003_embeddings.sql"),
+    },
+    Migration {
+        version: 4,
+        description: "sessions of activity",
+        sql: include_str!("migrations/0004_sessions.sql"),
+    },
+    Migration {
+        version: 5,
+        description: "running counts for statistics",
+        sql: include_str!("migrations/0005_statistics.sql"),
+    },
+    Migration {
+        version: 6,
+        description: "index of the last images of texts",
+        sql: include_str!("migrations/0006_texts_last_image_id_index.sql"),
+    },
+];
+
+#[cfg(feature = "postgres")]
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |it| it.version)
+}
+
+/// The schema version of the database, 0 for a database never migrated.
+#[cfg(feature = "postgres")]
+pub async fn current_version(pool: &sqlx::Pool<Postgres>) -> Result<u32> {
+    let exists: bool = sqlx::query("SELECT to_regclass('schema_version') IS NOT NULL")
+        .fetch_one(pool)
+        .await?
+        .get(0);
+    if !exists {
+        return Ok(0);
+    }
+    let version: Option<i32> = sqlx::query("SELECT MAX(version) FROM schema_version")
+        .fetch_one(pool)
+        .await?
+        .get(0);
+    Ok(version.unwrap_or(0) as u32)
+}
+
+/// Apply the pending migrations.
+///
+/// The migrations run in one transaction holding a lock on the version table, so servers
+/// starting together against the same database migrate it once. A database written by a newer
+/// binary is refused instead of being touched.
+#[cfg(feature = "postgres")]
+pub async fn migrate(pool: &sqlx::Pool<Postgres>) -> Result<()> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at_epoch BIGINT NOT NULL
+        )",
+    )
+    .execute(pool)
+    .await?;
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("LOCK TABLE schema_version IN EXCLUSIVE MODE")
+        .execute(&mut *tx)
+        .await?;
+    let current: Option<i32> = sqlx::query("SELECT MAX(version) FROM schema_version")
+        .fetch_one(&mut *tx)
+        .await?
+        .get(0);
+    let current = current.unwrap_or(0) as u32;
+    let latest = latest_version();
+    if current > latest {
+        return Err(anyhow!(
+            "database schema version {} is newer than {} supported by this binary, please upgrade dejavu",
+            current,
+            latest
+        ));
+    }
+
+    for migration in MIGRATIONS.iter().filter(|it| it.version > current) {
+        (&mut *tx).execute(migration.sql).await?;
+        sqlx::query(
+            "INSERT INTO schema_version (version, description, applied_at_epoch) VALUES ($1, $2, $3)",
+        )
+        .bind(migration.version as i32)
+        .bind(migration.description)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&mut *tx)
+        .await?;
+        info!(
+            "applied migration {}: {}",
+            migration.version, migration.description
+        );
+    }
+    tx.commit().await?;
+    Ok(())
+}
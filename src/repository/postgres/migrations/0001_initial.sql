@@ -0,0 +1,25 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm WITH SCHEMA public;
+
+CREATE TABLE IF NOT EXISTS images (
+    id SERIAL PRIMARY KEY,
+    archive_type TEXT NOT NULL,
+    archive_info TEXT NOT NULL,
+    captured_at_epoch BIGINT NOT NULL,
+    screen_id INTEGER
+);
+CREATE INDEX IF NOT EXISTS images_captured_at_epoch ON images (captured_at_epoch);
+
+CREATE TABLE IF NOT EXISTS texts (
+    id SERIAL PRIMARY KEY,
+    image_id INTEGER NOT NULL,
+    kind TEXT NOT NULL DEFAULT 'ocr',
+    text TEXT NOT NULL,
+    "left" INTEGER NOT NULL,
+    top INTEGER NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    tsv TSVECTOR GENERATED ALWAYS AS (to_tsvector('simple', text)) STORED
+);
+CREATE INDEX IF NOT EXISTS texts_image_id ON texts (image_id);
+CREATE INDEX IF NOT EXISTS texts_tsv ON texts USING GIN (tsv);
+CREATE INDEX IF NOT EXISTS texts_trigram ON texts USING GIN (lower(text) public.gin_trgm_ops);
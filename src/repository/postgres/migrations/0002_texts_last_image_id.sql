@@ -0,0 +1,4 @@
+-- a text is stored once per occurrence, seen in the images of the screen of its first image
+-- up to the last one
+ALTER TABLE texts ADD COLUMN last_image_id INTEGER NOT NULL DEFAULT 0;
+UPDATE texts SET last_image_id = image_id;
@@ -0,0 +1,52 @@
+-- running counts kept up to date by triggers, so statistics never scan the large tables
+
+-- frames per day since the epoch in UTC and per screen, -1 for images without a screen
+CREATE TABLE frame_counts (
+    day BIGINT NOT NULL,
+    screen_id INTEGER NOT NULL,
+    frames BIGINT NOT NULL,
+    PRIMARY KEY (day, screen_id)
+);
+INSERT INTO frame_counts (day, screen_id, frames)
+SELECT captured_at_epoch / 86400, COALESCE(screen_id, -1), COUNT(*) FROM images GROUP BY 1, 2;
+
+CREATE FUNCTION count_frames() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        INSERT INTO frame_counts (day, screen_id, frames)
+        VALUES (NEW.captured_at_epoch / 86400, COALESCE(NEW.screen_id, -1), 1)
+        ON CONFLICT (day, screen_id) DO UPDATE SET frames = frame_counts.frames + 1;
+    ELSE
+        UPDATE frame_counts SET frames = frames - 1
+        WHERE day = OLD.captured_at_epoch / 86400 AND screen_id = COALESCE(OLD.screen_id, -1);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+CREATE TRIGGER frame_counts AFTER INSERT OR DELETE ON images
+FOR EACH ROW EXECUTE FUNCTION count_frames();
+
+CREATE TABLE counters (
+    name TEXT PRIMARY KEY,
+    value BIGINT NOT NULL
+);
+INSERT INTO counters (name, value) SELECT 'texts', COUNT(*) FROM texts;
+
+-- texts are inserted and deleted in bulk, they are counted once per statement
+CREATE FUNCTION count_inserted_texts() RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE counters SET value = value + (SELECT COUNT(*) FROM inserted) WHERE name = 'texts';
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+CREATE TRIGGER texts_count_insert AFTER INSERT ON texts
+REFERENCING NEW TABLE AS inserted FOR EACH STATEMENT EXECUTE FUNCTION count_inserted_texts();
+
+CREATE FUNCTION count_deleted_texts() RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE counters SET value = value - (SELECT COUNT(*) FROM deleted) WHERE name = 'texts';
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+CREATE TRIGGER texts_count_delete AFTER DELETE ON texts
+REFERENCING OLD TABLE AS deleted FOR EACH STATEMENT EXECUTE FUNCTION count_deleted_texts();
@@ -0,0 +1,1073 @@
+#[cfg(feature = "postgres")]
+use {
+    super::{
+        fuzzy, nearest_image, EntityEmbedding, EntityImage, EntitySession, EntityText, FrameCount,
+        MaintenanceReport, Repository, RepositoryStats, SearchOptions, SearchPage, SearchResult,
+        TimelineDirection, TimelineOptions, MAX_OCCURRENCE_SPAN,
+    },
+    crate::{embedding, ocr::MarkupBox},
+    anyhow::{anyhow, Result},
+    async_trait::async_trait,
+    sqlx::{QueryBuilder, Row, Transaction},
+    sqlx_postgres::{PgRow, Postgres},
+};
+
+pub mod migration;
+
+/// Rows per multi-row insert.
+#[cfg(feature = "postgres")]
+const INSERT_CHUNK_SIZE: usize = 128;
+
+/// Repository on PostgreSQL, for an archive shared by several machines.
+///
+/// Texts are searched with a tsvector of the `simple` configuration, which lowercases words
+/// without stemming like the default tokenizer of SQLite FTS5, and typo tolerant search uses
+/// a `pg_trgm` index. Ids are `INTEGER` columns converted from and to `u32`.
+#[cfg(feature = "postgres")]
+pub struct PostgresRepository {
+    pool: sqlx::Pool<Postgres>,
+}
+
+#[cfg(feature = "postgres")]
+impl PostgresRepository {
+    pub fn new(pool: sqlx::Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    /// Create or migrate the schema, see `migration::migrate`.
+    pub async fn initialize(&self) -> Result<()> {
+        migration::migrate(&self.pool).await
+    }
+
+    async fn database_bytes(&self) -> Result<u64> {
+        let bytes: i64 = sqlx::query("SELECT pg_database_size(current_database())")
+            .fetch_one(&self.pool)
+            .await?
+            .get(0);
+        Ok(bytes.try_into()?)
+    }
+
+    async fn exact_search(&self, options: &SearchOptions) -> Result<SearchPage> {
+        let Some(ts_query) = ts_query(&options.text) else {
+            return Ok(SearchPage {
+                total: 0,
+                results: vec![],
+            });
+        };
+        // ts_rank is higher for better matches, normalized by the length of the text
+        let rows = sqlx::query(&format!(
+            "WITH hits AS (
+                SELECT t.id, -ts_rank(t.tsv, q, 1)::FLOAT8 AS rank
+                FROM texts t, to_tsquery('simple', $1) q
+                WHERE t.tsv @@ q
+            ),
+            framed AS (
+                SELECT h.id, h.rank, f.id AS image_id, f.captured_at_epoch, f.screen_id
+                FROM hits h
+                JOIN texts t ON t.id = h.id
+                JOIN images i ON i.id = t.image_id
+                JOIN images f ON f.id = {}
+                WHERE ($7::BIGINT IS NULL OR f.captured_at_epoch <= $7)
+                AND ($8::INTEGER IS NULL OR f.screen_id = $8)
+            ),
+            ranked AS (
+                SELECT image_id, captured_at_epoch, screen_id,
+                    MIN(rank) + $2 * GREATEST($3 - captured_at_epoch, 0) / 86400.0 AS score,
+                    COUNT(*) OVER () AS total
+                FROM framed
+                GROUP BY image_id, captured_at_epoch, screen_id
+                ORDER BY score, image_id
+                LIMIT $4 OFFSET $5
+            )
+            SELECT t.id, r.image_id, t.kind, t.text, t.left, t.top, t.width, t.height,
+                r.score, r.captured_at_epoch, r.total, r.screen_id, t.last_image_id
+            FROM ranked r
+            JOIN framed f ON f.image_id = r.image_id
+            JOIN texts t ON t.id = f.id
+            ORDER BY r.score, r.image_id, t.id",
+            first_frame_from("$6::BIGINT", "t.last_image_id")
+        ))
+        .bind(&ts_query)
+        .bind(options.recency_weight)
+        .bind(chrono::Utc::now().timestamp())
+        .bind(options.limit as i64)
+        .bind(options.offset as i64)
+        .bind(options.from_epoch.map(|it| it as i64))
+        .bind(options.to_epoch.map(|it| it as i64))
+        .bind(options.screen_id.map(|it| it as i32))
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut total = match rows.first() {
+            Some(row) => row.get::<i64, _>(10) as u64,
+            None => 0,
+        };
+        if rows.is_empty() && options.offset > 0 {
+            // a page past the end carries no total
+            let count: i64 = sqlx::query(&format!(
+                "SELECT COUNT(DISTINCT f.id) FROM texts t
+                JOIN images i ON i.id = t.image_id
+                JOIN images f ON f.id = {}
+                WHERE t.tsv @@ to_tsquery('simple', $1)
+                AND ($3::BIGINT IS NULL OR f.captured_at_epoch <= $3)
+                AND ($4::INTEGER IS NULL OR f.screen_id = $4)",
+                first_frame_from("$2::BIGINT", "t.last_image_id")
+            ))
+            .bind(&ts_query)
+            .bind(options.from_epoch.map(|it| it as i64))
+            .bind(options.to_epoch.map(|it| it as i64))
+            .bind(options.screen_id.map(|it| it as i32))
+            .fetch_one(&self.pool)
+            .await?
+            .get(0);
+            total = count as u64;
+        }
+
+        let mut results: Vec<SearchResult> = Vec::new();
+        for row in rows {
+            let text = text_from_row(&row)?;
+            match results.last_mut() {
+                Some(last) if last.image_id == text.image_id => last.texts.push(text),
+                _ => {
+                    let captured_at_epoch: i64 = row.get(9);
+                    let screen_id: Option<i32> = row.get(11);
+                    results.push(SearchResult::new(
+                        text.image_id,
+                        captured_at_epoch.try_into()?,
+                        screen_id.map(|it| it as u32),
+                        row.get(8),
+                        vec![text],
+                    ));
+                }
+            }
+        }
+        Ok(SearchPage { total, results })
+    }
+
+    /// Rank the texts sharing trigrams with the query by their edit distance to it, see
+    /// `fuzzy::rank`.
+    async fn fuzzy_search(
+        &self,
+        options: &SearchOptions,
+        trigrams: &[String],
+    ) -> Result<SearchPage> {
+        // the start of the range is bound once and joined, being read thrice
+        let mut builder = QueryBuilder::new(
+            "SELECT t.id, f.id, t.kind, t.text, t.left, t.top, t.width, t.height,
+                f.captured_at_epoch, f.screen_id, t.last_image_id
+            FROM (SELECT ",
+        );
+        builder
+            .push_bind(options.from_epoch.map(|it| it as i64))
+            .push(format!(
+                "::BIGINT AS from_epoch) r
+                CROSS JOIN texts t
+                JOIN images i ON i.id = t.image_id
+                JOIN images f ON f.id = {}
+                WHERE (",
+                first_frame_from("r.from_epoch", "t.last_image_id")
+            ));
+        // LIKE patterns are served by the trigram index
+        let mut separated = builder.separated(" OR ");
+        for trigram in trigrams {
+            separated
+                .push("lower(t.text) LIKE ")
+                .push_bind_unseparated(format!("%{}%", escape_like(trigram)));
+        }
+        builder.push(")");
+        if let Some(to_epoch) = options.to_epoch {
+            builder
+                .push(" AND f.captured_at_epoch <= ")
+                .push_bind(to_epoch as i64);
+        }
+        if let Some(screen_id) = options.screen_id {
+            builder
+                .push(" AND f.screen_id = ")
+                .push_bind(screen_id as i32);
+        }
+        builder
+            .push(" ORDER BY public.similarity(lower(t.text), ")
+            .push_bind(options.text.to_lowercase())
+            .push(") DESC, t.id LIMIT ")
+            .push_bind(fuzzy::MAX_CANDIDATES as i64);
+        let rows = builder.build().fetch_all(&self.pool).await?;
+
+        let mut candidates = Vec::with_capacity(rows.len());
+        for row in rows {
+            let captured_at_epoch: i64 = row.get(8);
+            let screen_id: Option<i32> = row.get(9);
+            candidates.push(fuzzy::Candidate {
+                text: text_from_row(&row)?,
+                captured_at_epoch: captured_at_epoch.try_into()?,
+                screen_id: screen_id.map(|it| it as u32),
+            });
+        }
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        Ok(fuzzy::rank(options, candidates, now))
+    }
+}
+
+#[cfg(feature = "postgres")]
+#[async_trait]
+impl Repository for PostgresRepository {
+    async fn save_frame_continuing(
+        &self,
+        image: &EntityImage,
+        texts: &[EntityText],
+        continued: &[u32],
+    ) -> Result<(EntityImage, Vec<EntityText>)> {
+        let mut tx = self.pool.begin().await?;
+        let id: i32 = sqlx::query(
+            "INSERT INTO images (archive_type, archive_info, captured_at_epoch, screen_id)
+            VALUES ($1, $2, $3, $4) RETURNING id",
+        )
+        .bind(&image.archive_type)
+        .bind(&image.archive_info)
+        .bind(image.captured_at_epoch as i64)
+        .bind(image.screen_id.map(|it| it as i32))
+        .fetch_one(&mut *tx)
+        .await?
+        .get(0);
+        let image = EntityImage {
+            id: id as u32,
+            ..image.clone()
+        };
+        let continued: Vec<i32> = continued.iter().map(|it| *it as i32).collect();
+        let rows = sqlx::query(
+            "UPDATE texts SET last_image_id = $1
+            WHERE id = ANY($2) AND image_id >= $1 - $3
+            AND last_image_id = (SELECT MAX(id) FROM images WHERE screen_id = $4 AND id < $1)
+            RETURNING id, image_id, kind, text, \"left\", top, width, height, last_image_id",
+        )
+        .bind(id)
+        .bind(&continued)
+        .bind(MAX_OCCURRENCE_SPAN as i32)
+        .bind(image.screen_id.map(|it| it as i32))
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut extended = rows
+            .iter()
+            .map(|row| {
+                Ok(EntityText {
+                    image_id: image.id,
+                    ..text_from_row(row)?
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        extended.sort_by_key(|it| it.id);
+
+        // the others are stored again
+        let extended_ids: Vec<i32> = extended.iter().map(|it| it.id as i32).collect();
+        let rows = sqlx::query(
+            "SELECT id, image_id, kind, text, \"left\", top, width, height, last_image_id
+            FROM texts WHERE id = ANY($1) AND NOT id = ANY($2)
+            ORDER BY id",
+        )
+        .bind(&continued)
+        .bind(&extended_ids)
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut new_texts = texts.to_vec();
+        for row in rows.iter() {
+            new_texts.push(text_from_row(row)?);
+        }
+        let mut texts = insert_texts(&mut tx, image.id, &new_texts).await?;
+        texts.extend(extended);
+        tx.commit().await?;
+        Ok((image, texts))
+    }
+
+    async fn get_image_by_id(&self, id: u32) -> Result<EntityImage> {
+        let row = sqlx::query(
+            "SELECT id, archive_type, archive_info, captured_at_epoch, screen_id FROM images
+            WHERE id = $1",
+        )
+        .bind(id as i32)
+        .fetch_one(&self.pool)
+        .await?;
+        image_from_row(&row)
+    }
+
+    async fn get_text_by_id(&self, id: u32) -> Result<EntityText> {
+        let row = sqlx::query(
+            "SELECT id, image_id, kind, text, \"left\", top, width, height, last_image_id
+            FROM texts WHERE id = $1",
+        )
+        .bind(id as i32)
+        .fetch_one(&self.pool)
+        .await?;
+        text_from_row(&row)
+    }
+
+    async fn get_texts_by_image_ids(&self, image_ids: &[u32]) -> Result<Vec<EntityText>> {
+        let image_ids: Vec<i32> = image_ids.iter().map(|it| *it as i32).collect();
+        // a text is seen in the images of its screen between its first and last image
+        let rows = sqlx::query(
+            "SELECT t.id, i.id, t.kind, t.text, t.\"left\", t.top, t.width, t.height,
+                t.last_image_id
+            FROM images i
+            JOIN texts t ON t.image_id BETWEEN i.id - $2 AND i.id AND t.last_image_id >= i.id
+                AND (t.image_id = i.id
+                    OR (SELECT screen_id FROM images WHERE id = t.image_id) = i.screen_id)
+            WHERE i.id = ANY($1)
+            ORDER BY i.id, t.top, t.\"left\", t.id",
+        )
+        .bind(&image_ids)
+        .bind(MAX_OCCURRENCE_SPAN as i32)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(text_from_row).collect()
+    }
+
+    async fn search(&self, options: &SearchOptions) -> Result<SearchPage> {
+        if options.fuzzy {
+            // terms shorter than a trigram are only matched exactly
+            let trigrams = fuzzy::trigrams(&options.text);
+            if !trigrams.is_empty() {
+                return self.fuzzy_search(options, &trigrams).await;
+            }
+        }
+        self.exact_search(options).await
+    }
+
+    async fn scan_images(
+        &self,
+        after_id: u32,
+        from_epoch: Option<u64>,
+        to_epoch: Option<u64>,
+        limit: u32,
+    ) -> Result<Vec<EntityImage>> {
+        let rows = sqlx::query(
+            "SELECT id, archive_type, archive_info, captured_at_epoch, screen_id FROM images
+            WHERE id > $1
+            AND ($2::BIGINT IS NULL OR captured_at_epoch >= $2)
+            AND ($3::BIGINT IS NULL OR captured_at_epoch <= $3)
+            ORDER BY id ASC
+            LIMIT $4",
+        )
+        .bind(after_id as i32)
+        .bind(from_epoch.map(|it| it as i64))
+        .bind(to_epoch.map(|it| it as i64))
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(image_from_row).collect()
+    }
+
+    async fn list_images(&self, options: &TimelineOptions) -> Result<Vec<EntityImage>> {
+        let rows = sqlx::query(
+            "SELECT id, archive_type, archive_info, captured_at_epoch, screen_id FROM images
+            WHERE ($1::BIGINT IS NULL OR (captured_at_epoch, id) > ($1, $2::INTEGER))
+            AND ($3::BIGINT IS NULL OR captured_at_epoch >= $3)
+            AND ($4::BIGINT IS NULL OR captured_at_epoch <= $4)
+            AND ($5::INTEGER IS NULL OR screen_id = $5)
+            ORDER BY captured_at_epoch, id
+            LIMIT $6",
+        )
+        .bind(options.after.map(|it| it.captured_at_epoch as i64))
+        .bind(options.after.map(|it| it.image_id as i32))
+        .bind(options.from_epoch.map(|it| it as i64))
+        .bind(options.to_epoch.map(|it| it as i64))
+        .bind(options.screen_id.map(|it| it as i32))
+        .bind(options.limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(image_from_row).collect()
+    }
+
+    async fn get_adjacent_image(
+        &self,
+        image_id: u32,
+        direction: TimelineDirection,
+        screen_id: Option<u32>,
+    ) -> Result<Option<EntityImage>> {
+        let origin = self.get_image_by_id(image_id).await?;
+        let sql = match direction {
+            TimelineDirection::Previous => {
+                "SELECT id, archive_type, archive_info, captured_at_epoch, screen_id FROM images
+                WHERE (captured_at_epoch, id) < ($1, $2)
+                AND ($3::INTEGER IS NULL OR screen_id = $3)
+                ORDER BY captured_at_epoch DESC, id DESC
+                LIMIT 1"
+            }
+            TimelineDirection::Next => {
+                "SELECT id, archive_type, archive_info, captured_at_epoch, screen_id FROM images
+                WHERE (captured_at_epoch, id) > ($1, $2)
+                AND ($3::INTEGER IS NULL OR screen_id = $3)
+                ORDER BY captured_at_epoch, id
+                LIMIT 1"
+            }
+        };
+        let row = sqlx::query(sql)
+            .bind(origin.captured_at_epoch as i64)
+            .bind(origin.id as i32)
+            .bind(screen_id.map(|it| it as i32))
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(image_from_row).transpose()
+    }
+
+    async fn get_nearest_image(
+        &self,
+        epoch: u64,
+        screen_id: Option<u32>,
+    ) -> Result<Option<EntityImage>> {
+        let before = sqlx::query(
+            "SELECT id, archive_type, archive_info, captured_at_epoch, screen_id FROM images
+            WHERE captured_at_epoch <= $1 AND ($2::INTEGER IS NULL OR screen_id = $2)
+            ORDER BY captured_at_epoch DESC, id DESC
+            LIMIT 1",
+        )
+        .bind(epoch as i64)
+        .bind(screen_id.map(|it| it as i32))
+        .fetch_optional(&self.pool)
+        .await?;
+        let after = sqlx::query(
+            "SELECT id, archive_type, archive_info, captured_at_epoch, screen_id FROM images
+            WHERE captured_at_epoch > $1 AND ($2::INTEGER IS NULL OR screen_id = $2)
+            ORDER BY captured_at_epoch, id
+            LIMIT 1",
+        )
+        .bind(epoch as i64)
+        .bind(screen_id.map(|it| it as i32))
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(nearest_image(
+            epoch,
+            before.as_ref().map(image_from_row).transpose()?,
+            after.as_ref().map(image_from_row).transpose()?,
+        ))
+    }
+
+    async fn delete_images(&self, image_ids: &[u32]) -> Result<Vec<EntityImage>> {
+        let image_ids: Vec<i32> = image_ids.iter().map(|it| *it as i32).collect();
+        let mut tx = self.pool.begin().await?;
+        let deleted = delete_images_in(&mut tx, &image_ids).await?;
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    async fn delete_images_captured_between(
+        &self,
+        from_epoch: u64,
+        to_epoch: u64,
+    ) -> Result<Vec<EntityImage>> {
+        let mut tx = self.pool.begin().await?;
+        let image_ids: Vec<i32> =
+            sqlx::query("SELECT id FROM images WHERE captured_at_epoch BETWEEN $1 AND $2")
+                .bind(from_epoch as i64)
+                .bind(to_epoch as i64)
+                .fetch_all(&mut *tx)
+                .await?
+                .iter()
+                .map(|row| row.get(0))
+                .collect();
+        let deleted = delete_images_in(&mut tx, &image_ids).await?;
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    async fn replace_texts(
+        &self,
+        image_id: u32,
+        entities: &[EntityText],
+    ) -> Result<Vec<EntityText>> {
+        let mut tx = self.pool.begin().await?;
+        isolate_texts(&mut tx, image_id, |b| {
+            b.push("TRUE");
+        })
+        .await?;
+        sqlx::query("DELETE FROM texts WHERE image_id = $1")
+            .bind(image_id as i32)
+            .execute(&mut *tx)
+            .await?;
+        let result = insert_texts(&mut tx, image_id, entities).await?;
+        tx.commit().await?;
+        Ok(result)
+    }
+
+    async fn replace_texts_in_region(
+        &self,
+        image_id: u32,
+        region: &MarkupBox,
+        entities: &[EntityText],
+    ) -> Result<Vec<EntityText>> {
+        let inside = |b: &mut QueryBuilder<'_, Postgres>| {
+            b.push("kind = 'ocr' AND \"left\" >= ")
+                .push_bind(region.left as i32)
+                .push(" AND top >= ")
+                .push_bind(region.top as i32)
+                .push(" AND \"left\" + width <= ")
+                .push_bind((region.left + region.width) as i32)
+                .push(" AND top + height <= ")
+                .push_bind((region.top + region.height) as i32);
+        };
+        let mut tx = self.pool.begin().await?;
+        isolate_texts(&mut tx, image_id, inside).await?;
+        let mut builder = QueryBuilder::new("DELETE FROM texts WHERE image_id = ");
+        builder.push_bind(image_id as i32).push(" AND ");
+        inside(&mut builder);
+        builder.build().execute(&mut *tx).await?;
+        let result = insert_texts(&mut tx, image_id, entities).await?;
+        tx.commit().await?;
+        Ok(result)
+    }
+
+    async fn replace_embeddings(
+        &self,
+        image_id: u32,
+        embeddings: &[EntityEmbedding],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM embeddings WHERE image_id = $1")
+            .bind(image_id as i32)
+            .execute(&mut *tx)
+            .await?;
+        for chunk in embeddings.chunks(INSERT_CHUNK_SIZE) {
+            let mut builder =
+                QueryBuilder::new("INSERT INTO embeddings (image_id, model, text_ids, vector) ");
+            builder.push_values(chunk, |mut b, it| {
+                b.push_bind(image_id as i32)
+                    .push_bind(&it.model)
+                    .push_bind(embedding::encode_text_ids(&it.text_ids))
+                    .push_bind(embedding::encode_vector(&it.vector));
+            });
+            builder.build().execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_embeddings(
+        &self,
+        model: &str,
+        options: &SearchOptions,
+        limit: u32,
+    ) -> Result<Vec<(EntityEmbedding, EntityImage)>> {
+        // a line lasts until the last frame of its texts, which is looked up only for the lines
+        // first seen in the screen before the end of the range. The latest lines are read first
+        // from the index by model and image.
+        let rows = sqlx::query(&format!(
+            "SELECT e.image_id, e.model, e.text_ids, e.vector,
+                f.id, f.archive_type, f.archive_info, f.captured_at_epoch, f.screen_id
+            FROM embeddings e
+            JOIN images i ON i.id = e.image_id
+            JOIN images f ON f.id = {}
+            WHERE e.model = $1
+            AND ($3::BIGINT IS NULL OR i.captured_at_epoch <= $3)
+            AND ($4::INTEGER IS NULL OR i.screen_id = $4)
+            AND ($3::BIGINT IS NULL OR f.captured_at_epoch <= $3)
+            ORDER BY e.image_id DESC, e.id
+            LIMIT $5",
+            first_frame_from(
+                "$2::BIGINT",
+                "(SELECT MAX(t.last_image_id) FROM texts t
+                    WHERE t.id = ANY(string_to_array(e.text_ids, ',')::INT[]))"
+            )
+        ))
+        .bind(model)
+        .bind(options.from_epoch.map(|it| it as i64))
+        .bind(options.to_epoch.map(|it| it as i64))
+        .bind(options.screen_id.map(|it| it as i32))
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut result = Vec::with_capacity(rows.len());
+        for row in rows {
+            let image_id: i32 = row.get(0);
+            let text_ids: String = row.get(2);
+            let vector: Vec<u8> = row.get(3);
+            let embedding = EntityEmbedding::new(
+                image_id as u32,
+                row.get(1),
+                embedding::decode_text_ids(&text_ids)?,
+                embedding::decode_vector(&vector)?,
+            );
+            // the columns of the image follow those of the embedding
+            let frame_id: i32 = row.get(4);
+            let captured_at_epoch: i64 = row.get(7);
+            let screen_id: Option<i32> = row.get(8);
+            let image = EntityImage::new(
+                frame_id as u32,
+                row.get(5),
+                row.get(6),
+                captured_at_epoch.try_into()?,
+                screen_id.map(|it| it as u32),
+            );
+            result.push((embedding, image));
+        }
+        Ok(result)
+    }
+
+    async fn save_session(&self, session: &EntitySession) -> Result<EntitySession> {
+        let id: i32 = if session.id == 0 {
+            sqlx::query(
+                "INSERT INTO sessions
+                (screen_id, first_image_id, last_image_id, representative_image_id)
+                VALUES ($1, $2, $3, $4) RETURNING id",
+            )
+            .bind(session.screen_id as i32)
+            .bind(session.first_image_id as i32)
+            .bind(session.last_image_id as i32)
+            .bind(session.representative_image_id as i32)
+            .fetch_one(&self.pool)
+            .await?
+            .get(0)
+        } else {
+            let updated = sqlx::query(
+                "UPDATE sessions SET screen_id = $1, first_image_id = $2, last_image_id = $3,
+                representative_image_id = $4 WHERE id = $5",
+            )
+            .bind(session.screen_id as i32)
+            .bind(session.first_image_id as i32)
+            .bind(session.last_image_id as i32)
+            .bind(session.representative_image_id as i32)
+            .bind(session.id as i32)
+            .execute(&self.pool)
+            .await?;
+            if updated.rows_affected() == 0 {
+                return Err(anyhow!("session {} not found", session.id));
+            }
+            session.id as i32
+        };
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM {} WHERE s.id = $1",
+            SESSION_COLUMNS, SESSION_TABLES
+        ))
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+        session_from_row(&row)
+    }
+
+    async fn get_last_sessions(&self) -> Result<Vec<EntitySession>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM {} WHERE s.last_image_id =
+            (SELECT MAX(last_image_id) FROM sessions WHERE screen_id = s.screen_id)
+            ORDER BY s.screen_id",
+            SESSION_COLUMNS, SESSION_TABLES
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(session_from_row).collect()
+    }
+
+    async fn list_sessions(&self, options: &TimelineOptions) -> Result<Vec<EntitySession>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM {}
+            WHERE ($1::BIGINT IS NULL OR (f.captured_at_epoch, s.first_image_id) > ($1, $2::INTEGER))
+            AND ($3::BIGINT IS NULL OR l.captured_at_epoch >= $3)
+            AND ($4::BIGINT IS NULL OR f.captured_at_epoch <= $4)
+            AND ($5::INTEGER IS NULL OR s.screen_id = $5)
+            ORDER BY f.captured_at_epoch, s.first_image_id
+            LIMIT $6",
+            SESSION_COLUMNS, SESSION_TABLES
+        ))
+        .bind(options.after.map(|it| it.captured_at_epoch as i64))
+        .bind(options.after.map(|it| it.image_id as i32))
+        .bind(options.from_epoch.map(|it| it as i64))
+        .bind(options.to_epoch.map(|it| it as i64))
+        .bind(options.screen_id.map(|it| it as i32))
+        .bind(options.limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(session_from_row).collect()
+    }
+
+    async fn get_sessions_of_images(&self, image_ids: &[u32]) -> Result<Vec<(u32, EntitySession)>> {
+        let image_ids: Vec<i32> = image_ids.iter().map(|it| *it as i32).collect();
+        let rows = sqlx::query(&format!(
+            "SELECT {}, i.id FROM {} JOIN images i ON i.screen_id = s.screen_id
+            AND i.id BETWEEN s.first_image_id AND s.last_image_id
+            WHERE i.id = ANY($1)
+            ORDER BY i.id",
+            SESSION_COLUMNS, SESSION_TABLES
+        ))
+        .bind(&image_ids)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut result = Vec::with_capacity(rows.len());
+        for row in rows {
+            // the id of the image follows the columns of the session
+            let image_id: i32 = row.get(7);
+            result.push((image_id as u32, session_from_row(&row)?));
+        }
+        Ok(result)
+    }
+
+    async fn get_stats(&self) -> Result<RepositoryStats> {
+        let images: i64 = sqlx::query("SELECT COALESCE(SUM(frames), 0)::BIGINT FROM frame_counts")
+            .fetch_one(&self.pool)
+            .await?
+            .get(0);
+        let texts: i64 = sqlx::query("SELECT value FROM counters WHERE name = 'texts'")
+            .fetch_one(&self.pool)
+            .await?
+            .get(0);
+        let database_bytes = self.database_bytes().await?;
+        let rows = sqlx::query(
+            "SELECT day, screen_id, frames FROM frame_counts WHERE frames > 0
+            ORDER BY day, screen_id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let mut frame_counts = Vec::with_capacity(rows.len());
+        for row in rows {
+            let day: i64 = row.get(0);
+            let screen_id: i32 = row.get(1);
+            let frames: i64 = row.get(2);
+            frame_counts.push(FrameCount {
+                day: day.try_into()?,
+                // images without a screen are counted under -1
+                screen_id: screen_id.try_into().ok(),
+                frames: frames.try_into()?,
+            });
+        }
+        Ok(RepositoryStats {
+            images: images.try_into()?,
+            texts: texts.try_into()?,
+            database_bytes,
+            frame_counts,
+        })
+    }
+
+    async fn backup_database(&self, _path: &str) -> Result<()> {
+        Err(anyhow!(
+            "PostgreSQL databases are backed up with pg_dump, not by dejavu"
+        ))
+    }
+
+    async fn maintain(&self) -> Result<MaintenanceReport> {
+        let before = self.database_bytes().await?;
+        // also cleans up the pending lists of the gin indexes of the texts
+        sqlx::query("VACUUM (ANALYZE) images, texts, embeddings, sessions, frame_counts, counters")
+            .execute(&self.pool)
+            .await?;
+        let after = self.database_bytes().await?;
+        Ok(MaintenanceReport {
+            reclaimed_bytes: before.saturating_sub(after),
+            database_bytes: after,
+        })
+    }
+}
+
+/// Id of the first frame captured from the epoch `from` of the occurrence first seen in the image
+/// `i` and last seen in the image of `last_image_id`, NULL when it ends before. Every frame of an
+/// occurrence is an image of the screen of `i`.
+#[cfg(feature = "postgres")]
+fn first_frame_from(from: &str, last_image_id: &str) -> String {
+    format!(
+        "CASE WHEN {0} IS NULL OR i.captured_at_epoch >= {0} THEN i.id ELSE (
+            SELECT MIN(n.id) FROM images n
+            WHERE n.screen_id = i.screen_id AND n.id > i.id AND n.id <= {1}
+            AND n.captured_at_epoch >= {0}
+        ) END",
+        from, last_image_id
+    )
+}
+
+/// Columns of a session read by `session_from_row`, selected from the `SESSION_TABLES`.
+#[cfg(feature = "postgres")]
+const SESSION_COLUMNS: &str = "s.id, s.screen_id, s.first_image_id, s.last_image_id,
+    s.representative_image_id, f.captured_at_epoch, l.captured_at_epoch";
+/// Sessions joined with their first and last images.
+#[cfg(feature = "postgres")]
+const SESSION_TABLES: &str = "sessions s
+    JOIN images f ON f.id = s.first_image_id
+    JOIN images l ON l.id = s.last_image_id";
+
+/// Insert texts of the image in batches, the search indexes follow the texts.
+#[cfg(feature = "postgres")]
+async fn insert_texts(
+    tx: &mut Transaction<'_, Postgres>,
+    image_id: u32,
+    entities: &[EntityText],
+) -> Result<Vec<EntityText>> {
+    let mut result = Vec::with_capacity(entities.len());
+    for chunk in entities.chunks(INSERT_CHUNK_SIZE) {
+        let mut builder = QueryBuilder::new(
+            "INSERT INTO texts (image_id, last_image_id, kind, text, \"left\", top, width, height) ",
+        );
+        builder.push_values(chunk, |mut b, it| {
+            b.push_bind(image_id as i32)
+                .push_bind(image_id as i32)
+                .push_bind(it.kind.as_str())
+                .push_bind(&it.text)
+                .push_bind(it.left as i32)
+                .push_bind(it.top as i32)
+                .push_bind(it.width as i32)
+                .push_bind(it.height as i32);
+        });
+        // the order of rows returned by RETURNING is unspecified
+        builder.push(
+            " RETURNING id, image_id, kind, text, \"left\", top, width, height, last_image_id",
+        );
+        let rows = builder.build().fetch_all(&mut **tx).await?;
+        let mut inserted = rows.iter().map(text_from_row).collect::<Result<Vec<_>>>()?;
+        inserted.sort_by_key(|it| it.id);
+        result.extend(inserted);
+    }
+    Ok(result)
+}
+
+/// Split the occurrences seen in the image and in other images too, among the texts matching
+/// the condition pushed by `condition`, so that those seen in the image are only seen in it.
+#[cfg(feature = "postgres")]
+async fn isolate_texts<F>(
+    tx: &mut Transaction<'_, Postgres>,
+    image_id: u32,
+    condition: F,
+) -> Result<()>
+where
+    F: Fn(&mut QueryBuilder<'_, Postgres>) + Sync,
+{
+    let image_id = image_id as i32;
+    let spanning = |b: &mut QueryBuilder<'_, Postgres>| {
+        b.push("image_id < last_image_id AND image_id BETWEEN ")
+            .push_bind(image_id - MAX_OCCURRENCE_SPAN as i32)
+            .push(" AND ")
+            .push_bind(image_id)
+            .push(" AND last_image_id >= ")
+            .push_bind(image_id)
+            .push(" AND (image_id = ")
+            .push_bind(image_id)
+            .push(
+                " OR (SELECT screen_id FROM images WHERE id = texts.image_id)
+                = (SELECT screen_id FROM images WHERE id = ",
+            )
+            .push_bind(image_id)
+            .push(")) AND (");
+        condition(b);
+        b.push(")");
+    };
+    // the neighbours of the image on its screen, they exist within a span
+    let neighbour = |b: &mut QueryBuilder<'_, Postgres>, aggregate: &str, comparison: &str| {
+        b.push(format!(
+            "(SELECT {}(id) FROM images WHERE id {} ",
+            aggregate, comparison
+        ))
+        .push_bind(image_id)
+        .push(" AND screen_id = (SELECT screen_id FROM images WHERE id = ")
+        .push_bind(image_id)
+        .push("))");
+    };
+
+    let mut builder = QueryBuilder::new(
+        "INSERT INTO texts (image_id, last_image_id, kind, text, \"left\", top, width, height)
+        SELECT ",
+    );
+    neighbour(&mut builder, "MIN", ">");
+    builder.push(", last_image_id, kind, text, \"left\", top, width, height FROM texts WHERE ");
+    spanning(&mut builder);
+    builder
+        .push(" AND image_id < ")
+        .push_bind(image_id)
+        .push(" AND last_image_id > ")
+        .push_bind(image_id);
+    builder.build().execute(&mut **tx).await?;
+
+    let mut builder = QueryBuilder::new("UPDATE texts SET last_image_id = ");
+    neighbour(&mut builder, "MAX", "<");
+    builder.push(" WHERE ");
+    spanning(&mut builder);
+    builder.push(" AND image_id < ").push_bind(image_id);
+    builder.build().execute(&mut **tx).await?;
+
+    let mut builder = QueryBuilder::new("UPDATE texts SET image_id = ");
+    neighbour(&mut builder, "MIN", ">");
+    builder.push(" WHERE ");
+    spanning(&mut builder);
+    builder.push(" AND image_id = ").push_bind(image_id);
+    builder.build().execute(&mut **tx).await?;
+    Ok(())
+}
+
+/// Delete the images along with their texts. Occurrences also seen in remaining images are
+/// trimmed to start and end at remaining images instead. Returns the deleted images in id
+/// order.
+#[cfg(feature = "postgres")]
+async fn delete_images_in(
+    tx: &mut Transaction<'_, Postgres>,
+    image_ids: &[i32],
+) -> Result<Vec<EntityImage>> {
+    sqlx::query(
+        "DELETE FROM texts t USING images f
+        WHERE f.id = t.image_id AND t.image_id = ANY($1)
+        AND NOT EXISTS (
+            SELECT 1 FROM images n
+            WHERE n.screen_id = f.screen_id AND n.id > t.image_id AND n.id <= t.last_image_id
+            AND NOT n.id = ANY($1)
+        )",
+    )
+    .bind(image_ids)
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query(
+        "UPDATE texts t SET image_id = (
+            SELECT MIN(n.id) FROM images n JOIN images f ON f.id = t.image_id
+            WHERE n.screen_id = f.screen_id AND n.id > t.image_id AND n.id <= t.last_image_id
+            AND NOT n.id = ANY($1)
+        )
+        WHERE t.image_id = ANY($1)",
+    )
+    .bind(image_ids)
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query(
+        "UPDATE texts t SET last_image_id = (
+            SELECT MAX(n.id) FROM images n JOIN images f ON f.id = t.image_id
+            WHERE n.screen_id = f.screen_id AND n.id >= t.image_id AND n.id < t.last_image_id
+            AND NOT n.id = ANY($1)
+        )
+        WHERE t.image_id < t.last_image_id AND t.last_image_id = ANY($1)",
+    )
+    .bind(image_ids)
+    .execute(&mut **tx)
+    .await?;
+    // sessions are trimmed the same way, and lose their representative image to their first
+    sqlx::query(
+        "DELETE FROM sessions s WHERE s.first_image_id = ANY($1)
+        AND NOT EXISTS (
+            SELECT 1 FROM images n
+            WHERE n.screen_id = s.screen_id
+            AND n.id BETWEEN s.first_image_id AND s.last_image_id
+            AND NOT n.id = ANY($1)
+        )",
+    )
+    .bind(image_ids)
+    .execute(&mut **tx)
+    .await?;
+    for (column, aggregate) in [("first_image_id", "MIN"), ("last_image_id", "MAX")] {
+        sqlx::query(&format!(
+            "UPDATE sessions s SET {0} = (
+                SELECT {1}(n.id) FROM images n
+                WHERE n.screen_id = s.screen_id
+                AND n.id BETWEEN s.first_image_id AND s.last_image_id
+                AND NOT n.id = ANY($1)
+            )
+            WHERE s.{0} = ANY($1)",
+            column, aggregate
+        ))
+        .bind(image_ids)
+        .execute(&mut **tx)
+        .await?;
+    }
+    sqlx::query(
+        "UPDATE sessions SET representative_image_id = first_image_id
+        WHERE representative_image_id = ANY($1)",
+    )
+    .bind(image_ids)
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query("DELETE FROM embeddings WHERE image_id = ANY($1)")
+        .bind(image_ids)
+        .execute(&mut **tx)
+        .await?;
+    let rows = sqlx::query(
+        "DELETE FROM images WHERE id = ANY($1)
+        RETURNING id, archive_type, archive_info, captured_at_epoch, screen_id",
+    )
+    .bind(image_ids)
+    .fetch_all(&mut **tx)
+    .await?;
+    images_from_rows(&rows)
+}
+
+/// Translate a search query into a tsquery requiring every term, a term ending with `*`
+/// matching as a prefix like in FTS5. `None` when the query has no word.
+#[cfg(feature = "postgres")]
+fn ts_query(query: &str) -> Option<String> {
+    let mut lexemes = Vec::new();
+    for term in query.split_whitespace() {
+        let prefix = term.ends_with('*');
+        let words: Vec<String> = term
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|it| !it.is_empty())
+            .map(|it| it.to_lowercase())
+            .collect();
+        for (i, word) in words.iter().enumerate() {
+            if prefix && i == words.len() - 1 {
+                lexemes.push(format!("'{}':*", word));
+            } else {
+                lexemes.push(format!("'{}'", word));
+            }
+        }
+    }
+    if lexemes.is_empty() {
+        return None;
+    }
+    Some(lexemes.join(" & "))
+}
+
+#[cfg(feature = "postgres")]
+fn escape_like(pattern: &str) -> String {
+    pattern
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Map the rows returned by a delete, in id order as the order of RETURNING is unspecified.
+#[cfg(feature = "postgres")]
+fn images_from_rows(rows: &[PgRow]) -> Result<Vec<EntityImage>> {
+    let mut images = rows
+        .iter()
+        .map(image_from_row)
+        .collect::<Result<Vec<_>>>()?;
+    images.sort_by_key(|it| it.id);
+    Ok(images)
+}
+
+/// Map a row of the columns `id, archive_type, archive_info, captured_at_epoch, screen_id`.
+#[cfg(feature = "postgres")]
+fn image_from_row(row: &PgRow) -> Result<EntityImage> {
+    let id: i32 = row.get(0);
+    let captured_at_epoch: i64 = row.get(3);
+    let screen_id: Option<i32> = row.get(4);
+    Ok(EntityImage {
+        id: id as u32,
+        archive_type: row.get(1),
+        archive_info: row.get(2),
+        captured_at_epoch: captured_at_epoch.try_into()?,
+        screen_id: screen_id.map(|it| it as u32),
+    })
+}
+
+/// Map a row of the `SESSION_COLUMNS`.
+#[cfg(feature = "postgres")]
+fn session_from_row(row: &PgRow) -> Result<EntitySession> {
+    let [id, screen_id, first_image_id, last_image_id, representative_image_id]: [i32; 5] =
+        [0, 1, 2, 3, 4].map(|index| row.get(index));
+    let started_at_epoch: i64 = row.get(5);
+    let ended_at_epoch: i64 = row.get(6);
+    Ok(EntitySession {
+        id: id as u32,
+        screen_id: screen_id as u32,
+        first_image_id: first_image_id as u32,
+        last_image_id: last_image_id as u32,
+        representative_image_id: representative_image_id as u32,
+        started_at_epoch: started_at_epoch.try_into()?,
+        ended_at_epoch: ended_at_epoch.try_into()?,
+    })
+}
+
+/// Map a row of the columns `id, image_id, kind, text, left, top, width, height` and
+/// `last_image_id`.
+#[cfg(feature = "postgres")]
+fn text_from_row(row: &PgRow) -> Result<EntityText> {
+    let kind: String = row.get(2);
+    let [id, image_id, left, top, width, height]: [i32; 6] =
+        [0, 1, 4, 5, 6, 7].map(|index| row.get(index));
+    let last_image_id: i32 = row.get("last_image_id");
+    Ok(EntityText {
+        id: id as u32,
+        image_id: image_id as u32,
+        kind: kind.parse()?,
+        text: row.get(3),
+        left: left as u32,
+        top: top as u32,
+        width: width as u32,
+        height: height as u32,
+        last_image_id: last_image_id as u32,
+    })
+}
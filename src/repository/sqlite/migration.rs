@@ -0,0 +1,315 @@
+use anyhow::{anyhow, Result};
+use sqlx::{Executor, Row};
+use sqlx_sqlite::Sqlite;
+use tracing::info;
+
+/// A schema change, applied in one transaction together with the record of its version.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+/// Ordered up-migrations embedded in the binary. A released migration must never be edited,
+/// append a new one instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        sql: include_str!("migrations/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        description: "index texts by image",
+        sql: include_str!("migrations/0002_texts_image_id_index.sql"),
+    },
+    Migration {
+        version: 3,
+        description: "record screen of images, index capture time",
+        sql: include_str!("migrations/0003_images_screen_id.sql"),
+    },
+    Migration {
+        version: 4,
+        description: "trigram index of texts",
+        sql: include_str!("migrations/0004_text_trigram.sql"),
+    },
+    Migration {
+        version: 5,
+        description: "texts seen across consecutive images",
+        sql: include_str!("migrations/0005_texts_last_image_id.sql"),
+    },
+    Migration {
+        version: 6,
+        description: "embeddings of lines",
+        sql: include_str!("migrations/0006_embeddings.sql"),
+    },
+    Migration {
+        version: 7,
+        description: "sessions of activity",
+        sql: include_str!("migrations/0007_sessions.sql"),
+    },
+    Migration {
+        version: 8,
+        description: "running counts for statistics",
+        sql: include_str!("migrations/0008_statistics.sql"),
+    },
+    Migration {
+        version: 9,
+        description: "index of the last images of texts",
+        sql: include_str!("migrations/0009_texts_last_image_id_index.sql"),
+    },
+];
+
+/// The schema version this binary brings databases to.
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |it| it.version)
+}
+
+/// The schema version of the database, 0 for a database never migrated.
+pub async fn current_version(pool: &sqlx::Pool<Sqlite>) -> Result<u32> {
+    if !table_exists(pool, "schema_version").await? {
+        return Ok(0);
+    }
+    let version: Option<u32> = sqlx::query("SELECT MAX(version) FROM schema_version")
+        .fetch_one(pool)
+        .await?
+        .get(0);
+    Ok(version.unwrap_or(0))
+}
+
+/// Apply the pending migrations.
+///
+/// A database written by a newer binary is refused instead of being touched. Before migrating a
+/// database holding data, a copy of it is written next to the database file.
+pub async fn migrate(pool: &sqlx::Pool<Sqlite>) -> Result<()> {
+    let current = current_version(pool).await?;
+    let latest = latest_version();
+    if current > latest {
+        return Err(anyhow!(
+            "database schema version {} is newer than {} supported by this binary, please upgrade dejavu",
+            current,
+            latest
+        ));
+    }
+    if current == latest {
+        return Ok(());
+    }
+
+    // databases created before the schema was versioned have tables but no version
+    let legacy = current == 0 && table_exists(pool, "images").await?;
+    if current > 0 || legacy {
+        backup(pool, current).await?;
+    }
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at_epoch INTEGER NOT NULL
+        )",
+    )
+    .execute(pool)
+    .await?;
+    if legacy {
+        adopt_legacy_schema(pool).await?;
+    }
+
+    for migration in MIGRATIONS.iter().filter(|it| it.version > current) {
+        let mut tx = pool.begin().await?;
+        (&mut *tx).execute(migration.sql).await?;
+        sqlx::query(
+            "INSERT INTO schema_version (version, description, applied_at_epoch) VALUES (?, ?, ?)",
+        )
+        .bind(migration.version)
+        .bind(migration.description)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        info!(
+            "applied migration {}: {}",
+            migration.version, migration.description
+        );
+    }
+    Ok(())
+}
+
+/// Bring a database created before schema versioning to the shape of the initial migration.
+async fn adopt_legacy_schema(pool: &sqlx::Pool<Sqlite>) -> Result<()> {
+    info!("adopting database created before schema versioning");
+    // databases created before decoding QR codes and barcodes have no kind column
+    let has_kind: i64 =
+        sqlx::query("SELECT COUNT(*) FROM pragma_table_info('texts') WHERE name = 'kind'")
+            .fetch_one(pool)
+            .await?
+            .get(0);
+    if has_kind == 0 && table_exists(pool, "texts").await? {
+        sqlx::query("ALTER TABLE texts ADD COLUMN kind TEXT NOT NULL DEFAULT 'ocr'")
+            .execute(pool)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Write a consistent copy of the database next to its file, skipped for in-memory databases.
+async fn backup(pool: &sqlx::Pool<Sqlite>, version: u32) -> Result<()> {
+    let file: String = sqlx::query("SELECT file FROM pragma_database_list WHERE name = 'main'")
+        .fetch_one(pool)
+        .await?
+        .get(0);
+    if file.is_empty() {
+        return Ok(());
+    }
+    let backup_path = format!(
+        "{}.v{}-{}.bak",
+        file,
+        version,
+        chrono::Local::now().format("%Y%m%d%H%M%S")
+    );
+    sqlx::query("VACUUM INTO ?")
+        .bind(&backup_path)
+        .execute(pool)
+        .await?;
+    info!("backed up database to {} before migrating", backup_path);
+    Ok(())
+}
+
+async fn table_exists(pool: &sqlx::Pool<Sqlite>, name: &str) -> Result<bool> {
+    let count: i64 =
+        sqlx::query("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?")
+            .bind(name)
+            .fetch_one(pool)
+            .await?
+            .get(0);
+    Ok(count > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use sqlx_sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+    use super::*;
+
+    async fn open(path: &Path) -> sqlx::Pool<Sqlite> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        SqlitePoolOptions::new()
+            .connect_with(options)
+            .await
+            .unwrap()
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dejavu-migration-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn backups_in(dir: &Path) -> Vec<PathBuf> {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .map(|it| it.unwrap().path())
+            .filter(|it| it.to_string_lossy().ends_with(".bak"))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn newer_schema_is_refused_untouched() {
+        let dir = temp_dir();
+        let pool = open(&dir.join("dejavu.db")).await;
+        sqlx::query(
+            "CREATE TABLE schema_version (
+                version INTEGER PRIMARY KEY,
+                description TEXT NOT NULL,
+                applied_at_epoch INTEGER NOT NULL
+            )",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO schema_version VALUES (?, 'from the future', 0)")
+            .bind(latest_version() + 1)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let error = migrate(&pool).await.unwrap_err().to_string();
+        assert!(error.contains("is newer than"), "{}", error);
+        assert!(!table_exists(&pool, "images").await.unwrap());
+        assert_eq!(current_version(&pool).await.unwrap(), latest_version() + 1);
+        assert!(backups_in(&dir).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn legacy_database_is_backed_up_and_adopted() {
+        let dir = temp_dir();
+        let pool = open(&dir.join("dejavu.db")).await;
+        // the schema before versioning, texts without a kind
+        pool.execute(
+            "CREATE TABLE images (
+                id INTEGER PRIMARY KEY,
+                archive_type TEXT NOT NULL,
+                archive_info TEXT NOT NULL,
+                captured_at_epoch INTEGER NOT NULL
+            );
+            CREATE TABLE texts (
+                id INTEGER PRIMARY KEY,
+                image_id INTEGER NOT NULL,
+                text TEXT NOT NULL,
+                left INTEGER NOT NULL,
+                top INTEGER NOT NULL,
+                width INTEGER NOT NULL,
+                height INTEGER NOT NULL
+            );
+            CREATE VIRTUAL TABLE text_fts USING fts5(text, text_id UNINDEXED);
+            INSERT INTO images VALUES (1, 'file_system', 'a.jpg', 1000);
+            INSERT INTO texts VALUES (1, 1, 'invoice', 10, 10, 60, 16);
+            INSERT INTO text_fts (text, text_id) VALUES ('invoice', 1);",
+        )
+        .await
+        .unwrap();
+
+        migrate(&pool).await.unwrap();
+        assert_eq!(current_version(&pool).await.unwrap(), latest_version());
+        let row = sqlx::query("SELECT text, kind, last_image_id FROM texts WHERE id = 1")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(row.get::<String, _>(0), "invoice");
+        assert_eq!(row.get::<String, _>(1), "ocr");
+        assert_eq!(row.get::<i64, _>(2), 1);
+
+        let backups = backups_in(&dir);
+        assert_eq!(backups.len(), 1);
+        assert!(backups[0]
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("dejavu.db.v0-"));
+        // the backup is the database as it was before migrating
+        let backup = open(&backups[0]).await;
+        assert_eq!(current_version(&backup).await.unwrap(), 0);
+        let kinds: i64 =
+            sqlx::query("SELECT COUNT(*) FROM pragma_table_info('texts') WHERE name = 'kind'")
+                .fetch_one(&backup)
+                .await
+                .unwrap()
+                .get(0);
+        assert_eq!(kinds, 0);
+        let texts: i64 = sqlx::query("SELECT COUNT(*) FROM texts")
+            .fetch_one(&backup)
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(texts, 1);
+
+        // an up to date database takes no other backup
+        migrate(&pool).await.unwrap();
+        assert_eq!(backups_in(&dir), backups);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
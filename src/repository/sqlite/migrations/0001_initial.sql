@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS images (
+    id INTEGER PRIMARY KEY,
+    archive_type TEXT NOT NULL,
+    archive_info TEXT NOT NULL,
+    captured_at_epoch INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS texts (
+    id INTEGER PRIMARY KEY,
+    image_id INTEGER NOT NULL,
+    text TEXT NOT NULL,
+    left INTEGER NOT NULL,
+    top INTEGER NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    kind TEXT NOT NULL DEFAULT 'ocr'
+);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS text_fts USING fts5(text, text_id UNINDEXED);
@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS texts_image_id ON texts (image_id);
@@ -0,0 +1,3 @@
+-- images captured before this migration have no known screen
+ALTER TABLE images ADD COLUMN screen_id INTEGER;
+CREATE INDEX IF NOT EXISTS images_captured_at_epoch ON images (captured_at_epoch);
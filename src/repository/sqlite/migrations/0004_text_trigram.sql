@@ -0,0 +1,3 @@
+-- trigram index of texts backing the typo tolerant search
+CREATE VIRTUAL TABLE IF NOT EXISTS text_trigram USING fts5(text, text_id UNINDEXED, tokenize = 'trigram');
+INSERT INTO text_trigram (text, text_id) SELECT text, id FROM texts;
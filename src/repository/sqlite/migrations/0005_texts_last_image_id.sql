@@ -0,0 +1,4 @@
+-- a text is stored once for the consecutive images of a screen it is seen in, from image_id to
+-- last_image_id
+ALTER TABLE texts ADD COLUMN last_image_id INTEGER NOT NULL DEFAULT 0;
+UPDATE texts SET last_image_id = image_id;
@@ -0,0 +1,11 @@
+-- embeddings of the lines of text first seen in an image, the vector holds little endian f32
+CREATE TABLE embeddings (
+    id INTEGER PRIMARY KEY,
+    image_id INTEGER NOT NULL,
+    model TEXT NOT NULL,
+    text_ids TEXT NOT NULL,
+    vector BLOB NOT NULL
+);
+
+CREATE INDEX embeddings_image_id ON embeddings (image_id);
+CREATE INDEX embeddings_model ON embeddings (model, image_id);
@@ -0,0 +1,12 @@
+-- sessions of activity, each spanning the images of its screen from first_image_id to
+-- last_image_id
+CREATE TABLE sessions (
+    id INTEGER PRIMARY KEY,
+    screen_id INTEGER NOT NULL,
+    first_image_id INTEGER NOT NULL,
+    last_image_id INTEGER NOT NULL,
+    representative_image_id INTEGER NOT NULL
+);
+
+CREATE INDEX sessions_screen_id ON sessions (screen_id, last_image_id);
+CREATE INDEX sessions_first_image_id ON sessions (first_image_id);
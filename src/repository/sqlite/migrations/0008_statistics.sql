@@ -0,0 +1,34 @@
+-- running counts kept up to date by triggers, so statistics never scan the large tables
+
+-- frames per day since the epoch in UTC and per screen, -1 for images without a screen
+CREATE TABLE frame_counts (
+    day INTEGER NOT NULL,
+    screen_id INTEGER NOT NULL,
+    frames INTEGER NOT NULL,
+    PRIMARY KEY (day, screen_id)
+);
+INSERT INTO frame_counts (day, screen_id, frames)
+SELECT captured_at_epoch / 86400, COALESCE(screen_id, -1), COUNT(*) FROM images GROUP BY 1, 2;
+
+CREATE TRIGGER frame_counts_insert AFTER INSERT ON images BEGIN
+    INSERT INTO frame_counts (day, screen_id, frames)
+    VALUES (new.captured_at_epoch / 86400, COALESCE(new.screen_id, -1), 1)
+    ON CONFLICT (day, screen_id) DO UPDATE SET frames = frames + 1;
+END;
+CREATE TRIGGER frame_counts_delete AFTER DELETE ON images BEGIN
+    UPDATE frame_counts SET frames = frames - 1
+    WHERE day = old.captured_at_epoch / 86400 AND screen_id = COALESCE(old.screen_id, -1);
+END;
+
+CREATE TABLE counters (
+    name TEXT PRIMARY KEY,
+    value INTEGER NOT NULL
+);
+INSERT INTO counters (name, value) SELECT 'texts', COUNT(*) FROM texts;
+
+CREATE TRIGGER texts_count_insert AFTER INSERT ON texts BEGIN
+    UPDATE counters SET value = value + 1 WHERE name = 'texts';
+END;
+CREATE TRIGGER texts_count_delete AFTER DELETE ON texts BEGIN
+    UPDATE counters SET value = value - 1 WHERE name = 'texts';
+END;
@@ -0,0 +1,2 @@
+-- occurrences are looked up by their last image when searching and deleting images
+CREATE INDEX IF NOT EXISTS texts_last_image_id ON texts (last_image_id);
@@ -0,0 +1,1078 @@
+use super::{
+    fuzzy, nearest_image, EntityEmbedding, EntityImage, EntitySession, EntityText, FrameCount,
+    MaintenanceReport, Repository, RepositoryStats, SearchOptions, SearchPage, SearchResult,
+    TimelineDirection, TimelineOptions, MAX_OCCURRENCE_SPAN,
+};
+use crate::{embedding, ocr::MarkupBox};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use sqlx::{QueryBuilder, Row, Transaction};
+use sqlx_sqlite::{Sqlite, SqliteRow};
+
+pub mod migration;
+
+/// Upper bound of the free pages given back per maintenance, bounding how long writers wait.
+const MAX_VACUUM_PAGES: u32 = 25_600;
+
+pub struct SqliteRepository {
+    pool: sqlx::Pool<sqlx_sqlite::Sqlite>,
+}
+
+impl SqliteRepository {
+    pub fn new(pool: sqlx::Pool<sqlx_sqlite::Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    /// Create or migrate the schema, see `migration::migrate`.
+    pub async fn initialize(&self) -> Result<()> {
+        migration::migrate(&self.pool).await
+    }
+
+    /// Size of the database file, the write-ahead log aside.
+    async fn database_bytes(&self) -> Result<u64> {
+        let bytes: i64 = sqlx::query(
+            "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .get(0);
+        Ok(bytes.try_into()?)
+    }
+
+    async fn exact_search(&self, options: &SearchOptions) -> Result<SearchPage> {
+        // an image ranks by its best matching text, bm25 is lower for better matches. The hits
+        // are materialized since bm25 is only available in the query of the fts table itself,
+        // and so are their frames which are read again for the texts of the page.
+        let rows = sqlx::query(&format!(
+            "WITH hits AS MATERIALIZED (
+                SELECT text_id, bm25(text_fts) AS rank FROM text_fts WHERE text_fts MATCH ?1
+            ),
+            framed AS MATERIALIZED (
+                SELECT h.text_id, h.rank, f.id AS image_id, f.captured_at_epoch, f.screen_id
+                FROM hits h
+                JOIN texts t ON t.id = h.text_id
+                JOIN images i ON i.id = t.image_id
+                JOIN images f ON f.id = {}
+                WHERE (?7 IS NULL OR f.captured_at_epoch <= ?7)
+                AND (?8 IS NULL OR f.screen_id = ?8)
+            ),
+            ranked AS (
+                SELECT image_id, captured_at_epoch, screen_id,
+                    MIN(rank) + ?2 * MAX(?3 - captured_at_epoch, 0) / 86400.0 AS score,
+                    COUNT(*) OVER () AS total
+                FROM framed
+                GROUP BY image_id
+                ORDER BY score, image_id
+                LIMIT ?4 OFFSET ?5
+            )
+            SELECT t.id, r.image_id, t.kind, t.text, t.left, t.top, t.width, t.height,
+                r.score, r.captured_at_epoch, r.total, r.screen_id, t.last_image_id
+            FROM ranked r
+            JOIN framed f ON f.image_id = r.image_id
+            JOIN texts t ON t.id = f.text_id
+            ORDER BY r.score, r.image_id, t.id",
+            first_frame_from("?6", "t.last_image_id")
+        ))
+        .bind(&options.text)
+        .bind(options.recency_weight)
+        .bind(chrono::Utc::now().timestamp())
+        .bind(options.limit)
+        .bind(options.offset)
+        .bind(options.from_epoch.map(|it| it as i64))
+        .bind(options.to_epoch.map(|it| it as i64))
+        .bind(options.screen_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut total = match rows.first() {
+            Some(row) => row.get::<i64, _>(10) as u64,
+            None => 0,
+        };
+        if rows.is_empty() && options.offset > 0 {
+            // a page past the end carries no total
+            let count: i64 = sqlx::query(&format!(
+                "SELECT COUNT(DISTINCT f.id) FROM text_fts
+                JOIN texts t ON t.id = text_fts.text_id
+                JOIN images i ON i.id = t.image_id
+                JOIN images f ON f.id = {}
+                WHERE text_fts MATCH ?1
+                AND (?3 IS NULL OR f.captured_at_epoch <= ?3)
+                AND (?4 IS NULL OR f.screen_id = ?4)",
+                first_frame_from("?2", "t.last_image_id")
+            ))
+            .bind(&options.text)
+            .bind(options.from_epoch.map(|it| it as i64))
+            .bind(options.to_epoch.map(|it| it as i64))
+            .bind(options.screen_id)
+            .fetch_one(&self.pool)
+            .await?
+            .get(0);
+            total = count as u64;
+        }
+
+        let mut results: Vec<SearchResult> = Vec::new();
+        for row in rows {
+            let text = text_from_row(&row)?;
+            match results.last_mut() {
+                Some(last) if last.image_id == text.image_id => last.texts.push(text),
+                _ => {
+                    let captured_at_epoch: i64 = row.get(9);
+                    results.push(SearchResult::new(
+                        text.image_id,
+                        captured_at_epoch.try_into()?,
+                        row.get(11),
+                        row.get(8),
+                        vec![text],
+                    ));
+                }
+            }
+        }
+        Ok(SearchPage { total, results })
+    }
+
+    /// Rank the texts sharing trigrams with the query by their edit distance to it, see
+    /// `fuzzy::rank`.
+    async fn fuzzy_search(
+        &self,
+        options: &SearchOptions,
+        trigram_query: &str,
+    ) -> Result<SearchPage> {
+        let rows = sqlx::query(&format!(
+            "SELECT t.id, f.id, t.kind, t.text, t.left, t.top, t.width, t.height,
+                f.captured_at_epoch, f.screen_id, t.last_image_id
+            FROM text_trigram
+            JOIN texts t ON t.id = text_trigram.text_id
+            JOIN images i ON i.id = t.image_id
+            JOIN images f ON f.id = {}
+            WHERE text_trigram MATCH ?1
+            AND (?3 IS NULL OR f.captured_at_epoch <= ?3)
+            AND (?4 IS NULL OR f.screen_id = ?4)
+            ORDER BY text_trigram.rank
+            LIMIT ?5",
+            first_frame_from("?2", "t.last_image_id")
+        ))
+        .bind(trigram_query)
+        .bind(options.from_epoch.map(|it| it as i64))
+        .bind(options.to_epoch.map(|it| it as i64))
+        .bind(options.screen_id)
+        .bind(fuzzy::MAX_CANDIDATES)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut candidates = Vec::with_capacity(rows.len());
+        for row in rows {
+            let captured_at_epoch: i64 = row.get(8);
+            candidates.push(fuzzy::Candidate {
+                text: text_from_row(&row)?,
+                captured_at_epoch: captured_at_epoch.try_into()?,
+                screen_id: row.get(9),
+            });
+        }
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        Ok(fuzzy::rank(options, candidates, now))
+    }
+}
+#[async_trait]
+impl Repository for SqliteRepository {
+    async fn save_frame_continuing(
+        &self,
+        image: &EntityImage,
+        texts: &[EntityText],
+        continued: &[u32],
+    ) -> Result<(EntityImage, Vec<EntityText>)> {
+        let mut tx = self.pool.begin().await?;
+        let query_result = sqlx::query(
+            "INSERT INTO images (archive_type, archive_info, captured_at_epoch, screen_id)
+            VALUES (?, ?, ?, ?)",
+        )
+        .bind(&image.archive_type)
+        .bind(&image.archive_info)
+        .bind(image.captured_at_epoch as i64)
+        .bind(image.screen_id)
+        .execute(&mut *tx)
+        .await?;
+        let image = EntityImage {
+            id: query_result.last_insert_rowid() as u32,
+            ..image.clone()
+        };
+        let mut new_texts = texts.to_vec();
+        let mut extended = vec![];
+        for chunk in continued.chunks(ID_CHUNK_SIZE) {
+            let mut builder = QueryBuilder::new("UPDATE texts SET last_image_id = ");
+            builder
+                .push_bind(image.id)
+                .push(" WHERE image_id >= ")
+                .push_bind(image.id as i64 - MAX_OCCURRENCE_SPAN as i64)
+                .push(
+                    " AND last_image_id = (
+                    SELECT MAX(id) FROM images WHERE screen_id = ",
+                )
+                .push_bind(image.screen_id)
+                .push(" AND id < ")
+                .push_bind(image.id)
+                .push(") AND id IN (");
+            let mut separated = builder.separated(", ");
+            for id in chunk {
+                separated.push_bind(*id);
+            }
+            builder.push(
+                ") RETURNING id, image_id, kind, text, left, top, width, height, last_image_id",
+            );
+            let rows = builder.build().fetch_all(&mut *tx).await?;
+            let updated = rows.iter().map(text_from_row).collect::<Result<Vec<_>>>()?;
+
+            // the others are stored again
+            let mut builder = QueryBuilder::new(
+                "SELECT id, image_id, kind, text, left, top, width, height, last_image_id
+                FROM texts WHERE id IN (",
+            );
+            let mut separated = builder.separated(", ");
+            for id in chunk {
+                separated.push_bind(*id);
+            }
+            builder.push(") ORDER BY id");
+            let rows = builder.build().fetch_all(&mut *tx).await?;
+            for row in rows.iter() {
+                let text = text_from_row(row)?;
+                if updated.iter().all(|it| it.id != text.id) {
+                    new_texts.push(text);
+                }
+            }
+            extended.extend(updated.into_iter().map(|it| EntityText {
+                image_id: image.id,
+                ..it
+            }));
+        }
+        extended.sort_by_key(|it| it.id);
+        let mut texts = insert_texts(&mut tx, image.id, &new_texts).await?;
+        texts.extend(extended);
+        tx.commit().await?;
+        Ok((image, texts))
+    }
+
+    async fn get_image_by_id(&self, id: u32) -> Result<EntityImage> {
+        let query = sqlx::query(
+            "SELECT id, archive_type, archive_info, captured_at_epoch, screen_id FROM images
+            WHERE id = ?",
+        )
+        .bind(id);
+        let row = query.fetch_one(&self.pool).await?;
+        image_from_row(&row)
+    }
+
+    async fn get_text_by_id(&self, id: u32) -> Result<EntityText> {
+        let query = sqlx::query(
+            "SELECT id, image_id, kind, text, left, top, width, height, last_image_id FROM texts
+            WHERE id = ?",
+        )
+        .bind(id);
+        let row = query.fetch_one(&self.pool).await?;
+        text_from_row(&row)
+    }
+
+    async fn get_texts_by_image_ids(&self, image_ids: &[u32]) -> Result<Vec<EntityText>> {
+        if image_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        // a text is seen in the images of its screen between its first and last image
+        let mut builder = QueryBuilder::new(
+            "SELECT t.id, i.id, t.kind, t.text, t.left, t.top, t.width, t.height, t.last_image_id
+            FROM images i
+            JOIN texts t ON t.image_id BETWEEN i.id - ",
+        );
+        builder.push_bind(MAX_OCCURRENCE_SPAN).push(
+            " AND i.id AND t.last_image_id >= i.id
+                AND (t.image_id = i.id
+                    OR (SELECT screen_id FROM images WHERE id = t.image_id) = i.screen_id)
+                WHERE i.id IN (",
+        );
+        let mut separated = builder.separated(", ");
+        for image_id in image_ids {
+            separated.push_bind(*image_id);
+        }
+        builder.push(") ORDER BY i.id, t.top, t.left, t.id");
+        let rows = builder.build().fetch_all(&self.pool).await?;
+        rows.iter().map(text_from_row).collect()
+    }
+
+    async fn search(&self, options: &SearchOptions) -> Result<SearchPage> {
+        if options.fuzzy {
+            // terms shorter than a trigram are only matched exactly
+            if let Some(trigram_query) = fuzzy::trigram_query(&options.text) {
+                return self.fuzzy_search(options, &trigram_query).await;
+            }
+        }
+        self.exact_search(options).await
+    }
+
+    async fn scan_images(
+        &self,
+        after_id: u32,
+        from_epoch: Option<u64>,
+        to_epoch: Option<u64>,
+        limit: u32,
+    ) -> Result<Vec<EntityImage>> {
+        let query = sqlx::query(
+            "SELECT id, archive_type, archive_info, captured_at_epoch, screen_id FROM images
+            WHERE id > ?1
+            AND (?2 IS NULL OR captured_at_epoch >= ?2)
+            AND (?3 IS NULL OR captured_at_epoch <= ?3)
+            ORDER BY id ASC
+            LIMIT ?4",
+        )
+        .bind(after_id)
+        .bind(from_epoch.map(|it| it as i64))
+        .bind(to_epoch.map(|it| it as i64))
+        .bind(limit);
+        let rows = query.fetch_all(&self.pool).await?;
+        rows.iter().map(image_from_row).collect()
+    }
+
+    async fn list_images(&self, options: &TimelineOptions) -> Result<Vec<EntityImage>> {
+        let query = sqlx::query(
+            "SELECT id, archive_type, archive_info, captured_at_epoch, screen_id FROM images
+            WHERE (?1 IS NULL OR (captured_at_epoch, id) > (?1, ?2))
+            AND (?3 IS NULL OR captured_at_epoch >= ?3)
+            AND (?4 IS NULL OR captured_at_epoch <= ?4)
+            AND (?5 IS NULL OR screen_id = ?5)
+            ORDER BY captured_at_epoch, id
+            LIMIT ?6",
+        )
+        .bind(options.after.map(|it| it.captured_at_epoch as i64))
+        .bind(options.after.map(|it| it.image_id))
+        .bind(options.from_epoch.map(|it| it as i64))
+        .bind(options.to_epoch.map(|it| it as i64))
+        .bind(options.screen_id)
+        .bind(options.limit);
+        let rows = query.fetch_all(&self.pool).await?;
+        rows.iter().map(image_from_row).collect()
+    }
+
+    async fn get_adjacent_image(
+        &self,
+        image_id: u32,
+        direction: TimelineDirection,
+        screen_id: Option<u32>,
+    ) -> Result<Option<EntityImage>> {
+        let origin = self.get_image_by_id(image_id).await?;
+        let sql = match direction {
+            TimelineDirection::Previous => {
+                "SELECT id, archive_type, archive_info, captured_at_epoch, screen_id FROM images
+                WHERE (captured_at_epoch, id) < (?1, ?2) AND (?3 IS NULL OR screen_id = ?3)
+                ORDER BY captured_at_epoch DESC, id DESC
+                LIMIT 1"
+            }
+            TimelineDirection::Next => {
+                "SELECT id, archive_type, archive_info, captured_at_epoch, screen_id FROM images
+                WHERE (captured_at_epoch, id) > (?1, ?2) AND (?3 IS NULL OR screen_id = ?3)
+                ORDER BY captured_at_epoch, id
+                LIMIT 1"
+            }
+        };
+        let row = sqlx::query(sql)
+            .bind(origin.captured_at_epoch as i64)
+            .bind(origin.id)
+            .bind(screen_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(image_from_row).transpose()
+    }
+
+    async fn get_nearest_image(
+        &self,
+        epoch: u64,
+        screen_id: Option<u32>,
+    ) -> Result<Option<EntityImage>> {
+        let before = sqlx::query(
+            "SELECT id, archive_type, archive_info, captured_at_epoch, screen_id FROM images
+            WHERE captured_at_epoch <= ?1 AND (?2 IS NULL OR screen_id = ?2)
+            ORDER BY captured_at_epoch DESC, id DESC
+            LIMIT 1",
+        )
+        .bind(epoch as i64)
+        .bind(screen_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        let after = sqlx::query(
+            "SELECT id, archive_type, archive_info, captured_at_epoch, screen_id FROM images
+            WHERE captured_at_epoch > ?1 AND (?2 IS NULL OR screen_id = ?2)
+            ORDER BY captured_at_epoch, id
+            LIMIT 1",
+        )
+        .bind(epoch as i64)
+        .bind(screen_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(nearest_image(
+            epoch,
+            before.as_ref().map(image_from_row).transpose()?,
+            after.as_ref().map(image_from_row).transpose()?,
+        ))
+    }
+
+    async fn delete_images(&self, image_ids: &[u32]) -> Result<Vec<EntityImage>> {
+        let mut tx = self.pool.begin().await?;
+        let mut deleted = Vec::with_capacity(image_ids.len());
+        for chunk in image_ids.chunks(ID_CHUNK_SIZE) {
+            let images = delete_images_where(&mut tx, |b, alias| {
+                b.push(format!("{}.id IN (", alias));
+                let mut separated = b.separated(", ");
+                for id in chunk {
+                    separated.push_bind(*id);
+                }
+                separated.push_unseparated(")");
+            })
+            .await?;
+            deleted.extend(images);
+        }
+        tx.commit().await?;
+        deleted.sort_by_key(|it| it.id);
+        Ok(deleted)
+    }
+
+    async fn delete_images_captured_between(
+        &self,
+        from_epoch: u64,
+        to_epoch: u64,
+    ) -> Result<Vec<EntityImage>> {
+        let mut tx = self.pool.begin().await?;
+        let deleted = delete_images_where(&mut tx, |b, alias| {
+            b.push(format!("{}.captured_at_epoch BETWEEN ", alias))
+                .push_bind(from_epoch as i64)
+                .push(" AND ")
+                .push_bind(to_epoch as i64);
+        })
+        .await?;
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    async fn replace_texts(
+        &self,
+        image_id: u32,
+        entities: &[EntityText],
+    ) -> Result<Vec<EntityText>> {
+        let mut tx = self.pool.begin().await?;
+        isolate_texts(&mut tx, image_id, |b| {
+            b.push("1");
+        })
+        .await?;
+        delete_texts(&mut tx, |b| {
+            b.push("SELECT id FROM texts WHERE image_id = ")
+                .push_bind(image_id);
+        })
+        .await?;
+        let result = insert_texts(&mut tx, image_id, entities).await?;
+        tx.commit().await?;
+        Ok(result)
+    }
+
+    async fn replace_texts_in_region(
+        &self,
+        image_id: u32,
+        region: &MarkupBox,
+        entities: &[EntityText],
+    ) -> Result<Vec<EntityText>> {
+        let inside = |b: &mut QueryBuilder<'_, Sqlite>| {
+            b.push("kind = 'ocr' AND left >= ")
+                .push_bind(region.left)
+                .push(" AND top >= ")
+                .push_bind(region.top)
+                .push(" AND left + width <= ")
+                .push_bind(region.left + region.width)
+                .push(" AND top + height <= ")
+                .push_bind(region.top + region.height);
+        };
+        let mut tx = self.pool.begin().await?;
+        isolate_texts(&mut tx, image_id, inside).await?;
+        delete_texts(&mut tx, |b| {
+            b.push("SELECT id FROM texts WHERE image_id = ")
+                .push_bind(image_id)
+                .push(" AND ");
+            inside(b);
+        })
+        .await?;
+        let result = insert_texts(&mut tx, image_id, entities).await?;
+        tx.commit().await?;
+        Ok(result)
+    }
+
+    async fn replace_embeddings(
+        &self,
+        image_id: u32,
+        embeddings: &[EntityEmbedding],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM embeddings WHERE image_id = ?")
+            .bind(image_id)
+            .execute(&mut *tx)
+            .await?;
+        for chunk in embeddings.chunks(INSERT_CHUNK_SIZE) {
+            let mut builder =
+                QueryBuilder::new("INSERT INTO embeddings (image_id, model, text_ids, vector) ");
+            builder.push_values(chunk, |mut b, it| {
+                b.push_bind(image_id)
+                    .push_bind(&it.model)
+                    .push_bind(embedding::encode_text_ids(&it.text_ids))
+                    .push_bind(embedding::encode_vector(&it.vector));
+            });
+            builder.build().execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_embeddings(
+        &self,
+        model: &str,
+        options: &SearchOptions,
+        limit: u32,
+    ) -> Result<Vec<(EntityEmbedding, EntityImage)>> {
+        // a line lasts until the last frame of its texts, which is looked up only for the lines
+        // first seen in the screen before the end of the range. The latest lines are read first
+        // from the index by model and image.
+        let rows = sqlx::query(&format!(
+            "SELECT e.image_id, e.model, e.text_ids, e.vector,
+                f.id, f.archive_type, f.archive_info, f.captured_at_epoch, f.screen_id
+            FROM embeddings e
+            JOIN images i ON i.id = e.image_id
+            JOIN images f ON f.id = {}
+            WHERE e.model = ?1
+            AND (?3 IS NULL OR i.captured_at_epoch <= ?3)
+            AND (?4 IS NULL OR i.screen_id = ?4)
+            AND (?3 IS NULL OR f.captured_at_epoch <= ?3)
+            ORDER BY e.image_id DESC, e.id
+            LIMIT ?5",
+            first_frame_from(
+                "?2",
+                "(SELECT MAX(t.last_image_id) FROM texts t
+                    WHERE t.id IN (SELECT value FROM json_each('[' || e.text_ids || ']')))"
+            )
+        ))
+        .bind(model)
+        .bind(options.from_epoch.map(|it| it as i64))
+        .bind(options.to_epoch.map(|it| it as i64))
+        .bind(options.screen_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut result = Vec::with_capacity(rows.len());
+        for row in rows {
+            let text_ids: String = row.get(2);
+            let vector: Vec<u8> = row.get(3);
+            let embedding = EntityEmbedding::new(
+                row.get(0),
+                row.get(1),
+                embedding::decode_text_ids(&text_ids)?,
+                embedding::decode_vector(&vector)?,
+            );
+            // the columns of the image follow those of the embedding
+            let captured_at_epoch: i64 = row.get(7);
+            let image = EntityImage::new(
+                row.get(4),
+                row.get(5),
+                row.get(6),
+                captured_at_epoch.try_into()?,
+                row.get(8),
+            );
+            result.push((embedding, image));
+        }
+        Ok(result)
+    }
+
+    async fn save_session(&self, session: &EntitySession) -> Result<EntitySession> {
+        let id: u32 = if session.id == 0 {
+            sqlx::query(
+                "INSERT INTO sessions
+                (screen_id, first_image_id, last_image_id, representative_image_id)
+                VALUES (?, ?, ?, ?) RETURNING id",
+            )
+            .bind(session.screen_id)
+            .bind(session.first_image_id)
+            .bind(session.last_image_id)
+            .bind(session.representative_image_id)
+            .fetch_one(&self.pool)
+            .await?
+            .get(0)
+        } else {
+            let updated = sqlx::query(
+                "UPDATE sessions SET screen_id = ?, first_image_id = ?, last_image_id = ?,
+                representative_image_id = ? WHERE id = ?",
+            )
+            .bind(session.screen_id)
+            .bind(session.first_image_id)
+            .bind(session.last_image_id)
+            .bind(session.representative_image_id)
+            .bind(session.id)
+            .execute(&self.pool)
+            .await?;
+            if updated.rows_affected() == 0 {
+                return Err(anyhow!("session {} not found", session.id));
+            }
+            session.id
+        };
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM {} WHERE s.id = ?",
+            SESSION_COLUMNS, SESSION_TABLES
+        ))
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+        session_from_row(&row)
+    }
+
+    async fn get_last_sessions(&self) -> Result<Vec<EntitySession>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM {} WHERE s.last_image_id =
+            (SELECT MAX(last_image_id) FROM sessions WHERE screen_id = s.screen_id)
+            ORDER BY s.screen_id",
+            SESSION_COLUMNS, SESSION_TABLES
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(session_from_row).collect()
+    }
+
+    async fn list_sessions(&self, options: &TimelineOptions) -> Result<Vec<EntitySession>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM {}
+            WHERE (?1 IS NULL OR (f.captured_at_epoch, s.first_image_id) > (?1, ?2))
+            AND (?3 IS NULL OR l.captured_at_epoch >= ?3)
+            AND (?4 IS NULL OR f.captured_at_epoch <= ?4)
+            AND (?5 IS NULL OR s.screen_id = ?5)
+            ORDER BY f.captured_at_epoch, s.first_image_id
+            LIMIT ?6",
+            SESSION_COLUMNS, SESSION_TABLES
+        ))
+        .bind(options.after.map(|it| it.captured_at_epoch as i64))
+        .bind(options.after.map(|it| it.image_id))
+        .bind(options.from_epoch.map(|it| it as i64))
+        .bind(options.to_epoch.map(|it| it as i64))
+        .bind(options.screen_id)
+        .bind(options.limit)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(session_from_row).collect()
+    }
+
+    async fn get_sessions_of_images(&self, image_ids: &[u32]) -> Result<Vec<(u32, EntitySession)>> {
+        let mut result = Vec::with_capacity(image_ids.len());
+        for chunk in image_ids.chunks(ID_CHUNK_SIZE) {
+            let mut builder = QueryBuilder::new(format!(
+                "SELECT {}, i.id FROM {} JOIN images i ON i.screen_id = s.screen_id
+                AND i.id BETWEEN s.first_image_id AND s.last_image_id
+                WHERE i.id IN (",
+                SESSION_COLUMNS, SESSION_TABLES
+            ));
+            let mut separated = builder.separated(", ");
+            for id in chunk {
+                separated.push_bind(*id);
+            }
+            separated.push_unseparated(") ORDER BY i.id");
+            let rows = builder.build().fetch_all(&self.pool).await?;
+            for row in rows {
+                // the id of the image follows the columns of the session
+                result.push((row.get(7), session_from_row(&row)?));
+            }
+        }
+        Ok(result)
+    }
+
+    async fn get_stats(&self) -> Result<RepositoryStats> {
+        let images: i64 = sqlx::query("SELECT COALESCE(SUM(frames), 0) FROM frame_counts")
+            .fetch_one(&self.pool)
+            .await?
+            .get(0);
+        let texts: i64 = sqlx::query("SELECT value FROM counters WHERE name = 'texts'")
+            .fetch_one(&self.pool)
+            .await?
+            .get(0);
+        let database_bytes = self.database_bytes().await?;
+        let rows = sqlx::query(
+            "SELECT day, screen_id, frames FROM frame_counts WHERE frames > 0
+            ORDER BY day, screen_id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let mut frame_counts = Vec::with_capacity(rows.len());
+        for row in rows {
+            let day: i64 = row.get(0);
+            let screen_id: i64 = row.get(1);
+            let frames: i64 = row.get(2);
+            frame_counts.push(FrameCount {
+                day: day.try_into()?,
+                // images without a screen are counted under -1
+                screen_id: screen_id.try_into().ok(),
+                frames: frames.try_into()?,
+            });
+        }
+        Ok(RepositoryStats {
+            images: images.try_into()?,
+            texts: texts.try_into()?,
+            database_bytes,
+            frame_counts,
+        })
+    }
+
+    async fn backup_database(&self, path: &str) -> Result<()> {
+        // unlike copying the file, the copy is consistent while other connections write
+        sqlx::query("VACUUM INTO ?")
+            .bind(path)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn maintain(&self) -> Result<MaintenanceReport> {
+        let before = self.database_bytes().await?;
+        // merge the segments the full text indexes accumulate with each insert and delete
+        sqlx::query("INSERT INTO text_fts (text_fts) VALUES ('optimize')")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("INSERT INTO text_trigram (text_trigram) VALUES ('optimize')")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("ANALYZE").execute(&self.pool).await?;
+        // free pages are only given back by databases created with incremental auto vacuum,
+        // older ones keep reusing them
+        sqlx::query(&format!("PRAGMA incremental_vacuum({})", MAX_VACUUM_PAGES))
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .execute(&self.pool)
+            .await?;
+        let after = self.database_bytes().await?;
+        Ok(MaintenanceReport {
+            reclaimed_bytes: before.saturating_sub(after),
+            database_bytes: after,
+        })
+    }
+}
+
+/// Id of the first frame captured from the epoch bound to `from` of the occurrence first seen in
+/// the image `i` and last seen in the image of `last_image_id`, NULL when it ends before. Every
+/// frame of an occurrence is an image of the screen of `i`.
+fn first_frame_from(from: &str, last_image_id: &str) -> String {
+    format!(
+        "CASE WHEN {0} IS NULL OR i.captured_at_epoch >= {0} THEN i.id ELSE (
+            SELECT MIN(n.id) FROM images n
+            WHERE n.screen_id = i.screen_id AND n.id > i.id AND n.id <= {1}
+            AND n.captured_at_epoch >= {0}
+        ) END",
+        from, last_image_id
+    )
+}
+
+/// Columns of a session read by `session_from_row`, selected from the `SESSION_TABLES`.
+const SESSION_COLUMNS: &str = "s.id, s.screen_id, s.first_image_id, s.last_image_id,
+    s.representative_image_id, f.captured_at_epoch, l.captured_at_epoch";
+/// Sessions joined with their first and last images.
+const SESSION_TABLES: &str = "sessions s
+    JOIN images f ON f.id = s.first_image_id
+    JOIN images l ON l.id = s.last_image_id";
+
+/// Rows per multi-row insert. With 8 parameters per text this stays below the limit of 999
+/// bound parameters of SQLite builds before 3.32.
+const INSERT_CHUNK_SIZE: usize = 120;
+
+/// Insert texts of the image with their full text search entries, in batches of bound
+/// parameters.
+async fn insert_texts(
+    tx: &mut Transaction<'_, Sqlite>,
+    image_id: u32,
+    entities: &[EntityText],
+) -> Result<Vec<EntityText>> {
+    let mut result = Vec::with_capacity(entities.len());
+    for chunk in entities.chunks(INSERT_CHUNK_SIZE) {
+        let mut builder = QueryBuilder::new(
+            "INSERT INTO texts (image_id, last_image_id, kind, text, left, top, width, height) ",
+        );
+        builder.push_values(chunk, |mut b, it| {
+            b.push_bind(image_id)
+                .push_bind(image_id)
+                .push_bind(it.kind.as_str())
+                .push_bind(&it.text)
+                .push_bind(it.left)
+                .push_bind(it.top)
+                .push_bind(it.width)
+                .push_bind(it.height);
+        });
+        // the order of rows returned by RETURNING is unspecified, so every row carries the
+        // whole text instead of being matched with the inserted entities by position
+        builder
+            .push(" RETURNING id, image_id, kind, text, left, top, width, height, last_image_id");
+        let rows = builder.build().fetch_all(&mut **tx).await?;
+        let mut inserted = rows.iter().map(text_from_row).collect::<Result<Vec<_>>>()?;
+        inserted.sort_by_key(|it| it.id);
+        index_texts(tx, &inserted).await?;
+        result.extend(inserted);
+    }
+    Ok(result)
+}
+
+/// Add the texts to the search indexes.
+async fn index_texts(tx: &mut Transaction<'_, Sqlite>, texts: &[EntityText]) -> Result<()> {
+    for chunk in texts.chunks(INSERT_CHUNK_SIZE) {
+        for index in ["text_fts", "text_trigram"] {
+            let mut builder = QueryBuilder::new(format!("INSERT INTO {} (text, text_id) ", index));
+            builder.push_values(chunk, |mut b, it| {
+                b.push_bind(&it.text).push_bind(it.id);
+            });
+            builder.build().execute(&mut **tx).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Split the occurrences seen in the image and in other images too, among the texts matching
+/// the condition pushed by `condition`, so that those seen in the image are only seen in it:
+/// the part of an occurrence after the image is stored as a new text, and the occurrence is
+/// cut before the image.
+async fn isolate_texts<F>(
+    tx: &mut Transaction<'_, Sqlite>,
+    image_id: u32,
+    condition: F,
+) -> Result<()>
+where
+    F: Fn(&mut QueryBuilder<'_, Sqlite>) + Sync,
+{
+    let spanning = |b: &mut QueryBuilder<'_, Sqlite>| {
+        b.push("image_id < last_image_id AND image_id BETWEEN ")
+            .push_bind(image_id as i64 - MAX_OCCURRENCE_SPAN as i64)
+            .push(" AND ")
+            .push_bind(image_id)
+            .push(" AND last_image_id >= ")
+            .push_bind(image_id)
+            .push(" AND (image_id = ")
+            .push_bind(image_id)
+            .push(
+                " OR (SELECT screen_id FROM images WHERE id = texts.image_id)
+                = (SELECT screen_id FROM images WHERE id = ",
+            )
+            .push_bind(image_id)
+            .push(")) AND (");
+        condition(b);
+        b.push(")");
+    };
+    // the neighbours of the image on its screen, they exist within a span
+    let neighbour = |b: &mut QueryBuilder<'_, Sqlite>, aggregate: &str, comparison: &str| {
+        b.push(format!(
+            "(SELECT {}(id) FROM images WHERE id {} ",
+            aggregate, comparison
+        ))
+        .push_bind(image_id)
+        .push(" AND screen_id = (SELECT screen_id FROM images WHERE id = ")
+        .push_bind(image_id)
+        .push("))");
+    };
+
+    let mut builder = QueryBuilder::new(
+        "INSERT INTO texts (image_id, last_image_id, kind, text, left, top, width, height) SELECT ",
+    );
+    neighbour(&mut builder, "MIN", ">");
+    builder.push(", last_image_id, kind, text, left, top, width, height FROM texts WHERE ");
+    spanning(&mut builder);
+    builder
+        .push(" AND image_id < ")
+        .push_bind(image_id)
+        .push(" AND last_image_id > ")
+        .push_bind(image_id)
+        .push(" RETURNING id, image_id, kind, text, left, top, width, height, last_image_id");
+    let rows = builder.build().fetch_all(&mut **tx).await?;
+    let rest = rows.iter().map(text_from_row).collect::<Result<Vec<_>>>()?;
+    index_texts(tx, &rest).await?;
+
+    let mut builder = QueryBuilder::new("UPDATE texts SET last_image_id = ");
+    neighbour(&mut builder, "MAX", "<");
+    builder.push(" WHERE ");
+    spanning(&mut builder);
+    builder.push(" AND image_id < ").push_bind(image_id);
+    builder.build().execute(&mut **tx).await?;
+
+    let mut builder = QueryBuilder::new("UPDATE texts SET image_id = ");
+    neighbour(&mut builder, "MIN", ">");
+    builder.push(" WHERE ");
+    spanning(&mut builder);
+    builder.push(" AND image_id = ").push_bind(image_id);
+    builder.build().execute(&mut **tx).await?;
+    Ok(())
+}
+
+/// Delete the texts whose ids are selected by the subquery pushed by `select_ids`, along with
+/// their entries in the search indexes.
+async fn delete_texts<F>(tx: &mut Transaction<'_, Sqlite>, select_ids: F) -> Result<()>
+where
+    F: Fn(&mut QueryBuilder<'_, Sqlite>) + Sync,
+{
+    // the texts go last, the subquery selects from them
+    for (table, column) in [
+        ("text_fts", "text_id"),
+        ("text_trigram", "text_id"),
+        ("texts", "id"),
+    ] {
+        let mut builder = QueryBuilder::new(format!("DELETE FROM {} WHERE {} IN (", table, column));
+        select_ids(&mut builder);
+        builder.push(")");
+        builder.build().execute(&mut **tx).await?;
+    }
+    Ok(())
+}
+
+/// Ids per statement, bound up to twice within the limit of 999 bound parameters.
+const ID_CHUNK_SIZE: usize = 400;
+
+/// Delete the images matching the condition pushed by `condition` on the `images` table aliased
+/// as its second argument, along with their texts. Occurrences also seen in remaining images
+/// are trimmed to start and end at remaining images instead. Returns the deleted images in id
+/// order.
+async fn delete_images_where<F>(
+    tx: &mut Transaction<'_, Sqlite>,
+    condition: F,
+) -> Result<Vec<EntityImage>>
+where
+    F: Fn(&mut QueryBuilder<'_, Sqlite>, &str) + Sync,
+{
+    // the images remaining in the span of an occurrence first seen in a deleted image
+    let remaining = |b: &mut QueryBuilder<'_, Sqlite>, select: &str| {
+        b.push(format!(
+            "SELECT {} FROM images n JOIN images f ON f.id = t.image_id
+            WHERE n.screen_id = f.screen_id AND n.id > t.image_id AND n.id <= t.last_image_id
+            AND NOT (",
+            select
+        ));
+        condition(b, "n");
+        b.push(")");
+    };
+
+    delete_texts(tx, |b| {
+        b.push("SELECT t.id FROM texts t JOIN images f ON f.id = t.image_id WHERE ");
+        condition(b, "f");
+        b.push(" AND NOT EXISTS (");
+        remaining(b, "1");
+        b.push(")");
+    })
+    .await?;
+
+    let mut builder = QueryBuilder::new("UPDATE texts AS t SET image_id = (");
+    remaining(&mut builder, "MIN(n.id)");
+    builder.push(") WHERE t.image_id IN (SELECT f.id FROM images f WHERE ");
+    condition(&mut builder, "f");
+    builder.push(")");
+    builder.build().execute(&mut **tx).await?;
+
+    // and those last seen in a deleted image end at the last remaining one
+    let mut builder = QueryBuilder::new(
+        "UPDATE texts AS t SET last_image_id = (
+        SELECT MAX(n.id) FROM images n JOIN images f ON f.id = t.image_id
+        WHERE n.screen_id = f.screen_id AND n.id >= t.image_id AND n.id < t.last_image_id
+        AND NOT (",
+    );
+    condition(&mut builder, "n");
+    builder.push(
+        ")) WHERE t.image_id < t.last_image_id
+        AND t.last_image_id IN (SELECT f.id FROM images f WHERE ",
+    );
+    condition(&mut builder, "f");
+    builder.push(")");
+    builder.build().execute(&mut **tx).await?;
+
+    // sessions are trimmed the same way, and lose their representative image to their first
+    let in_session = |b: &mut QueryBuilder<'_, Sqlite>, select: &str| {
+        b.push(format!(
+            "SELECT {} FROM images n
+            WHERE n.screen_id = s.screen_id AND n.id BETWEEN s.first_image_id AND s.last_image_id
+            AND NOT (",
+            select
+        ));
+        condition(b, "n");
+        b.push(")");
+    };
+    let mut builder = QueryBuilder::new(
+        "DELETE FROM sessions AS s WHERE s.first_image_id IN (SELECT f.id FROM images f WHERE ",
+    );
+    condition(&mut builder, "f");
+    builder.push(") AND NOT EXISTS (");
+    in_session(&mut builder, "1");
+    builder.push(")");
+    builder.build().execute(&mut **tx).await?;
+    for (column, aggregate) in [("first_image_id", "MIN"), ("last_image_id", "MAX")] {
+        let mut builder = QueryBuilder::new(format!("UPDATE sessions AS s SET {} = (", column));
+        in_session(&mut builder, &format!("{}(n.id)", aggregate));
+        builder.push(format!(
+            ") WHERE s.{} IN (SELECT f.id FROM images f WHERE ",
+            column
+        ));
+        condition(&mut builder, "f");
+        builder.push(")");
+        builder.build().execute(&mut **tx).await?;
+    }
+    let mut builder = QueryBuilder::new(
+        "UPDATE sessions SET representative_image_id = first_image_id
+        WHERE representative_image_id IN (SELECT f.id FROM images f WHERE ",
+    );
+    condition(&mut builder, "f");
+    builder.push(")");
+    builder.build().execute(&mut **tx).await?;
+
+    let mut builder = QueryBuilder::new(
+        "DELETE FROM embeddings WHERE image_id IN (SELECT f.id FROM images f WHERE ",
+    );
+    condition(&mut builder, "f");
+    builder.push(")");
+    builder.build().execute(&mut **tx).await?;
+
+    let mut builder = QueryBuilder::new("DELETE FROM images WHERE ");
+    condition(&mut builder, "images");
+    builder.push(" RETURNING id, archive_type, archive_info, captured_at_epoch, screen_id");
+    let rows = builder.build().fetch_all(&mut **tx).await?;
+    let mut deleted = rows
+        .iter()
+        .map(image_from_row)
+        .collect::<Result<Vec<_>>>()?;
+    // the order of rows returned by RETURNING is unspecified
+    deleted.sort_by_key(|it| it.id);
+    Ok(deleted)
+}
+
+/// Map a row of the columns `id, archive_type, archive_info, captured_at_epoch, screen_id`.
+fn image_from_row(row: &SqliteRow) -> Result<EntityImage> {
+    let captured_at_epoch: i64 = row.get(3);
+    Ok(EntityImage {
+        id: row.get(0),
+        archive_type: row.get(1),
+        archive_info: row.get(2),
+        captured_at_epoch: captured_at_epoch.try_into()?,
+        screen_id: row.get(4),
+    })
+}
+
+/// Map a row of the `SESSION_COLUMNS`.
+fn session_from_row(row: &SqliteRow) -> Result<EntitySession> {
+    let started_at_epoch: i64 = row.get(5);
+    let ended_at_epoch: i64 = row.get(6);
+    Ok(EntitySession {
+        id: row.get(0),
+        screen_id: row.get(1),
+        first_image_id: row.get(2),
+        last_image_id: row.get(3),
+        representative_image_id: row.get(4),
+        started_at_epoch: started_at_epoch.try_into()?,
+        ended_at_epoch: ended_at_epoch.try_into()?,
+    })
+}
+
+/// Map a row of the columns `id, image_id, kind, text, left, top, width, height` and
+/// `last_image_id`.
+fn text_from_row(row: &SqliteRow) -> Result<EntityText> {
+    let kind: String = row.get(2);
+    Ok(EntityText {
+        id: row.get(0),
+        image_id: row.get(1),
+        kind: kind.parse()?,
+        text: row.get(3),
+        left: row.get(4),
+        top: row.get(5),
+        width: row.get(6),
+        height: row.get(7),
+        last_image_id: row.get("last_image_id"),
+    })
+}
@@ -5,6 +5,8 @@ use async_trait::async_trait;
 use image::DynamicImage;
 use screenshots::{Image, Screen};
 
+pub mod scripted;
+
 #[async_trait]
 pub trait Capturer {
     /// Capture the contents of all the screens, returning a vector of images.
@@ -17,15 +19,23 @@ impl DefaultCapturer {
     pub fn new() -> Self {
         DefaultCapturer {}
     }
+
+    /// Ids of the screens which would be captured.
+    pub fn screen_ids(&self) -> anyhow::Result<Vec<u32>> {
+        Ok(Screen::all()?
+            .iter()
+            .map(|screen| screen.display_info.id)
+            .collect())
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Metadata {
     pub screen_id: u32,
     pub captured_at_epoch: u64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Screenshot {
     pub image: DynamicImage,
     pub metadata: Metadata,
@@ -73,8 +83,8 @@ impl Capturer for DefaultCapturer {
 fn screen_image_2_image_image(screen_image: Image) -> anyhow::Result<DynamicImage> {
     let buffer = screen_image.rgba().to_owned();
     let image: image::RgbaImage = image::RgbaImage::from_raw(
-        screen_image.width() as u32,
-        screen_image.height() as u32,
+        screen_image.width(),
+        screen_image.height(),
         buffer,
     )
     .ok_or(anyhow!("load screen image to image::RgbaImage"))?;
@@ -0,0 +1,49 @@
+#[cfg(any(test, feature = "in-memory"))]
+use {
+    super::{Capturer, Metadata, Screenshot},
+    async_trait::async_trait,
+    image::DynamicImage,
+    std::collections::VecDeque,
+    tokio::sync::Mutex,
+};
+
+/// Capturer replaying a prepared sequence of captures, for tests without a display.
+///
+/// Each call to `capture` returns the next scripted batch, and an empty batch once the script
+/// is exhausted.
+#[cfg(any(test, feature = "in-memory"))]
+pub struct ScriptedCapturer {
+    script: Mutex<VecDeque<Vec<Screenshot>>>,
+}
+
+#[cfg(any(test, feature = "in-memory"))]
+impl ScriptedCapturer {
+    pub fn new() -> Self {
+        Self {
+            script: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Append a batch holding one screenshot per `(screen_id, image)` pair.
+    pub async fn push(&self, captured_at_epoch: u64, screens: Vec<(u32, DynamicImage)>) {
+        let batch = screens
+            .into_iter()
+            .map(|(screen_id, image)| Screenshot {
+                image,
+                metadata: Metadata {
+                    screen_id,
+                    captured_at_epoch,
+                },
+            })
+            .collect();
+        self.script.lock().await.push_back(batch);
+    }
+}
+
+#[cfg(any(test, feature = "in-memory"))]
+#[async_trait]
+impl Capturer for ScriptedCapturer {
+    async fn capture(&self) -> anyhow::Result<Vec<Screenshot>> {
+        Ok(self.script.lock().await.pop_front().unwrap_or_default())
+    }
+}
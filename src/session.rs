@@ -0,0 +1,230 @@
+//! Sessions of activity, grouping the frames of a screen into moments like a meeting or a
+//! debugging session.
+//!
+//! A screen stays in the same session until nothing is captured from it for a while, or until
+//! its content changes at once, which is also how switching to another application shows since
+//! the capture does not record applications.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Result;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::{
+    embedding,
+    repository::{EntityImage, EntitySession, EntityText, Repository},
+};
+
+/// Images fetched from the repository per scan.
+const BATCH_SIZE: u32 = 256;
+/// Images captured within this many seconds are left to the next run, their frames may still be
+/// being recorded.
+const SETTLE_SECS: u64 = 10;
+
+#[derive(Debug, Clone)]
+pub struct SessionOptions {
+    /// Longest pause in seconds between two frames of a session.
+    pub max_gap_secs: u64,
+    /// Smallest share of words two consecutive frames of a session have in common, from 0 to 1.
+    pub min_overlap: f64,
+    /// Pause between two runs of the background job.
+    pub interval_secs: u64,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        Self {
+            max_gap_secs: 300,
+            min_overlap: 0.2,
+            interval_secs: 60,
+        }
+    }
+}
+
+/// The latest session of a screen, which the next frames of the screen may extend.
+struct OpenSession {
+    session: EntitySession,
+    /// Words of the last image of the session.
+    words: HashSet<String>,
+    /// Number of words of the representative image.
+    representative_words: usize,
+    /// Whether the session changed since it was saved.
+    changed: bool,
+}
+
+impl OpenSession {
+    fn new(session: EntitySession, words: HashSet<String>, representative_words: usize) -> Self {
+        Self {
+            session,
+            words,
+            representative_words,
+            changed: false,
+        }
+    }
+
+    fn continues_with(
+        &self,
+        image: &EntityImage,
+        words: &HashSet<String>,
+        options: &SessionOptions,
+    ) -> bool {
+        image
+            .captured_at_epoch
+            .saturating_sub(self.session.ended_at_epoch)
+            <= options.max_gap_secs
+            && overlap(&self.words, words) >= options.min_overlap
+    }
+
+    /// Append the image to the session, the image with the most words stands for the session.
+    fn extend(&mut self, image: &EntityImage, words: HashSet<String>) {
+        self.session.last_image_id = image.id;
+        self.session.ended_at_epoch = image.captured_at_epoch;
+        if words.len() > self.representative_words {
+            self.session.representative_image_id = image.id;
+            self.representative_words = words.len();
+        }
+        self.words = words;
+        self.changed = true;
+    }
+}
+
+/// Background job segmenting the recorded frames into sessions as they come.
+///
+/// Each run continues after the last segmented image, extending the latest session of each
+/// screen. Images recorded without a screen are left out of sessions.
+pub struct Segmenter {
+    repo: Arc<dyn Repository + Send + Sync>,
+    options: SessionOptions,
+}
+
+impl Segmenter {
+    pub fn new(repo: Arc<dyn Repository + Send + Sync>, options: SessionOptions) -> Self {
+        Self { repo, options }
+    }
+
+    /// Segment the images until cancelled, a failed run is retried with the next one.
+    pub async fn run(&self, token: CancellationToken) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.options.interval_secs));
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => {
+                    info!("shutting down session segmentation");
+                    break;
+                },
+                _ = interval.tick() => {
+                    let now = chrono::Utc::now().timestamp().max(0) as u64;
+                    if let Err(e) = self.segment(now.saturating_sub(SETTLE_SECS)).await {
+                        warn!("failed to segment sessions: {}", e);
+                    }
+                },
+            }
+        }
+    }
+
+    /// Segment the images captured at or before `until_epoch` which are not in a session yet.
+    /// Returns the number of segmented images.
+    pub async fn segment(&self, until_epoch: u64) -> Result<u64> {
+        let mut open: HashMap<u32, OpenSession> = HashMap::new();
+        for session in self.repo.get_last_sessions().await? {
+            let texts = self
+                .repo
+                .get_texts_by_image_ids(&[session.last_image_id, session.representative_image_id])
+                .await?;
+            let last_words = words_of(&texts, session.last_image_id);
+            let representative_words = words_of(&texts, session.representative_image_id).len();
+            open.insert(
+                session.screen_id,
+                OpenSession::new(session, last_words, representative_words),
+            );
+        }
+        let mut after_id = open
+            .values()
+            .map(|it| it.session.last_image_id)
+            .max()
+            .unwrap_or(0);
+
+        let mut segmented = 0;
+        loop {
+            let images = self
+                .repo
+                .scan_images(after_id, None, None, BATCH_SIZE)
+                .await?;
+            // images are scanned in id order, which is the capture order
+            let images: Vec<EntityImage> = images
+                .into_iter()
+                .take_while(|it| it.captured_at_epoch <= until_epoch)
+                .collect();
+            let Some(last) = images.last() else {
+                break;
+            };
+            after_id = last.id;
+
+            let image_ids: Vec<u32> = images.iter().map(|it| it.id).collect();
+            let texts = self.repo.get_texts_by_image_ids(&image_ids).await?;
+            for image in images {
+                let Some(screen_id) = image.screen_id else {
+                    continue;
+                };
+                let words = words_of(&texts, image.id);
+                match open.get_mut(&screen_id) {
+                    Some(current) if current.continues_with(&image, &words, &self.options) => {
+                        current.extend(&image, words);
+                    }
+                    current => {
+                        // the previous session of the screen is complete
+                        if let Some(previous) = current {
+                            self.save(previous).await?;
+                        }
+                        let session = self
+                            .repo
+                            .save_session(&EntitySession::new(
+                                0, screen_id, image.id, image.id, image.id,
+                            ))
+                            .await?;
+                        let count = words.len();
+                        open.insert(screen_id, OpenSession::new(session, words, count));
+                    }
+                }
+                segmented += 1;
+            }
+            for current in open.values_mut() {
+                self.save(current).await?;
+            }
+        }
+        if segmented > 0 {
+            info!("segmented {} images into sessions", segmented);
+        }
+        Ok(segmented)
+    }
+
+    async fn save(&self, open: &mut OpenSession) -> Result<()> {
+        if open.changed {
+            open.session = self.repo.save_session(&open.session).await?;
+            open.changed = false;
+        }
+        Ok(())
+    }
+}
+
+/// The distinct words of the texts seen in the image.
+fn words_of(texts: &[EntityText], image_id: u32) -> HashSet<String> {
+    texts
+        .iter()
+        .filter(|it| it.image_id == image_id)
+        .flat_map(|it| embedding::words(&it.text))
+        .collect()
+}
+
+/// Share of the words in common, 1 for two frames without words.
+fn overlap(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
@@ -0,0 +1,151 @@
+//! Text snippets of search hits, reconstructed from the boxes of the words around them.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::repository::{EntityText, TextKind};
+
+/// Words kept on each side of a hit.
+const CONTEXT_WORDS: usize = 6;
+/// Upper bound of the snippets of one image.
+const MAX_SNIPPETS: usize = 3;
+/// Horizontal gap, in line heights, beyond which two words on the same line belong to
+/// different columns.
+const MAX_WORD_GAP_IN_LINE_HEIGHTS: u32 = 3;
+
+/// A line of text around search hits.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snippet {
+    pub fragments: Vec<SnippetFragment>,
+}
+
+/// A run of text in a snippet, `highlighted` when it is a search hit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnippetFragment {
+    pub text: String,
+    pub highlighted: bool,
+}
+
+/// Build the snippets of the hits of an image out of the words recognized in the image.
+///
+/// Each hit is shown within the words of its line, a line being the words vertically
+/// overlapping the hit and not separated from it by a wide gap. Hits sharing a line share a
+/// snippet.
+pub fn build_snippets(hits: &[EntityText], words: &[EntityText]) -> Vec<Snippet> {
+    let hit_ids: HashSet<u32> = hits.iter().map(|it| it.id).collect();
+    let mut covered: HashSet<u32> = HashSet::new();
+    let mut snippets = Vec::new();
+    for hit in hits {
+        if snippets.len() >= MAX_SNIPPETS {
+            break;
+        }
+        if covered.contains(&hit.id) {
+            continue;
+        }
+        let line = line_of(hit, words);
+        // the hit itself is not among the words when it is a decoded code
+        let Some(position) = line.iter().position(|it| it.id == hit.id) else {
+            covered.insert(hit.id);
+            snippets.push(fragments_of(&[hit], 0, 1, &hit_ids));
+            continue;
+        };
+        let start = position.saturating_sub(CONTEXT_WORDS);
+        let end = (position + CONTEXT_WORDS + 1).min(line.len());
+        covered.extend(line[start..end].iter().map(|it| it.id));
+        snippets.push(fragments_of(&line[start..end], start, line.len(), &hit_ids));
+    }
+    snippets
+}
+
+/// Group the texts of an image into lines in reading order, each decoded code being a line of
+/// its own.
+pub fn lines(texts: &[EntityText]) -> Vec<Vec<&EntityText>> {
+    let mut sorted: Vec<&EntityText> = texts.iter().collect();
+    sorted.sort_by_key(|it| (it.top, it.left, it.id));
+    let mut covered: HashSet<u32> = HashSet::new();
+    let mut lines = Vec::new();
+    for text in sorted {
+        if covered.contains(&text.id) {
+            continue;
+        }
+        let line: Vec<&EntityText> = match text.kind {
+            TextKind::Ocr => line_of(text, texts)
+                .into_iter()
+                .filter(|it| !covered.contains(&it.id))
+                .collect(),
+            _ => vec![text],
+        };
+        covered.extend(line.iter().map(|it| it.id));
+        lines.push(line);
+    }
+    lines
+}
+
+/// The OCR words on the line of the hit, in reading order.
+fn line_of<'a>(hit: &EntityText, words: &'a [EntityText]) -> Vec<&'a EntityText> {
+    let mut line: Vec<&EntityText> = words
+        .iter()
+        .filter(|it| it.kind == TextKind::Ocr && it.image_id == hit.image_id)
+        .filter(|it| same_line(hit, it))
+        .collect();
+    line.sort_by_key(|it| it.left);
+
+    // keep the run of words around the hit without a gap wider than a few line heights
+    let max_gap = hit.height.max(1) * MAX_WORD_GAP_IN_LINE_HEIGHTS;
+    let Some(position) = line.iter().position(|it| it.id == hit.id) else {
+        return line;
+    };
+    let mut start = position;
+    while start > 0 && gap(line[start - 1], line[start]) <= max_gap {
+        start -= 1;
+    }
+    let mut end = position + 1;
+    while end < line.len() && gap(line[end - 1], line[end]) <= max_gap {
+        end += 1;
+    }
+    line[start..end].to_vec()
+}
+
+/// Whether the boxes overlap vertically by at least half of the shorter one.
+fn same_line(a: &EntityText, b: &EntityText) -> bool {
+    let top = a.top.max(b.top);
+    let bottom = (a.top + a.height).min(b.top + b.height);
+    let overlap = bottom.saturating_sub(top);
+    overlap * 2 >= a.height.min(b.height).max(1)
+}
+
+fn gap(left: &EntityText, right: &EntityText) -> u32 {
+    right.left.saturating_sub(left.left + left.width)
+}
+
+/// Join the words into fragments, merging consecutive words which are not hits. An ellipsis
+/// marks words of the line left out of the snippet.
+fn fragments_of(
+    words: &[&EntityText],
+    start: usize,
+    line_len: usize,
+    hit_ids: &HashSet<u32>,
+) -> Snippet {
+    let mut fragments: Vec<SnippetFragment> = Vec::new();
+    let mut push = |text: &str, highlighted: bool| match fragments.last_mut() {
+        Some(last) if !last.highlighted && !highlighted => last.text.push_str(text),
+        _ => fragments.push(SnippetFragment {
+            text: text.to_string(),
+            highlighted,
+        }),
+    };
+    if start > 0 {
+        push("… ", false);
+    }
+    for (i, word) in words.iter().enumerate() {
+        if i > 0 {
+            push(" ", false);
+        }
+        push(&word.text, hit_ids.contains(&word.id));
+    }
+    if start + words.len() < line_len {
+        push(" …", false);
+    }
+    Snippet { fragments }
+}
@@ -0,0 +1,157 @@
+//! Statistics of the archive and of the ingest, served by `/api/stats`.
+//!
+//! Counts of the archive come from running counts of the repository and of the archiver, the
+//! ingest is measured in process since the start of the server.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{
+    ocr::cache::CacheStats,
+    repository::{FrameCount, RepositoryStats},
+};
+
+/// Counters of the ingest path, updated by `crate::analysis::Analysis`.
+pub struct IngestMetrics {
+    started_at: Instant,
+    recorded_frames: AtomicU64,
+    failed_frames: AtomicU64,
+    ocr_runs: AtomicU64,
+    ocr_micros: AtomicU64,
+}
+
+impl IngestMetrics {
+    pub fn new() -> Self {
+        Self {
+            started_at: Instant::now(),
+            recorded_frames: AtomicU64::new(0),
+            failed_frames: AtomicU64::new(0),
+            ocr_runs: AtomicU64::new(0),
+            ocr_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_frame(&self, saved: bool) {
+        let counter = if saved {
+            &self.recorded_frames
+        } else {
+            &self.failed_frames
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a run of OCR over a whole image, which took `elapsed`.
+    pub fn record_ocr(&self, elapsed: Duration) {
+        self.ocr_runs.fetch_add(1, Ordering::Relaxed);
+        self.ocr_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> IngestStats {
+        let uptime_secs = self.started_at.elapsed().as_secs();
+        let recorded_frames = self.recorded_frames.load(Ordering::Relaxed);
+        let ocr_runs = self.ocr_runs.load(Ordering::Relaxed);
+        let ocr_secs = self.ocr_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        IngestStats {
+            uptime_secs,
+            recorded_frames,
+            failed_frames: self.failed_frames.load(Ordering::Relaxed),
+            frames_per_minute: per_second(recorded_frames, uptime_secs as f64) * 60.0,
+            ocr_runs,
+            ocr_mean_millis: if ocr_runs == 0 {
+                0.0
+            } else {
+                ocr_secs * 1000.0 / ocr_runs as f64
+            },
+            ocr_images_per_second: per_second(ocr_runs, ocr_secs),
+        }
+    }
+}
+
+fn per_second(count: u64, secs: f64) -> f64 {
+    if secs > 0.0 {
+        count as f64 / secs
+    } else {
+        0.0
+    }
+}
+
+/// Ingest since the start of the server.
+#[derive(Debug, Clone, Serialize)]
+pub struct IngestStats {
+    pub uptime_secs: u64,
+    /// Frames captured and saved.
+    pub recorded_frames: u64,
+    /// Frames captured but lost to an error.
+    pub failed_frames: u64,
+    pub frames_per_minute: f64,
+    /// Images read by OCR, by the capture and by reindexing.
+    pub ocr_runs: u64,
+    /// Mean time of OCR over an image.
+    pub ocr_mean_millis: f64,
+    /// Images read per second of OCR, what a single reader sustains.
+    pub ocr_images_per_second: f64,
+}
+
+/// Number of frames captured from a screen on a day.
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyFrames {
+    /// The day in UTC, as `YYYY-MM-DD`.
+    pub date: String,
+    pub screen_id: Option<u32>,
+    pub frames: u64,
+}
+
+impl From<FrameCount> for DailyFrames {
+    fn from(count: FrameCount) -> Self {
+        let date = chrono::DateTime::from_timestamp((count.day * 86400) as i64, 0)
+            .map(|it| it.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        Self {
+            date,
+            screen_id: count.screen_id,
+            frames: count.frames,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Stats {
+    pub images: u64,
+    /// Stored texts, a text seen in consecutive frames counts once.
+    pub texts: u64,
+    pub database_bytes: u64,
+    pub archive_bytes: u64,
+    /// Frames per day and screen, by day then screen.
+    pub daily_frames: Vec<DailyFrames>,
+    pub ingest: IngestStats,
+    /// The OCR cache since the start of the server, `None` without a cache.
+    pub ocr_cache: Option<CacheStats>,
+}
+
+impl Stats {
+    pub fn new(
+        repository: RepositoryStats,
+        archive_bytes: u64,
+        ingest: IngestStats,
+        ocr_cache: Option<CacheStats>,
+    ) -> Self {
+        Self {
+            images: repository.images,
+            texts: repository.texts,
+            database_bytes: repository.database_bytes,
+            archive_bytes,
+            daily_frames: repository
+                .frame_counts
+                .into_iter()
+                .map(DailyFrames::from)
+                .collect(),
+            ingest,
+            ocr_cache,
+        }
+    }
+}
@@ -13,9 +13,12 @@ export default function Search() {
   const { data } = useQuery({
     queryKey: ['search', text],
     queryFn: async () => {
-      const response = await fetch(`/api/search?text=${text}`)
-      return await response.json() as {
+      const response = await fetch(`/api/search?text=${text}&limit=20`)
+      const total = Number(response.headers.get('x-total-count') ?? 0)
+      const results = await response.json() as {
         image_id: string
+        captured_at_epoch: number
+        score: number
         texts: {
           id: number,
           image_id: string,
@@ -25,7 +28,11 @@ export default function Search() {
           width: number,
           height: number,
         }[]
+        snippets: {
+          fragments: { text: string, highlighted: boolean }[]
+        }[]
       }[]
+      return { total, results }
     }
   })
 
@@ -35,9 +42,10 @@ export default function Search() {
         <div className="p-8">
           <div className="pb-8">
             <p className="text-2xl">{`Result for "${text}":`}</p>
+            {data && <p className="text-sm text-gray-500">{`${data.total} matching screenshots`}</p>}
           </div>
           <div className="grid grid-cols-4 gap-4">
-            {data?.slice(0, 20).map((item, i) => {
+            {data?.results.map((item, i) => {
               const text_ids = item.texts.map(it => it.id).join(',');
               return (<div key={i}>
                 <Link href={`/detail?image_id=${item.image_id}&text_ids=${text_ids}`}>
@@ -48,6 +56,13 @@ export default function Search() {
                     </CardMedia>
                   </Card>
                 </Link>
+                {item.snippets.map((snippet, j) => (
+                  <p key={j} className="text-sm pt-1 truncate">
+                    {snippet.fragments.map((fragment, k) => fragment.highlighted
+                      ? <mark key={k}>{fragment.text}</mark>
+                      : <span key={k}>{fragment.text}</span>)}
+                  </p>
+                ))}
 
               </div>)
             }